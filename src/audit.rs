@@ -0,0 +1,473 @@
+/*
+Summary of audit functions
+
+Look for entries that let someone other than the user decide what runs. Each
+finding has a severity and one of the codes in RULES, which stay the same between
+versions so scripts can pick out the ones they care about. Permissions and owners
+are only checked on Unix.
+
+# audit the entries of PATH for security problems, in the order of the entries
+audit(Vec<PathBuf>) -> Vec<Finding>
+
+# read a denylist of command names and SHA-256 hashes, one per line
+Denylist::parse(str) -> Denylist
+
+# find every executable on the denylist, shadowed or not, with the entry holding it
+deny(Vec<PathBuf>, Denylist) -> Vec<Finding>
+*/
+
+use crate::pathops;
+use serde::Serialize;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    // Worth knowing, but not a problem as such
+    Info,
+    Warn,
+    Error,
+}
+
+impl Severity {
+    pub fn label(self) -> &'static str {
+        match self {
+            Severity::Info => "info",
+            Severity::Warn => "warn",
+            Severity::Error => "error",
+        }
+    }
+}
+
+// A kind of finding, as listed by help security
+pub struct Rule {
+    pub code: &'static str,
+    pub severity: Severity,
+    pub summary: &'static str,
+}
+
+const fn rule(code: &'static str, severity: Severity, summary: &'static str) -> Rule {
+    Rule {
+        code,
+        severity,
+        summary,
+    }
+}
+
+pub const RULES: [Rule; 10] = [
+    rule(
+        "cwd",
+        Severity::Error,
+        "an empty entry or '.' at either end, which means the current directory",
+    ),
+    rule(
+        "cwd-hidden",
+        Severity::Error,
+        "the same between other entries, like :.: or ::, easy to overlook",
+    ),
+    rule(
+        "relative",
+        Severity::Error,
+        "an entry that depends on the current directory",
+    ),
+    rule(
+        "temp-dir",
+        Severity::Warn,
+        "under the temporary directory, where anyone can create it once it is cleaned away (error if it is already gone)",
+    ),
+    rule(
+        "repository",
+        Severity::Warn,
+        "inside a git working copy, so a checkout or pull changes commands",
+    ),
+    rule(
+        "world-writable",
+        Severity::Error,
+        "anyone can add or replace commands",
+    ),
+    rule(
+        "group-writable",
+        Severity::Warn,
+        "everyone in the group can add or replace commands",
+    ),
+    rule(
+        "foreign-owner",
+        Severity::Warn,
+        "owned by someone other than the user or root",
+    ),
+    rule(
+        "setuid-writable",
+        Severity::Error,
+        "a setuid or setgid program in a directory others can write",
+    ),
+    rule(
+        "denied",
+        Severity::Error,
+        "an executable on a denylist, by name or SHA-256",
+    ),
+];
+
+#[derive(Serialize, Debug)]
+pub struct Finding {
+    pub severity: Severity,
+    pub code: &'static str,
+    // Counted from 0, as everywhere else
+    pub index: usize,
+    pub path: PathBuf,
+    // The executable found, for findings about one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file: Option<PathBuf>,
+    pub message: String,
+}
+
+// Executables that mustn't be reachable, as handed out by a security team
+#[derive(Debug, Default, PartialEq)]
+pub struct Denylist {
+    // Compared as command names are on the platform
+    names: HashSet<String>,
+    // Lowercase hex
+    hashes: HashSet<String>,
+}
+
+impl Denylist {
+    // One name or hash per line, a hash as 64 hex digits with or without a
+    // sha256: prefix. Blank lines and anything after a # are ignored.
+    pub fn parse(contents: &str) -> Self {
+        let mut list = Denylist::default();
+        for line in contents.lines() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let hash = line.strip_prefix("sha256:").unwrap_or(line);
+            if hash.len() == 64 && hash.chars().all(|c| c.is_ascii_hexdigit()) {
+                list.hashes.insert(hash.to_ascii_lowercase());
+            } else if let Some(name) = pathops::command_name(Path::new(line)) {
+                list.names.insert(name);
+            }
+        }
+        list
+    }
+
+    pub fn len(&self) -> usize {
+        self.names.len() + self.hashes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+pub fn audit(paths: &[PathBuf]) -> Vec<Finding> {
+    let mut findings: Vec<Finding> = Vec::new();
+    for (index, p) in paths.iter().enumerate() {
+        let mut found = |severity, code, message: String| {
+            debug_assert!(
+                RULES.iter().any(|r| r.code == code),
+                "{} isn't a rule",
+                code
+            );
+            findings.push(Finding {
+                severity,
+                code,
+                index,
+                path: p.clone(),
+                file: None,
+                message,
+            })
+        };
+        if pathops::is_cwd_entry(p) && index > 0 && index + 1 < paths.len() {
+            found(
+                Severity::Error,
+                "cwd-hidden",
+                format!(
+                    "'{}' between {} and {} is the current directory and easy to overlook, {}",
+                    p.display(),
+                    paths[index - 1].display(),
+                    paths[index + 1].display(),
+                    pathops::CWD_RATIONALE
+                ),
+            );
+            continue;
+        }
+        if pathops::is_cwd_entry(p) {
+            found(
+                Severity::Error,
+                "cwd",
+                format!(
+                    "'{}' is the current directory, {}",
+                    p.display(),
+                    pathops::CWD_RATIONALE
+                ),
+            );
+            continue;
+        }
+        if p.is_relative() {
+            found(
+                Severity::Error,
+                "relative",
+                format!(
+                    "{} is relative, so what it finds depends on the current directory",
+                    p.display()
+                ),
+            );
+            continue;
+        }
+        if let Some(temp) = temp_dirs().into_iter().find(|t| p.starts_with(t)) {
+            if pathops::exists(p) {
+                found(
+                    Severity::Warn,
+                    "temp-dir",
+                    format!(
+                        "{} is under {}, once it is cleaned away anyone can create it again and put commands there",
+                        p.display(),
+                        temp.display()
+                    ),
+                );
+            } else {
+                found(
+                    Severity::Error,
+                    "temp-dir",
+                    format!(
+                        "{} is under {} and doesn't exist, so anyone can create it and put commands there",
+                        p.display(),
+                        temp.display()
+                    ),
+                );
+            }
+        }
+        if let Some(repo) = repository_of(p) {
+            found(
+                Severity::Warn,
+                "repository",
+                format!(
+                    "{} is inside the git working copy {}, so checking out or pulling changes the commands you run",
+                    p.display(),
+                    repo.display()
+                ),
+            );
+        }
+        permissions(p, &mut found);
+    }
+    findings
+}
+
+// Where anyone can create directories, as written and resolved, since an entry
+// can be either
+fn temp_dirs() -> Vec<PathBuf> {
+    let mut dirs = vec![std::env::temp_dir()];
+    if cfg!(unix) {
+        dirs.extend(["/tmp", "/var/tmp", "/dev/shm"].map(PathBuf::from));
+    }
+    let resolved: Vec<PathBuf> = dirs
+        .iter()
+        .filter_map(|d| pathops::resolve(d).ok())
+        .collect();
+    dirs.extend(resolved);
+    dirs.sort();
+    dirs.dedup();
+    // A root directory as TMPDIR would put everything under it:
+    dirs.retain(|d| d.parent().is_some());
+    dirs
+}
+
+// The working copy an entry is in, found by a .git directory, or a .git file as
+// worktrees and submodules have, in it or above it
+fn repository_of(path: &Path) -> Option<PathBuf> {
+    let path = pathops::resolve(path).unwrap_or_else(|_| path.to_path_buf());
+    path.ancestors()
+        .find(|a| pathops::on_host(&a.join(".git")).is_ok_and(|g| g.exists()))
+        .map(Path::to_path_buf)
+}
+
+// Executables shadowed by an earlier one of the same name still count, as they
+// can be run by their full path
+pub fn deny(paths: &[PathBuf], denylist: &Denylist) -> Vec<Finding> {
+    let mut findings: Vec<Finding> = Vec::new();
+    for (name, found) in pathops::scan_executables(paths) {
+        for (n, (index, exe)) in found.into_iter().enumerate() {
+            let how = if denylist.names.contains(&name) {
+                "its name"
+            } else if !denylist.hashes.is_empty()
+                && pathops::file_sha256(&exe).is_ok_and(|h| denylist.hashes.contains(&h))
+            {
+                "its SHA-256"
+            } else {
+                continue;
+            };
+            let runs = if n == 0 {
+                format!("runs as '{}'", name)
+            } else {
+                format!(
+                    "though another runs as '{}' it can be run by its path",
+                    name
+                )
+            };
+            findings.push(Finding {
+                severity: Severity::Error,
+                code: "denied",
+                index,
+                path: paths[index].clone(),
+                message: format!("{} is denied by {}, and {}", exe.display(), how, runs),
+                file: Some(exe),
+            });
+        }
+    }
+    findings.sort_by(|a, b| (a.index, &a.file).cmp(&(b.index, &b.file)));
+    findings
+}
+
+#[cfg(unix)]
+fn permissions(path: &Path, found: &mut impl FnMut(Severity, &'static str, String)) {
+    use std::fs;
+    use std::os::unix::fs::MetadataExt;
+
+    let Ok(host) = pathops::on_host(path) else {
+        return;
+    };
+    let Ok(meta) = fs::metadata(&host) else {
+        return;
+    };
+    let mode = meta.mode();
+    let user = unsafe { libc::geteuid() };
+    // Others can only write where the directory isn't only root's or the user's:
+    let writable_by_others = mode & 0o022 != 0 || (meta.uid() != 0 && meta.uid() != user);
+    if mode & 0o002 != 0 {
+        found(
+            Severity::Error,
+            "world-writable",
+            format!(
+                "{} is writable by everyone, so anyone can put commands there",
+                path.display()
+            ),
+        );
+    } else if mode & 0o020 != 0 {
+        found(
+            Severity::Warn,
+            "group-writable",
+            format!(
+                "{} is writable by its group, so every member can put commands there",
+                path.display()
+            ),
+        );
+    }
+    if meta.uid() != 0 && meta.uid() != user {
+        found(
+            Severity::Warn,
+            "foreign-owner",
+            format!(
+                "{} is owned by uid {}, neither you nor root",
+                path.display(),
+                meta.uid()
+            ),
+        );
+    }
+    if !writable_by_others {
+        return;
+    }
+    let mut programs: Vec<PathBuf> = fs::read_dir(&host)
+        .into_iter()
+        .flatten()
+        .filter_map(|d| d.ok())
+        .filter(|d| fs::metadata(d.path()).is_ok_and(|m| m.is_file() && m.mode() & 0o6000 != 0))
+        .map(|d| path.join(d.file_name()))
+        .collect();
+    programs.sort();
+    for program in programs {
+        found(
+            Severity::Error,
+            "setuid-writable",
+            format!(
+                "{} is setuid or setgid in a directory others can write, so it can be replaced",
+                program.display()
+            ),
+        );
+    }
+}
+
+#[cfg(not(unix))]
+fn permissions(_path: &Path, _found: &mut impl FnMut(Severity, &'static str, String)) {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn codes(paths: &[PathBuf]) -> Vec<&'static str> {
+        audit(paths).iter().map(|f| f.code).collect()
+    }
+
+    #[test]
+    fn test_audit_relative() {
+        let paths = [PathBuf::from("."), PathBuf::from("bin")];
+        assert_eq!(codes(&paths), vec!["cwd", "relative"]);
+        assert_eq!(audit(&paths)[1].index, 1);
+        let paths = ["/nonexistent/a", ".", "/nonexistent/b", ""].map(PathBuf::from);
+        assert_eq!(codes(&paths), vec!["cwd-hidden", "cwd"])
+    }
+
+    #[test]
+    fn test_audit_temp_and_repository() {
+        let temp = std::env::temp_dir().join(format!("pathaid-gone-{}", std::process::id()));
+        let finding = &audit(std::slice::from_ref(&temp))[0];
+        assert_eq!(
+            (finding.code, finding.severity),
+            ("temp-dir", Severity::Error)
+        );
+        let crate_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let in_repo = crate_dir
+            .join(".git")
+            .exists()
+            .then(|| crate_dir.join("src"));
+        if let Some(src) = in_repo {
+            assert!(audit(&[src]).iter().any(|f| f.code == "repository"));
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_audit_permissions() {
+        use std::fs;
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("pathaid-audit-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::set_permissions(&dir, fs::Permissions::from_mode(0o777)).unwrap();
+        let program = dir.join("su");
+        fs::write(&program, "").unwrap();
+        fs::set_permissions(&program, fs::Permissions::from_mode(0o4755)).unwrap();
+        let findings = audit(std::slice::from_ref(&dir));
+        fs::remove_dir_all(&dir).unwrap();
+        let codes: Vec<&str> = findings.iter().map(|f| f.code).collect();
+        // Being in the temporary directory is a finding of its own:
+        assert_eq!(codes, vec!["temp-dir", "world-writable", "setuid-writable"]);
+        assert!(findings[1..].iter().all(|f| f.severity == Severity::Error));
+        assert_eq!(findings[2].path, dir)
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_denylist() {
+        use std::fs;
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("pathaid-deny-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for (name, contents) in [("nc", "#!/bin/sh\n"), ("tool", "#!/bin/sh\nexit 1\n")] {
+            fs::write(dir.join(name), contents).unwrap();
+            fs::set_permissions(dir.join(name), fs::Permissions::from_mode(0o755)).unwrap();
+        }
+        let hash = pathops::file_sha256(&dir.join("tool")).unwrap();
+        let list = Denylist::parse(&format!(
+            "# from the security team\nnc\n\nsha256:{}  # tool\n",
+            hash.to_uppercase()
+        ));
+        assert_eq!(list.len(), 2);
+        let findings = deny(std::slice::from_ref(&dir), &list);
+        let found: Vec<Option<PathBuf>> = findings.iter().map(|f| f.file.clone()).collect();
+        let empty = deny(std::slice::from_ref(&dir), &Denylist::parse("# nothing"));
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(found, vec![Some(dir.join("nc")), Some(dir.join("tool"))]);
+        assert!(empty.is_empty())
+    }
+}
@@ -0,0 +1,4843 @@
+/*
+Joel Gruselius 2024
+
+Summary of path tool
+
+# Commands:
+
+list           list all paths in PATH
+validate       check for duplicate entries, non-existing or empty directories
+audit          find entries that let others decide what runs
+doctor         run validate, shadow and audit together and summarize
+ack            accept a problem doctor finds so it no longer fails doctor
+why-slow       tell which files add entries and which entries are slow
+explain        tell where entries come from
+dedup          remove any duplicates and print result
+count          count executables in each entry
+all            run list, validate or count for several variables
+prefix         add or remove the directories of an install prefix
+env            run a command with a modified PATH
+query          list the entries matching an expression
+diff           show entries added, removed and moved in another path string
+export         write what every command name runs to a file
+shadow         list commands found in several entries and which one runs
+which          find the executable a command name runs
+prune          remove missing and empty entries and print result
+clean          strip invisible characters and spaces and print result
+normalize      write entries the same way and print result
+remove         remove paths and print result
+append         add one or more paths (also separated by ':') to the end and print result
+prepend        add one or more paths (also separated by ':') to the front and print result
+insert         add paths at a position and print result
+move           move an entry to another position and print result
+edit           reorder, remove and disable entries in the terminal
+top            show the entries with their status as they change
+lint-script    check the changes to PATH in a shell script
+lint-ci        check the changes to PATH in CI configs
+lint-build     check a Makefile or justfile for PATH changes and undeclared tools
+stats          show how pathaid itself is used
+history        show when entries were added, went missing or changed
+snapshot       save PATH under a name and get it back
+daemon         keep entries scanned and answer over a local socket
+serve          answer JSON-RPC on stdio, for editors and linters
+gc             remove files left over in the state directory
+setup          offer to install completions for the shells in use
+completions    print or install a shell completion script
+help           print the help of a command or a topic
+*/
+
+use crate::{
+    audit, daemon, envmodules, fixture, lint, packages, pathops, query, rcfile, rootfs, rpc, state,
+    tui,
+};
+use anyhow::{anyhow, bail, ensure, Context, Result};
+use clap::{arg, value_parser, ArgAction, ArgGroup, ArgMatches, Command};
+use clap_complete::Shell;
+use colored::{ColoredString, Colorize};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::env;
+use std::ffi::{OsStr, OsString};
+use std::fs;
+use std::io::{self, IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+// Replacements applied to everything printed, set once from the command line
+static REDACTIONS: OnceLock<Vec<(String, String)>> = OnceLock::new();
+// Keep warnings out of stderr, they are still part of JSON output
+static QUIET: OnceLock<bool> = OnceLock::new();
+// Make entries clickable with OSC 8 escapes
+static HYPERLINKS: OnceLock<bool> = OnceLock::new();
+// Output that only depends on the entries, for comparing byte for byte in tests
+static DETERMINISTIC: OnceLock<bool> = OnceLock::new();
+// The path-like variable worked on, PATH unless another is given with --var
+static VAR: OnceLock<String> = OnceLock::new();
+
+fn var_name() -> &'static str {
+    VAR.get().map(String::as_str).unwrap_or("PATH")
+}
+
+// Columns are as wide as their contents, or as the placeholder width when output
+// has to be deterministic so it doesn't change with the widest value seen
+fn column_width(natural: usize, placeholder: usize) -> usize {
+    if DETERMINISTIC.get() == Some(&true) {
+        placeholder
+    } else {
+        natural
+    }
+}
+
+// Replace word with placeholder only where it isn't part of a longer name, so a
+// user called bin doesn't turn /usr/bin into /usr/<user>
+fn replace_word(s: &str, word: &str, placeholder: &str) -> String {
+    let is_name = |c: Option<char>| c.is_some_and(|c| c.is_alphanumeric() || "_-~".contains(c));
+    let mut replaced = String::new();
+    let mut rest = s;
+    while let Some(i) = rest.find(word) {
+        let before = match i {
+            0 => replaced.chars().last(),
+            _ => rest[..i].chars().last(),
+        };
+        let after = rest[i + word.len()..].chars().next();
+        replaced.push_str(&rest[..i]);
+        if is_name(before) || is_name(after) {
+            replaced.push_str(word);
+        } else {
+            replaced.push_str(placeholder);
+        }
+        rest = &rest[i + word.len()..];
+    }
+    replaced.push_str(rest);
+    replaced
+}
+
+// Home directory, user name and host name, the things identifying whoever runs this
+fn identity_redactions() -> Vec<(String, String)> {
+    let mut redactions: Vec<(String, String)> = Vec::new();
+    for var in ["HOME", "USERPROFILE"] {
+        if let Ok(home) = env::var(var) {
+            if !home.is_empty() {
+                redactions.push((home, String::from("~")));
+            }
+        }
+    }
+    for var in ["USER", "USERNAME"] {
+        if let Ok(user) = env::var(var) {
+            redactions.push((user, String::from("<user>")));
+        }
+    }
+    let host = env::var("HOSTNAME")
+        .or_else(|_| env::var("COMPUTERNAME"))
+        .or_else(|_| std::fs::read_to_string("/etc/hostname"));
+    if let Ok(host) = host {
+        redactions.push((host.trim().to_string(), String::from("<host>")));
+    }
+    redactions.retain(|(from, _)| !from.is_empty());
+    redactions
+}
+
+fn redact(s: &str) -> String {
+    let mut s = s.to_string();
+    for (from, to) in REDACTIONS.get().into_iter().flatten() {
+        s = replace_word(&s, from, to);
+    }
+    s
+}
+
+// Redact the entries of a path string that are valid Unicode, the others can
+// only be kept as they are
+fn redact_os(path_var: &OsStr) -> OsString {
+    if let Some(s) = path_var.to_str() {
+        return redact(s).into();
+    }
+    let entries: Vec<PathBuf> = pathops::split(path_var)
+        .into_iter()
+        .map(|p| match p.to_str() {
+            Some(s) => PathBuf::from(redact(s)),
+            None => p,
+        })
+        .collect();
+    pathops::join(&entries).unwrap_or_else(|_| path_var.to_os_string())
+}
+
+// Print a path string as it is, even bytes that aren't valid Unicode
+#[cfg(unix)]
+fn print_raw(prefix: &str, path_var: &OsStr) -> Result<()> {
+    use std::os::unix::ffi::OsStrExt;
+    let mut out = io::stdout().lock();
+    out.write_all(prefix.as_bytes())?;
+    out.write_all(path_var.as_bytes())?;
+    out.write_all(b"\n")?;
+    Ok(())
+}
+
+// Windows consoles only take text
+#[cfg(not(unix))]
+fn print_raw(prefix: &str, path_var: &OsStr) -> Result<()> {
+    println!(
+        "{}{}",
+        prefix,
+        pathops::into_string(path_var.to_os_string())?
+    );
+    Ok(())
+}
+
+// Path strings as JSON text, print_change refuses a new one that isn't Unicode
+// before getting here
+fn lossy<S: serde::Serializer>(
+    path_var: &OsString,
+    serializer: S,
+) -> std::result::Result<S::Ok, S::Error> {
+    serializer.serialize_str(&path_var.to_string_lossy())
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Format {
+    Text,
+    Json,
+    // Tcl and Lua modulefiles
+    Modulefile,
+    Lua,
+    // Statements to eval in a shell
+    Shell(rcfile::Syntax),
+}
+
+// Outcome of a command that modifies the path string
+#[derive(Serialize)]
+struct Change {
+    #[serde(serialize_with = "lossy")]
+    old: OsString,
+    #[serde(serialize_with = "lossy")]
+    new: OsString,
+    // Where the addition ended up, if anything was added
+    index: Option<usize>,
+    warnings: Vec<String>,
+}
+
+// Record a problem with an entry, with the message as validate prints it
+macro_rules! issue {
+    ($issues:expr, $code:expr, $path:expr, $($arg:tt)*) => {{
+        debug_assert!(ISSUE_CODES.iter().any(|c| c.code == $code), "{} isn't an issue code", $code);
+        $issues.push(pathops::Issue {
+            code: $code,
+            path: Some($path.to_path_buf()),
+            message: format!($($arg)*),
+        })
+    }};
+}
+
+// Print anything serializable as JSON, redacted like all other output
+fn print_json(value: &impl Serialize) -> Result<()> {
+    println!("{}", serde_json::to_string_pretty(&redact_json(value)?)?);
+    Ok(())
+}
+
+// Redact the strings and keys in a value before it is serialized, once escaped a
+// Windows home like C:\Users\me would no longer be found in the text
+fn redact_json(value: &impl Serialize) -> Result<serde_json::Value> {
+    Ok(redact_value(serde_json::to_value(value)?))
+}
+
+fn redact_value(value: serde_json::Value) -> serde_json::Value {
+    use serde_json::Value;
+    match value {
+        Value::String(s) => Value::String(redact(&s)),
+        Value::Array(values) => Value::Array(values.into_iter().map(redact_value).collect()),
+        Value::Object(map) => Value::Object(
+            map.into_iter()
+                .map(|(k, v)| (redact(&k), redact_value(v)))
+                .collect(),
+        ),
+        other => other,
+    }
+}
+
+// Print the messages of issues, or the issues themselves as JSON
+fn print_issues(issues: &[pathops::Issue], plain: bool, format: Format) -> Result<()> {
+    if format == Format::Json {
+        return print_json(&issues);
+    }
+    for issue in issues.iter() {
+        match plain {
+            true => println!("{}", plain_issue(issue)),
+            false => println!("{}", issue.message),
+        }
+    }
+    Ok(())
+}
+
+// An issue as its code, entry and message separated by tabs
+fn plain_issue(issue: &pathops::Issue) -> String {
+    let path = issue
+        .path
+        .as_ref()
+        .map_or_else(String::new, |p| redact(&p.to_string_lossy()));
+    format!("{}\t{}\t{}", issue.code, path, issue.message)
+}
+
+// The number of executables in an entry, or - if it couldn't be read, and the entry
+fn plain_count(entry: &pathops::PathEntry) -> String {
+    let count = entry
+        .executables
+        .map_or(String::from("-"), |n| n.to_string());
+    format!("{}\t{}", count, redact(&entry.path.to_string_lossy()))
+}
+
+fn print_warnings(warnings: &[String]) {
+    if QUIET.get() == Some(&true) {
+        return;
+    }
+    for w in warnings.iter() {
+        eprintln!("{} {}", "warning:".yellow().bold(), w);
+    }
+}
+
+impl Change {
+    fn redacted(self) -> Self {
+        Self {
+            old: redact_os(&self.old),
+            new: redact_os(&self.new),
+            index: self.index,
+            warnings: self.warnings.iter().map(|w| redact(w)).collect(),
+        }
+    }
+
+    // The new path string as text, for every format but plain text
+    fn new_text(&self) -> Result<String> {
+        Ok(pathops::into_string(self.new.clone())?)
+    }
+}
+
+// Print only the new path as text, or the whole change as JSON, a modulefile or
+// a statement for a shell
+fn print_change(change: Change, format: Format) -> Result<()> {
+    let change = change.redacted();
+    match format {
+        Format::Text => {
+            print_warnings(&change.warnings);
+            print_raw("", &change.new)?;
+        }
+        Format::Json => {
+            change.new_text()?;
+            println!("{}", serde_json::to_string_pretty(&change)?)
+        }
+        _ => print_changes(vec![(var_name().to_string(), change)], format)?,
+    }
+
+    Ok(())
+}
+
+// Print changes to several variables as NAME=value lines, one JSON object, a
+// modulefile or statements for a shell
+fn print_changes(changes: Vec<(String, Change)>, format: Format) -> Result<()> {
+    let changes: Vec<(String, Change)> = changes
+        .into_iter()
+        .map(|(var, change)| (var, change.redacted()))
+        .collect();
+    if format != Format::Text {
+        for (_, change) in changes.iter() {
+            change.new_text()?;
+        }
+    }
+    match format {
+        Format::Modulefile | Format::Lua => {
+            let mut lines: Vec<String> = Vec::new();
+            for (var, change) in changes.iter() {
+                let old = pathops::split_entries(&change.old);
+                let new = pathops::split_entries(&change.new);
+                let Some(var_lines) =
+                    envmodules::modulefile_lines(var, &old, &new, format == Format::Lua)
+                else {
+                    bail!(
+                        "modulefiles can only add and remove directories, not reorder {} or drop a repeated entry, use another --format",
+                        var
+                    );
+                };
+                lines.extend(var_lines);
+            }
+            for (_, change) in changes.iter() {
+                print_warnings(&change.warnings);
+            }
+            if format == Format::Modulefile {
+                println!("#%Module");
+            }
+            for line in lines {
+                println!("{}", line);
+            }
+        }
+        Format::Text => {
+            for (var, change) in changes.iter() {
+                print_warnings(&change.warnings);
+                print_raw(&format!("{}=", var), &change.new)?;
+            }
+        }
+        Format::Shell(syntax) => {
+            for (var, change) in changes.iter() {
+                print_warnings(&change.warnings);
+                println!("{}", rcfile::set_line(syntax, var, &change.new_text()?));
+            }
+        }
+        Format::Json => {
+            let changes: serde_json::Map<String, serde_json::Value> = changes
+                .into_iter()
+                .map(|(var, change)| Ok((var, serde_json::to_value(change)?)))
+                .collect::<Result<_>>()?;
+            println!("{}", serde_json::to_string_pretty(&changes)?);
+        }
+    }
+
+    Ok(())
+}
+
+// Guess if the terminal turns OSC 8 escapes into links, as there is no way to ask.
+// FORCE_HYPERLINK overrides it either way, like for other tools.
+fn supports_hyperlinks() -> bool {
+    if let Some(force) = env::var_os("FORCE_HYPERLINK") {
+        return force != "0";
+    }
+    if !io::stdout().is_terminal() || env::var_os("CI").is_some() {
+        return false;
+    }
+    let var = |name| env::var(name).unwrap_or_default();
+    ["WT_SESSION", "KITTY_WINDOW_ID", "WEZTERM_EXECUTABLE", "DOMTERM"]
+        .iter()
+        .any(|v| env::var_os(v).is_some())
+        || ["iTerm.app", "WezTerm", "vscode", "ghostty", "Hyper"].contains(&var("TERM_PROGRAM").as_str())
+        || ["xterm-kitty", "xterm-ghostty", "alacritty", "foot"].contains(&var("TERM").as_str())
+        // VTE based terminals (GNOME Terminal and friends) since 0.50:
+        || var("VTE_VERSION").parse::<u32>().is_ok_and(|v| v >= 5000)
+}
+
+// A file:// URL for an absolute path, percent-encoding anything but plain characters
+fn file_url(path: &Path) -> String {
+    let path = path.as_os_str().as_encoded_bytes();
+    let mut url = String::from(if path.starts_with(b"/") {
+        "file://"
+    } else {
+        "file:///"
+    });
+    for &b in path {
+        if b == b'\\' {
+            url.push('/');
+        } else if b.is_ascii_alphanumeric() || b"/-._~:".contains(&b) {
+            url.push(b as char);
+        } else {
+            url.push_str(&format!("%{:02X}", b));
+        }
+    }
+    url
+}
+
+fn fmt_path(path: impl AsRef<Path>, level: usize) -> String {
+    let p = redact(&path.as_ref().to_string_lossy());
+    let text = match level {
+        0 => p.blue(),
+        1 => p.yellow(),
+        2 => p.red(),
+        _ => p.bold(),
+    };
+    // Link directories that are there to open, in the file manager usually:
+    if HYPERLINKS.get() == Some(&true) && pathops::exists(path.as_ref()) {
+        if let Ok(abs) = pathops::absolute(path.as_ref()) {
+            return format!("\x1b]8;;{}\x1b\\{}\x1b]8;;\x1b\\", file_url(&abs), text);
+        }
+    }
+    text.to_string()
+}
+
+fn fmt_num(num: usize, level: usize) -> ColoredString {
+    let n = format!("{}", num);
+    match level {
+        0 => n.magenta(),
+        1 => n.yellow(),
+        2 => n.red(),
+        _ => n.bold(),
+    }
+}
+// Use different format for normal paths, those that refer to some other path,
+// and non-existing paths
+fn fmt_entry(p: &Path) -> String {
+    if let Ok(res) = pathops::resolve(p) {
+        if res.as_os_str() == pathops::normalize(p).as_os_str() {
+            fmt_path(p, 0)
+        } else {
+            format!("{} -> {}", fmt_path(p, 1), fmt_path(res, 0))
+        }
+    } else {
+        fmt_path(p, 2)
+    }
+}
+
+// A glyph for where an entry comes from or what state it is in, as icons make long
+// listings quicker to scan than labels
+fn icon(p: &Path, managed: bool) -> &'static str {
+    if !pathops::exists(p) {
+        "⚠️ "
+    } else if pathops::is_read_only(p).unwrap_or(false) || pathops::is_immutable(p).unwrap_or(false)
+    {
+        "🔒"
+    } else if p.components().any(|c| c.as_os_str() == ".cargo") {
+        "🦀"
+    } else if managed {
+        "📦"
+    } else {
+        "📁"
+    }
+}
+
+// The positions of the entries to list, all unless any filters are given
+fn listed(paths: &[PathBuf], filters: &[pathops::Filter]) -> Vec<usize> {
+    if filters.is_empty() {
+        return (0..paths.len()).collect();
+    }
+    pathops::select(paths, filters, var_name() == "PATH")
+}
+
+fn list_filters(subm: &ArgMatches) -> Vec<pathops::Filter> {
+    [
+        ("missing", pathops::Filter::Missing),
+        ("empty", pathops::Filter::Empty),
+        ("duplicates", pathops::Filter::Duplicate),
+        ("ok", pathops::Filter::Ok),
+    ]
+    .into_iter()
+    .filter(|(id, _)| subm.get_flag(id))
+    .map(|(_, filter)| filter)
+    .collect()
+}
+
+fn list_paths(
+    path: &OsStr,
+    plain: bool,
+    icons: bool,
+    filters: &[pathops::Filter],
+    format: Format,
+) -> Result<()> {
+    let paths = pathops::split(path);
+    let listed = listed(&paths, filters);
+    if format == Format::Json {
+        let mut entries = pathops::entries(&paths, false);
+        entries.retain(|e| listed.contains(&e.index));
+        return print_json(&entries);
+    }
+    let loaded = envmodules::loaded_modules();
+    for p in listed.iter().map(|&i| &paths[i]) {
+        if plain {
+            println!("{}", redact(&p.to_string_lossy()));
+            continue;
+        }
+        // Label entries that some other tool is in charge of:
+        let manager = envmodules::manager(p, &loaded);
+        let label = match &manager {
+            Some(m) => redact(&format!(" [{}]", m)).dimmed().to_string(),
+            None => String::new(),
+        };
+        if icons {
+            print!("{} ", icon(p, manager.is_some()));
+        }
+        println!("{}{}", fmt_entry(p), label);
+    }
+
+    Ok(())
+}
+
+// Print entries fully resolved without any decoration, one per line or as a path string
+fn list_resolved(path: &OsStr, keep_missing: bool, join: bool, format: Format) -> Result<()> {
+    let paths = pathops::resolve_all(&pathops::split(path), keep_missing);
+    if format == Format::Json && join {
+        print_json(&pathops::join(&paths)?.to_string_lossy())?;
+    } else if format == Format::Json {
+        print_json(&paths)?;
+    } else if join {
+        print_raw("", &redact_os(&pathops::join(&paths)?))?;
+    } else {
+        for p in paths.iter() {
+            println!("{}", redact(&p.to_string_lossy()));
+        }
+    }
+
+    Ok(())
+}
+
+// An entry as list --long gives it in JSON
+#[derive(Serialize)]
+struct LongEntry<'a> {
+    index: usize,
+    path: &'a Path,
+    fs_type: Option<String>,
+    #[serde(flatten)]
+    meta: pathops::EntryMeta,
+}
+
+// Permissions as ls -l shows them, with l for a link and d for a directory
+fn fmt_mode(meta: &pathops::EntryMeta) -> String {
+    let mut mode = String::from(if meta.symlink {
+        'l'
+    } else if meta.dir {
+        'd'
+    } else {
+        '-'
+    });
+    let Some(bits) = meta.mode else {
+        return mode;
+    };
+    for (i, c) in "rwxrwxrwx".chars().enumerate() {
+        mode.push(if bits & (0o400 >> i) != 0 { c } else { '-' });
+    }
+    // Sticky directories are where others can write but not replace what's there:
+    if bits & 0o1000 != 0 {
+        let x = mode.pop() == Some('x');
+        mode.push(if x { 't' } else { 'T' });
+    }
+    mode
+}
+
+// List entries with details in aligned columns before them
+fn list_long(
+    path: &OsStr,
+    plain: bool,
+    icons: bool,
+    filters: &[pathops::Filter],
+    format: Format,
+) -> Result<()> {
+    let paths = pathops::split(path);
+    let count = var_name() == "PATH";
+    // Modification times differ between machines and checkouts:
+    let deterministic = DETERMINISTIC.get() == Some(&true);
+    let entries: Vec<LongEntry> = listed(&paths, filters)
+        .into_iter()
+        .map(|index| {
+            let mut meta = pathops::entry_meta(&paths[index], count);
+            if deterministic && format == Format::Json {
+                meta.modified = None;
+            }
+            LongEntry {
+                index,
+                path: &paths[index],
+                fs_type: pathops::mount_of(&paths[index]).ok().map(|m| m.fs_type),
+                meta,
+            }
+        })
+        .collect();
+    if format == Format::Json {
+        return print_json(&entries);
+    }
+    let dash = || String::from("-");
+    let rows: Vec<[String; 6]> = entries
+        .iter()
+        .map(|e| {
+            [
+                e.index.to_string(),
+                fmt_mode(&e.meta),
+                e.meta.owner.clone().unwrap_or_else(dash),
+                e.meta.executables.map_or_else(dash, |n| n.to_string()),
+                match (deterministic, e.meta.modified) {
+                    (_, None) => dash(),
+                    (true, Some(_)) => String::from("<modified>"),
+                    (false, Some(t)) => state::format_time(t),
+                },
+                e.fs_type.clone().unwrap_or_else(dash),
+            ]
+        })
+        .collect();
+    if plain {
+        for (e, row) in entries.iter().zip(rows) {
+            println!("{}\t{}", row.join("\t"), redact(&e.path.to_string_lossy()));
+        }
+        return Ok(());
+    }
+    let widest = |i: usize| rows.iter().map(|r| r[i].len()).max().unwrap_or(0);
+    let owner_width = column_width(widest(2), 8);
+    let count_width = column_width(widest(3), 4);
+    let fs_width = column_width(widest(5), 8);
+    let index_width = widest(0);
+    let mode_width = widest(1);
+    let loaded = envmodules::loaded_modules();
+    for (e, [index, mode, owner, count, date, fs_type]) in entries.iter().zip(rows) {
+        // Network mounts are the usual suspects when things are slow:
+        let fs_col = format!("{:<fs_width$}", fs_type);
+        let fs_col = if pathops::is_network_fs(&fs_type) {
+            fs_col.yellow()
+        } else {
+            fs_col.dimmed()
+        };
+        print!(
+            "{}  {:<mode_width$}  {:<owner_width$}  {}  {:<20}  {}  ",
+            format!("{:>index_width$}", index).dimmed(),
+            mode,
+            redact(&owner),
+            format!("{:>count_width$}", count).magenta(),
+            date,
+            fs_col
+        );
+        if icons {
+            let managed = envmodules::manager(e.path, &loaded).is_some();
+            print!("{} ", icon(e.path, managed));
+        }
+        println!("{}", fmt_entry(e.path));
+    }
+
+    Ok(())
+}
+
+// Print entries under the mount point they are on, in the order the mounts first
+// appear, with the number of executables on each
+// A mount point with its entries, as list --by-mount gives it in JSON
+#[derive(Serialize)]
+struct MountGroup<'a> {
+    mount: Option<&'a Path>,
+    fs_type: Option<&'a str>,
+    executables: usize,
+    entries: Vec<MountEntry<'a>>,
+}
+
+#[derive(Serialize)]
+struct MountEntry<'a> {
+    path: &'a Path,
+    executables: Option<usize>,
+}
+
+fn list_by_mount(path: &OsStr, format: Format) -> Result<()> {
+    let paths = pathops::split(path);
+    let mut groups: Vec<(Option<pathops::Mount>, Vec<_>)> = Vec::new();
+    for p in paths.iter() {
+        let mount = pathops::mount_of(p).ok();
+        let count = pathops::count_executables(p).ok();
+        let point = mount.as_ref().map(|m| &m.point);
+        match groups
+            .iter_mut()
+            .find(|(m, _)| m.as_ref().map(|m| &m.point) == point)
+        {
+            Some((_, entries)) => entries.push((p, count)),
+            None => groups.push((mount, vec![(p, count)])),
+        }
+    }
+    if format == Format::Json {
+        let groups: Vec<MountGroup> = groups
+            .iter()
+            .map(|(mount, entries)| MountGroup {
+                mount: mount.as_ref().map(|m| m.point.as_path()),
+                fs_type: mount.as_ref().map(|m| m.fs_type.as_str()),
+                executables: entries.iter().filter_map(|(_, n)| *n).sum(),
+                entries: entries
+                    .iter()
+                    .map(|(p, n)| MountEntry {
+                        path: p,
+                        executables: *n,
+                    })
+                    .collect(),
+            })
+            .collect();
+        return print_json(&groups);
+    }
+    for (mount, entries) in groups.iter() {
+        let total: usize = entries.iter().filter_map(|(_, n)| *n).sum();
+        match mount {
+            Some(m) => println!(
+                "{} ({}): {}",
+                fmt_path(&m.point, 3),
+                m.fs_type,
+                fmt_num(total, 0)
+            ),
+            None => println!("{}: {}", "unknown mount".bold(), fmt_num(total, 0)),
+        }
+        for (p, count) in entries.iter() {
+            match count {
+                Some(n) => println!("  {}: {}", fmt_entry(p), fmt_num(*n, 0)),
+                None => println!("  {}: --", fmt_entry(p)),
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// How many packages to name before just counting the rest
+const MAX_PACKAGES_SHOWN: usize = 10;
+
+// Tell what is known about where entries come from
+fn explain(path: &OsStr, entries: &[String], package: bool) -> Result<()> {
+    let paths = pathops::split(path);
+    let loaded = envmodules::loaded_modules();
+    for entry in entries {
+        let p = Path::new(entry);
+        println!("{}", fmt_entry(p));
+        let positions: Vec<String> = pathops::find_positions(&paths, p, false)
+            .iter()
+            .map(usize::to_string)
+            .collect();
+        if positions.is_empty() {
+            println!("  not in PATH");
+        } else {
+            println!("  in PATH at {}", positions.join(", "));
+        }
+        if let Some(m) = envmodules::manager(p, &loaded) {
+            println!("  managed by {}", redact(&m.to_string()));
+        }
+        if let Ok(m) = pathops::mount_of(p) {
+            println!("  on {} mounted at {}", m.fs_type, fmt_path(&m.point, 0));
+        }
+        if let Some(manager) = packages::shim_manager(p) {
+            let apps = packages::shimmed_apps(p);
+            println!("  {} shims for {}", manager, apps.join(", "));
+        }
+        if package {
+            let owners = packages::owners(p);
+            if owners.is_empty() {
+                println!("  not installed by any package manager found");
+            }
+            for owner in owners.iter() {
+                let mut names =
+                    owner.packages[..owner.packages.len().min(MAX_PACKAGES_SHOWN)].join(", ");
+                if owner.packages.len() > MAX_PACKAGES_SHOWN {
+                    names.push_str(&format!(
+                        " and {} more",
+                        owner.packages.len() - MAX_PACKAGES_SHOWN
+                    ));
+                }
+                println!("  installed by {}: {}", owner.manager, names.bold());
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// How long looking at an entry took and where it came from, for why-slow
+#[derive(Serialize)]
+struct EntryCost {
+    index: usize,
+    path: PathBuf,
+    // Listing all of it
+    ms: u64,
+    // Looking for a command that isn't there, which is what every entry costs a
+    // shell that doesn't find one
+    lookup_us: u64,
+    fs_type: Option<String>,
+    // The startup file that added it, if any did
+    source: Option<PathBuf>,
+}
+
+// How long a stat of a name that isn't in the entry takes, as a shell does for
+// every entry before it gives up on a command, averaged over a few tries
+fn failed_lookup_us(p: &Path) -> u64 {
+    const TRIES: u32 = 3;
+    let Ok(dir) = pathops::on_host(p) else {
+        return 0;
+    };
+    let missing = dir.join("pathaid-no-such-command");
+    let start = Instant::now();
+    for _ in 0..TRIES {
+        let _ = fs::metadata(&missing);
+    }
+    (start.elapsed() / TRIES).as_micros() as u64
+}
+
+// Tell which files add how many entries, which entries are slow to look at and
+// what dropping duplicate and missing entries would save. Shells look at every
+// entry for a command that isn't found, so each entry costs one failed lookup
+// every time.
+fn why_slow(path: &OsStr, threshold: u64, format: Format) -> Result<()> {
+    if cfg!(windows) {
+        bail!("why-slow reads the startup files of sh-like shells, which Windows doesn't have");
+    }
+    let root = pathops::root().unwrap_or(Path::new("/"));
+    let composed = rootfs::compose_path(root)?;
+    let mut sources = composed.sources;
+    // The files of this user, unless looking at another system:
+    let shell = env::var("SHELL").unwrap_or(composed.shell);
+    if pathops::root().is_none() {
+        let mut composed_path = composed.path;
+        for file in rootfs::user_files(&shell, &state::home_dir()?) {
+            let Ok(contents) = fs::read_to_string(&file) else {
+                continue;
+            };
+            let before = composed_path.clone();
+            composed_path = rootfs::apply_assignments(&contents, composed_path);
+            let added = rootfs::added_entries(&before, &composed_path);
+            if !added.is_empty() {
+                sources.push(rootfs::Source {
+                    file,
+                    reader: shell.clone(),
+                    added,
+                });
+            }
+        }
+    }
+    let deterministic = DETERMINISTIC.get() == Some(&true);
+    let paths = pathops::split(path);
+    let costs: Vec<EntryCost> = paths
+        .iter()
+        .enumerate()
+        .map(|(index, p)| {
+            let start = Instant::now();
+            if pathops::exists(p) {
+                let _ = pathops::count_executables(p);
+            }
+            let ms = start.elapsed().as_millis() as u64;
+            let lookup_us = failed_lookup_us(p);
+            EntryCost {
+                index,
+                path: p.clone(),
+                // Timings differ from run to run:
+                ms: if deterministic { 0 } else { ms },
+                lookup_us: if deterministic { 0 } else { lookup_us },
+                fs_type: pathops::mount_of(p).ok().map(|m| m.fs_type),
+                source: sources
+                    .iter()
+                    .find(|s| s.added.contains(p))
+                    .map(|s| s.file.clone()),
+            }
+        })
+        .collect();
+    // What dedup and prune would drop, which changes nothing that runs:
+    let kept = pathops::dedup_positions(&paths, pathops::Keep::First);
+    let dropped: Vec<&EntryCost> = costs
+        .iter()
+        .filter(|c| !kept.contains(&c.index) || !pathops::exists(&c.path))
+        .collect();
+    let saved_us: u64 = dropped.iter().map(|c| c.lookup_us).sum();
+    let lookup_us: u64 = costs.iter().map(|c| c.lookup_us).sum();
+    let total_ms: u64 = costs.iter().map(|c| c.ms).sum();
+    let slow: Vec<&EntryCost> = costs.iter().filter(|c| c.ms > threshold).collect();
+    let unattributed = costs.iter().filter(|c| c.source.is_none()).count();
+    if format == Format::Json {
+        return print_json(&serde_json::json!({
+            "shell": shell,
+            "sources": sources,
+            "unattributed": unattributed,
+            "total_ms": total_ms,
+            "lookup_us": lookup_us,
+            "slow": slow,
+            "dropped": dropped,
+            "saved_us": saved_us,
+        }));
+    }
+    println!("{}", "files that add entries".bold());
+    let width = column_width(
+        sources
+            .iter()
+            .map(|s| s.added.len().to_string().len())
+            .max()
+            .unwrap_or(1),
+        3,
+    );
+    for s in sources.iter().filter(|s| !s.added.is_empty()) {
+        println!(
+            "  {}  {}, read by {}",
+            format!("{:>width$}", s.added.len()).magenta(),
+            fmt_path(&s.file, 0),
+            s.reader
+        );
+    }
+    if unattributed > 0 {
+        println!(
+            "  {}  added after startup, like by a terminal, an IDE or by hand",
+            format!("{:>width$}", unattributed).yellow()
+        );
+    }
+    println!(
+        "{}",
+        format!("entries slower than {} ms to look at", threshold).bold()
+    );
+    if slow.is_empty() {
+        println!("  none");
+    }
+    for c in slow.iter() {
+        let from = match &c.source {
+            Some(file) => format!("from {}", fmt_path(file, 0)),
+            None => String::from("added after startup"),
+        };
+        println!(
+            "  {}  {} ms on {}, {}",
+            fmt_path(&c.path, 1),
+            c.ms,
+            c.fs_type.as_deref().unwrap_or("an unknown file system"),
+            from
+        );
+    }
+    println!("{}", "cleaned ordering".bold());
+    println!(
+        "  looking at all {} entries took {} ms, and looking for a command that isn't found takes {} µs",
+        costs.len(),
+        total_ms,
+        lookup_us
+    );
+    if dropped.is_empty() {
+        println!("  nothing to drop, there are no duplicate or missing entries");
+    } else {
+        println!(
+            "  dropping {} duplicate and missing entries with dedup and prune saves {} µs on every lookup of a command that isn't found",
+            dropped.len(),
+            saved_us
+        );
+    }
+    Ok(())
+}
+
+// Builds use JAVA_HOME while shells run whatever java comes first in PATH, and
+// the two disagreeing about the JDK makes for failures that are hard to place
+fn validate_java(paths: &[PathBuf], issues: &mut Vec<pathops::Issue>) {
+    let Some(java_home) = env::var_os("JAVA_HOME").filter(|h| !h.is_empty()) else {
+        return;
+    };
+    let java_home = PathBuf::from(java_home);
+    let home = pathops::resolve(&java_home).unwrap_or(java_home.clone());
+    // Every entry with a java, in the order they are looked up:
+    let providers: Vec<(&PathBuf, PathBuf)> = paths
+        .iter()
+        .filter_map(|p| {
+            let java = pathops::find_executable(p.as_os_str(), "java")?;
+            Some((p, pathops::resolve(&java).unwrap_or(java)))
+        })
+        .collect();
+    let Some((entry, java)) = providers.first() else {
+        issue!(
+            issues,
+            "java-missing",
+            &java_home,
+            "{} is set but no java is found in PATH",
+            fmt_path(&java_home, 1)
+        );
+        return;
+    };
+    // jenv's shims pick the JDK per directory, and set JAVA_HOME to match:
+    if entry.ends_with(".jenv/shims") {
+        return;
+    }
+    if !java.starts_with(&home) {
+        issue!(
+            issues,
+            "java-mismatch",
+            entry,
+            "{} runs {}, which is not the JDK in JAVA_HOME ({})",
+            fmt_path(entry, 1),
+            fmt_path(java, 1),
+            fmt_path(&java_home, 0)
+        );
+        let matching = providers.iter().find(|(_, j)| j.starts_with(&home));
+        if let Some((other, _)) = matching {
+            issue!(
+                issues,
+                "java-later",
+                other,
+                "{} has the java from JAVA_HOME but comes later",
+                fmt_path(other, 0)
+            );
+        }
+    }
+}
+
+// A shim directory stands in for many apps at once, so its place in PATH decides
+// between it and every app installed some other way
+fn validate_shims(paths: &[PathBuf], issues: &mut Vec<pathops::Issue>) {
+    for (i, shims) in paths.iter().enumerate() {
+        let Some(manager) = packages::shim_manager(shims) else {
+            continue;
+        };
+        let names: HashSet<String> = packages::command_names(shims).into_iter().collect();
+        for (j, other) in paths.iter().enumerate().filter(|(j, _)| *j != i) {
+            if packages::shim_manager(other).is_some() && j > i {
+                continue;
+            }
+            let mut both: Vec<String> = packages::command_names(other)
+                .into_iter()
+                .filter(|n| names.contains(n))
+                .collect();
+            if both.is_empty() {
+                continue;
+            }
+            both.truncate(MAX_PACKAGES_SHOWN);
+            let winner = if j < i { other } else { shims };
+            issue!(
+                issues,
+                "shim-conflict",
+                other,
+                "{} and the {} shims in {} both have {}, {} comes first",
+                fmt_path(other, 1),
+                manager,
+                fmt_path(shims, 1),
+                both.join(", "),
+                fmt_path(winner, 0)
+            );
+        }
+        // Shims only keep working in new sessions if the registry has them:
+        #[cfg(windows)]
+        if let Ok(registry) = pathops::registry_path() {
+            let (added, _) = pathops::find_drift(shims.as_os_str(), registry);
+            if !added.is_empty() {
+                issue!(
+                    issues,
+                    "shim-registry",
+                    shims,
+                    "{} is not in the registry PATH, new sessions won't have the {} shims",
+                    fmt_path(shims, 2),
+                    manager
+                );
+            }
+        }
+    }
+}
+
+// Commands installed by winget are links in one directory, which has to be in
+// PATH, and the links are left behind when the package goes away without winget
+fn validate_winget(paths: &[PathBuf], issues: &mut Vec<pathops::Issue>) {
+    let Some(links) = packages::winget_links().filter(|l| l.is_dir()) else {
+        return;
+    };
+    if pathops::find_matches(paths, &links).is_empty() {
+        issue!(
+            issues,
+            "winget-not-in-path",
+            &links,
+            "{} has the commands winget installs but isn't in PATH, add it to the user PATH",
+            fmt_path(&links, 2)
+        );
+    }
+    for link in packages::broken_links(&links) {
+        issue!(
+            issues,
+            "winget-broken-link",
+            &link,
+            "{} links to a package that is no longer there",
+            fmt_path(&link, 1)
+        );
+    }
+}
+
+fn validate(path: &OsStr, var: &str, allow_cwd: bool) -> Result<Vec<pathops::Issue>> {
+    let paths = pathops::split(path);
+    let mut issues = pathops::validate(&paths, var, allow_cwd, |p, level| fmt_path(p, level))?;
+    // Packages, toolchains and mounts are this system's, and home this user's, so
+    // they say nothing about entries under another root:
+    let on_host = pathops::root().is_none();
+    // Missing entries a package manager added are left over from it:
+    for issue in issues.iter_mut().filter(|i| on_host && i.code == "missing") {
+        let Some(p) = issue.path.as_deref() else {
+            continue;
+        };
+        if let Some(owner) = packages::orphaned_by(p) {
+            issue.code = "orphaned";
+            issue.message = format!(
+                "{} is left over from {} package {}, which is no longer installed",
+                fmt_path(p, 2),
+                owner.manager,
+                owner.packages.join(", ")
+            );
+        }
+    }
+    // Versioned Homebrew paths stop working on the next upgrade:
+    for p in paths.iter() {
+        if let Some(opt) = packages::brew_opt_path(p) {
+            if paths.contains(&opt) {
+                issue!(
+                    issues,
+                    "brew-duplicate",
+                    p,
+                    "{} is also in PATH as {}, which follows upgrades",
+                    fmt_path(p, 1),
+                    fmt_path(&opt, 0)
+                );
+            } else if packages::orphaned_by(p).is_none() {
+                issue!(
+                    issues,
+                    "brew-pinned",
+                    p,
+                    "{} is pinned to one version, use {} to follow upgrades",
+                    fmt_path(p, 1),
+                    fmt_path(&opt, 0)
+                );
+            }
+        }
+    }
+    // A toolchain ahead of the rustup proxies wins over rust-toolchain files and
+    // overrides, as its rustc and cargo are found first:
+    let cargo_bin = packages::cargo_bin()
+        .filter(|_| on_host)
+        .and_then(|b| pathops::resolve(&b).ok());
+    let proxies = cargo_bin.and_then(|bin| {
+        paths
+            .iter()
+            .position(|p| pathops::resolve(p).is_ok_and(|res| res == bin))
+    });
+    for (i, p) in paths.iter().enumerate() {
+        if let Some(name) = packages::rustup_toolchain(p).filter(|_| on_host) {
+            if proxies.is_none_or(|proxies| i < proxies) {
+                issue!(
+                    issues,
+                    "rustup-toolchain",
+                    p,
+                    "{} is ahead of the rustup proxies, so toolchain {} wins over overrides and rust-toolchain files, remove it to let rustup pick",
+                    fmt_path(p, 1),
+                    name
+                );
+            }
+        }
+    }
+    if var == "PATH" && on_host {
+        validate_java(&paths, &mut issues);
+        validate_winget(&paths, &mut issues);
+    }
+    if var == "PATH" {
+        validate_shims(&paths, &mut issues);
+        if cfg!(windows) {
+            validate_max_path(&paths, &mut issues);
+        }
+    }
+    // Tools installed with pipx or uv can't be run unless their directory is in
+    // PATH, and running 'ensurepath' more than once tends to add it again:
+    if var == "PATH" && on_host {
+        for (tool, dir) in packages::python_tool_dirs(path) {
+            let positions = pathops::find_positions(&paths, &dir, false);
+            let resolved = pathops::resolve(&dir)
+                .map(|res| pathops::find_positions(&paths, &res, true))
+                .unwrap_or_default();
+            if positions.is_empty() && resolved.is_empty() {
+                issue!(
+                    issues,
+                    "tool-dir-missing",
+                    &dir,
+                    "{} is where {} puts commands, but it isn't in PATH",
+                    fmt_path(&dir, 2),
+                    tool
+                );
+            } else if positions.len() > 1 {
+                issue!(
+                    issues,
+                    "tool-dir-repeated",
+                    &dir,
+                    "{} is where {} puts commands and was added {} times, dedup keeps only the first",
+                    fmt_path(&dir, 1),
+                    tool,
+                    positions.len()
+                );
+            }
+        }
+    }
+    debug_assert!(
+        issues
+            .iter()
+            .all(|i| ISSUE_CODES.iter().any(|c| c.code == i.code)),
+        "validate gave an issue code that isn't listed"
+    );
+
+    Ok(issues)
+}
+
+// Legacy programs fail to open anything with a path longer than MAX_PATH as if it
+// wasn't there, which is hard to tell from the outside
+fn validate_max_path(paths: &[PathBuf], issues: &mut Vec<pathops::Issue>) {
+    // Room left for file names, enough for the long names installers like to use:
+    const MARGIN: usize = 64;
+    let support = match pathops::long_paths_enabled() {
+        Some(true) => "long paths are enabled, but only for programs that opt in",
+        _ => "long paths are not enabled in the registry",
+    };
+    for p in paths.iter().filter(|p| pathops::exists(p)) {
+        let long = pathops::exceeds_max_path(p);
+        let room = pathops::max_path_room(p);
+        if let Some(exe) = long.first() {
+            issue!(
+                issues,
+                "max-path",
+                p,
+                "{} has {} executables with paths longer than MAX_PATH ({}), like {}, {}",
+                fmt_path(p, 2),
+                long.len(),
+                pathops::MAX_PATH,
+                exe.file_name().unwrap_or_default().to_string_lossy(),
+                support
+            );
+        } else if room < MARGIN {
+            issue!(
+                issues,
+                "max-path-margin",
+                p,
+                "{} leaves only {} characters for file names within MAX_PATH, {}",
+                fmt_path(p, 1),
+                room,
+                support
+            );
+        }
+    }
+}
+
+// Tell apart what this session added to PATH from what it hasn't picked up from
+// the registry yet, which takes restarting the terminal or IDE (or signing out)
+#[cfg(windows)]
+fn validate_drift(path: &OsStr, issues: &mut Vec<pathops::Issue>) -> Result<()> {
+    let (added, missing) = pathops::find_drift(path, pathops::registry_path()?);
+    for p in added.iter() {
+        issue!(
+            issues,
+            "session-only",
+            p,
+            "{} is only in this session, new processes won't get it",
+            fmt_path(p, 0)
+        );
+    }
+    for p in missing.iter() {
+        issue!(
+            issues,
+            "registry-only",
+            p,
+            "{} is in the registry but not in this session, restart to get it",
+            fmt_path(p, 1)
+        );
+    }
+    Ok(())
+}
+
+fn dedup(
+    path: OsString,
+    keep: pathops::Keep,
+    prefer_resolved: bool,
+    write: bool,
+    format: Format,
+) -> Result<()> {
+    let paths = pathops::split(&path);
+    let resolved_dups = pathops::find_duplicates_resolved(&paths);
+    let mut warnings: Vec<String> = Vec::new();
+    if !resolved_dups.is_empty() {
+        warnings.push(format!(
+            "{} resolved duplicate entries removed",
+            resolved_dups.len()
+        ));
+    }
+    let kept = pathops::dedup_positions(&paths, keep);
+    // Warn about removing entries that belong to modules or stow:
+    let loaded = envmodules::loaded_modules();
+    for (i, p) in paths.iter().enumerate() {
+        if kept.contains(&i) {
+            continue;
+        }
+        if let Some(m) = envmodules::manager(p, &loaded) {
+            warnings.push(format!(
+                "removed '{}' which is managed by {}",
+                p.display(),
+                m
+            ));
+        }
+    }
+    let unique: Vec<PathBuf> = kept
+        .into_iter()
+        .map(|i| match prefer_resolved {
+            true => pathops::resolve(&paths[i]).unwrap_or_else(|_| paths[i].clone()),
+            false => paths[i].clone(),
+        })
+        .collect();
+    let new_path = pathops::join(&unique)?;
+    if write {
+        write_rc(&new_path)?;
+    }
+    let change = Change {
+        old: path,
+        new: new_path,
+        index: None,
+        warnings,
+    };
+    print_change(change, format)
+}
+
+fn count_exes(path: &OsStr, plain: bool, format: Format) -> Result<()> {
+    let entries = match from_daemon(daemon::Query::Status, path, None)? {
+        Some(entries) => entries,
+        None => pathops::entries(&pathops::split(path), true),
+    };
+    if format == Format::Json {
+        return print_json(&entries);
+    }
+    for entry in entries.iter() {
+        if plain {
+            println!("{}", plain_count(entry));
+            continue;
+        }
+        let p = &entry.path;
+        match entry.executables {
+            Some(0) => println!("{}: {}", fmt_path(p, 1), 0),
+            Some(n) => println!("{}: {}", fmt_path(p, 0), fmt_num(n, 0)),
+            None => println!("{}: --", fmt_path(p, 2)),
+        }
+    }
+
+    Ok(())
+}
+
+// Ask a running daemon, which has the scan of the entries at hand, unless a
+// fixture is recorded or replayed as that has to see every observation, or the
+// entries are looked at differently than the daemon does. Requests are JSON, so
+// a path string that isn't valid Unicode is looked at here instead.
+fn from_daemon<T: DeserializeOwned>(
+    query: daemon::Query,
+    path: &OsStr,
+    name: Option<&str>,
+) -> Result<Option<T>> {
+    let Some(path) = path
+        .to_str()
+        .filter(|_| !fixture::active() && !pathops::is_tuned())
+    else {
+        return Ok(None);
+    };
+    let request = daemon::Request {
+        query,
+        path: path.to_string(),
+        name: name.map(String::from),
+    };
+    match daemon::request(&request) {
+        Some(value) => Ok(Some(serde_json::from_value(value?)?)),
+        None => Ok(None),
+    }
+}
+
+// Print the command loading a module that provides addition, if there is one
+fn print_module_load(addition: &str) -> bool {
+    match envmodules::find_available_module(Path::new(addition)) {
+        Some(name) => {
+            println!("module load {}", name);
+            true
+        }
+        None => false,
+    }
+}
+
+// Flags shared by the commands adding a directory
+#[derive(Default)]
+struct AddOptions {
+    module: bool,
+    keep_relative: bool,
+    allow_cwd: bool,
+    promote: bool,
+    create: bool,
+    force: bool,
+    // Also keep the result in the startup file of the login shell
+    write: bool,
+}
+
+// Make a relative addition absolute, unless asked to keep it as it is, since a
+// relative entry is looked up from whatever directory a command is run in. An
+// entry for the current directory that is allowed stays as written, as making it
+// absolute would add the directory pathaid runs in instead.
+fn absolute_addition(
+    addition: &str,
+    opts: &AddOptions,
+    warnings: &mut Vec<String>,
+) -> Result<String> {
+    if !opts.allow_cwd {
+        pathops::ensure_not_cwd(addition)?;
+    } else if pathops::is_cwd_entry(Path::new(addition)) {
+        warnings.push(format!(
+            "'{}' is the current directory, {}",
+            addition,
+            pathops::CWD_RATIONALE
+        ));
+        return Ok(addition.to_string());
+    }
+    if Path::new(addition).is_absolute() {
+        Ok(addition.to_string())
+    } else if opts.keep_relative {
+        warnings.push(format!(
+            "'{}' is relative and will be looked up from the current directory of every command",
+            addition
+        ));
+        Ok(addition.to_string())
+    } else {
+        Ok(pathops::into_string(
+            pathops::absolute(addition)?.into_os_string(),
+        )?)
+    }
+}
+
+// Check an addition against path, returning the path to add it to: without any
+// existing entry for it if promoting, so it moves instead of being refused
+fn check_addition(
+    path: &OsStr,
+    addition: &str,
+    opts: &AddOptions,
+    warnings: &mut Vec<String>,
+) -> Result<OsString> {
+    let cwd = pathops::is_cwd_entry(Path::new(addition));
+    let path = if opts.promote && cwd {
+        let mut paths = pathops::split_entries(path);
+        paths.retain(|p| !pathops::is_cwd_entry(p));
+        pathops::join(&paths)?
+    } else if opts.promote {
+        pathops::remove_paths(path, &[PathBuf::from(addition)])?
+    } else {
+        path.to_os_string()
+    };
+    // There always is a current directory, and it is a different one wherever a
+    // command runs, so only another entry for it makes it present:
+    if cwd {
+        if pathops::split(&path)
+            .iter()
+            .any(|p| pathops::is_cwd_entry(p))
+        {
+            return Err(pathops::Error::Present(format!(
+                "{} already has an entry for the current directory",
+                var_name()
+            ))
+            .into());
+        }
+        return Ok(path);
+    }
+    // Created once every addition is checked:
+    if !pathops::exists(Path::new(addition)) && (opts.create || opts.force) {
+        if !opts.create {
+            warnings.push(pathops::Error::Missing(addition.to_string()).to_string());
+        }
+        pathops::ensure_unique_addition(&path, addition)?;
+        return Ok(path);
+    }
+    pathops::validate_addition(&path, addition)?;
+    Ok(path)
+}
+
+// Split and check every addition in order, giving the path with any promoted
+// entries removed and the directories to add. With --create the missing ones are
+// only made after that, so a later addition that is refused leaves none behind.
+fn check_additions(
+    path: &OsStr,
+    additions: &[String],
+    opts: &AddOptions,
+    warnings: &mut Vec<String>,
+) -> Result<(OsString, Vec<PathBuf>)> {
+    let mut dirs: Vec<PathBuf> = Vec::new();
+    let mut base = path.to_os_string();
+    for addition in pathops::split_additions(additions) {
+        let addition = absolute_addition(&addition.to_string_lossy(), opts, warnings)?;
+        base = check_addition(&base, &addition, opts, warnings)?;
+        dirs.push(PathBuf::from(addition));
+    }
+    ensure!(
+        !opts.module || dirs.len() == 1,
+        "--module can only be used with a single directory"
+    );
+    if opts.create {
+        for dir in dirs.iter().filter(|d| !pathops::exists(d)) {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("unable to create '{}'", dir.display()))?;
+        }
+    }
+    Ok((base, dirs))
+}
+
+fn append_paths(
+    path: OsString,
+    additions: &[String],
+    opts: AddOptions,
+    format: Format,
+) -> Result<()> {
+    let mut warnings: Vec<String> = Vec::new();
+    let (base, dirs) = check_additions(&path, additions, &opts, &mut warnings)?;
+    if opts.module && print_module_load(&dirs[0].to_string_lossy()) {
+        return Ok(());
+    }
+    let new_path = pathops::append_paths(&base, &dirs)?;
+    if opts.write {
+        write_rc(&new_path)?;
+    }
+    let change = Change {
+        // Where the first of the additions ended up:
+        index: Some(pathops::split(&new_path).len() - dirs.len()),
+        old: path,
+        new: new_path,
+        warnings,
+    };
+    print_change(change, format)
+}
+
+fn prepend_paths(
+    path: OsString,
+    additions: &[String],
+    opts: AddOptions,
+    format: Format,
+) -> Result<()> {
+    let mut warnings: Vec<String> = Vec::new();
+    let (base, dirs) = check_additions(&path, additions, &opts, &mut warnings)?;
+    if opts.module && print_module_load(&dirs[0].to_string_lossy()) {
+        return Ok(());
+    }
+    let new_path = pathops::prepend_paths(&base, &dirs)?;
+    if opts.write {
+        write_rc(&new_path)?;
+    }
+    let change = Change {
+        old: path,
+        new: new_path,
+        index: Some(0),
+        warnings,
+    };
+    print_change(change, format)
+}
+
+// Where insert adds directories, or move moves an entry to
+enum Position {
+    Front,
+    Back,
+    At(usize),
+    Before(String),
+    After(String),
+}
+
+impl Position {
+    // From the options of insert or move, each only has some of them
+    fn from_matches(subm: &ArgMatches) -> Position {
+        let index = |id| subm.try_get_one::<usize>(id).ok().flatten().copied();
+        let entry = |id| subm.get_one::<String>(id).cloned();
+        if let Some(index) = index("to").or(index("at")) {
+            Position::At(index)
+        } else if let Some(other) = entry("before") {
+            Position::Before(other)
+        } else if let Some(other) = entry("after") {
+            Position::After(other)
+        } else if subm.try_get_one::<bool>("to-front").ok().flatten() == Some(&true) {
+            Position::Front
+        } else {
+            Position::Back
+        }
+    }
+}
+
+// Find the positions of entries given as #<position> like validate reports them,
+// or as a directory matched as written or resolved
+fn target_matches(paths: &[PathBuf], target: &str) -> Result<Vec<usize>> {
+    Ok(match target.strip_prefix('#').map(str::parse::<usize>) {
+        Some(Ok(i)) if i < paths.len() => vec![i],
+        Some(Ok(i)) => bail!(
+            "there is no entry at position {}, {} has {}",
+            i,
+            var_name(),
+            paths.len()
+        ),
+        _ => pathops::find_matches(paths, Path::new(target)),
+    })
+}
+
+// Find the one entry given like remove takes them, preferring one written the
+// same way over earlier ones only resolving to the same directory
+fn find_entry(entries: &[PathBuf], entry: &str) -> Result<usize> {
+    let matches = target_matches(entries, entry)?;
+    let written = pathops::find_positions(entries, Path::new(entry), false);
+    matches
+        .iter()
+        .find(|i| written.contains(i))
+        .or(matches.first())
+        .copied()
+        .with_context(|| format!("{} has no entry '{}'", var_name(), entry))
+}
+
+fn insert_paths(
+    path: OsString,
+    additions: &[String],
+    position: Position,
+    opts: AddOptions,
+    format: Format,
+) -> Result<()> {
+    let mut warnings: Vec<String> = Vec::new();
+    let (base, dirs) = check_additions(&path, additions, &opts, &mut warnings)?;
+    // Positions are in what is left once any promoted entries are taken out:
+    let entries = pathops::split_entries(&base);
+    let index = match position {
+        Position::Front => 0,
+        Position::Back => entries.len(),
+        Position::At(index) => index,
+        Position::Before(entry) => find_entry(&entries, &entry)?,
+        Position::After(entry) => find_entry(&entries, &entry)? + 1,
+    };
+    let new_path = pathops::insert_paths(&base, index, &dirs)?;
+    if opts.write {
+        write_rc(&new_path)?;
+    }
+    if format == Format::Text && QUIET.get() != Some(&true) {
+        eprintln!("inserted at {}", index);
+    }
+    let change = Change {
+        old: path,
+        new: new_path,
+        index: Some(index),
+        warnings,
+    };
+    print_change(change, format)
+}
+
+fn move_path(path: OsString, entry: &str, position: Position, format: Format) -> Result<()> {
+    let entries = pathops::split_entries(&path);
+    let from = find_entry(&entries, entry)?;
+    // Where it goes among the others, as if it was taken out first:
+    let among_others = |other: &str, after: bool| -> Result<usize> {
+        let i = find_entry(&entries, other)?;
+        ensure!(i != from, "'{}' can't be moved next to itself", entry);
+        let i = i + usize::from(after);
+        Ok(if i > from { i - 1 } else { i })
+    };
+    let to = match position {
+        Position::Front => 0,
+        Position::Back => entries.len().saturating_sub(1),
+        Position::At(index) => index,
+        Position::Before(other) => among_others(&other, false)?,
+        Position::After(other) => among_others(&other, true)?,
+    };
+    let new_path = pathops::move_entry(&path, from, to)?;
+    if format == Format::Text && QUIET.get() != Some(&true) {
+        eprintln!("moved from {} to {}", from, to);
+    }
+    let change = Change {
+        old: path,
+        new: new_path,
+        index: Some(to),
+        warnings: Vec::new(),
+    };
+    print_change(change, format)
+}
+
+// Print the entries an expression matches, like list does or as JSON records
+fn query(path: &OsStr, expr: &str, plain: bool, format: Format) -> Result<()> {
+    let expr = query::parse(expr)?;
+    ensure!(
+        matches!(format, Format::Text | Format::Json),
+        "query can only print text or JSON"
+    );
+    // Only run a login shell for the system entries if they are asked about:
+    let system = match expr.uses("origin") || format == Format::Json {
+        true => Some(pathops::split(&pathops::system_path()?)),
+        false => None,
+    };
+    let mut matched: Vec<query::Entry> = Vec::new();
+    for entry in query::entries(path, system.as_deref()) {
+        if expr.matches(&entry)? {
+            matched.push(entry);
+        }
+    }
+    match format {
+        Format::Json => print_json(&matched)?,
+        _ if plain => {
+            for entry in matched.iter() {
+                println!("{}", redact(&entry.path.to_string_lossy()));
+            }
+        }
+        _ => {
+            for entry in matched.iter() {
+                println!("{} {}", fmt_num(entry.index, 0), fmt_entry(&entry.path));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// Get the path string to compare with: from stdin, a file holding one like
+// --stdin reads it, a snapshot, or else the argument itself
+fn read_other(other: &str, var: &str) -> Result<OsString> {
+    if other == "-" {
+        let mut input = Vec::new();
+        io::stdin()
+            .read_to_end(&mut input)
+            .context("unable to read the path string from stdin")?;
+        return parse_input(&input, var);
+    }
+    if Path::new(other).is_file() {
+        let input = fs::read(other).with_context(|| format!("unable to read '{}'", other))?;
+        return parse_input(&input, var);
+    }
+    if let Ok(snapshot) = state::read_snapshot(other) {
+        return Ok(snapshot.path.into());
+    }
+    Ok(other.into())
+}
+
+// Print the entries of the other path string, with those only in this one where
+// they were, marked with + for added, - for removed and ~ for moved
+fn diff(path: &OsStr, other: &OsStr, format: Format) -> Result<()> {
+    let entries = pathops::diff(
+        &pathops::split_entries(path),
+        &pathops::split_entries(other),
+    );
+    if format == Format::Json {
+        return print_json(&entries);
+    }
+    let count = |kind| entries.iter().filter(|e| e.kind == kind).count();
+    for e in entries.iter() {
+        let p = redact(&e.path.to_string_lossy());
+        match e.kind {
+            pathops::DiffKind::Same => println!("  {}", p),
+            pathops::DiffKind::Added => println!("{}", format!("+ {}", p).green()),
+            pathops::DiffKind::Removed => println!("{}", format!("- {}", p).red()),
+            pathops::DiffKind::Moved => println!(
+                "{} {}",
+                format!("~ {}", p).yellow(),
+                format!(
+                    "(moved from {} to {})",
+                    e.old_index.unwrap_or(0),
+                    e.new_index.unwrap_or(0)
+                )
+                .dimmed()
+            ),
+        }
+    }
+    println!(
+        "{} added, {} removed, {} moved",
+        count(pathops::DiffKind::Added),
+        count(pathops::DiffKind::Removed),
+        count(pathops::DiffKind::Moved)
+    );
+
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct Shadowed {
+    index: usize,
+    path: PathBuf,
+}
+
+// What a command name runs, and what it would run if not for that
+#[derive(Serialize)]
+struct Resolution {
+    index: usize,
+    path: PathBuf,
+    // Where a link leads, if it is one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    resolved: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sha256: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    version: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    shadowed: Vec<Shadowed>,
+}
+
+#[derive(Serialize)]
+struct ResolutionMap {
+    pathaid: &'static str,
+    // Seconds since the epoch
+    time: u64,
+    path: String,
+    commands: BTreeMap<String, Resolution>,
+}
+
+// How long a command gets to print its version
+const VERSION_TIMEOUT: Duration = Duration::from_secs(2);
+
+// Run the executable with --version and take the first line it prints, giving up
+// on it if it fails, waits for input or takes too long. Not all take --version
+// as a flag, so they run where anything they write doesn't get in the way.
+fn probe_version(exe: &Path) -> Option<String> {
+    let mut child = process::Command::new(exe)
+        .arg("--version")
+        .current_dir(env::temp_dir())
+        .stdin(process::Stdio::null())
+        .stdout(process::Stdio::piped())
+        .stderr(process::Stdio::piped())
+        .spawn()
+        .ok()?;
+    let start = Instant::now();
+    while child.try_wait().ok()?.is_none() {
+        if start.elapsed() > VERSION_TIMEOUT {
+            let _ = child.kill();
+            let _ = child.wait();
+            return None;
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    let output = child.wait_with_output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    // Some print their version on stderr:
+    [output.stdout, output.stderr].iter().find_map(|out| {
+        String::from_utf8_lossy(out)
+            .lines()
+            .map(str::trim)
+            .find(|l| !l.is_empty())
+            .map(String::from)
+    })
+}
+
+fn export_resolution_map(path: &OsStr, file: &Path, hash: bool, versions: bool) -> Result<()> {
+    let paths = pathops::split(path);
+    let mut commands: BTreeMap<String, Resolution> = pathops::scan_executables(&paths)
+        .into_iter()
+        .filter_map(|(name, mut found)| {
+            if found.is_empty() {
+                return None;
+            }
+            let (index, exe) = found.remove(0);
+            let resolution = Resolution {
+                index,
+                resolved: pathops::resolve(&exe).ok().filter(|r| *r != exe),
+                sha256: if hash {
+                    pathops::file_sha256(&exe).ok()
+                } else {
+                    None
+                },
+                version: None,
+                shadowed: found
+                    .into_iter()
+                    .map(|(index, path)| Shadowed { index, path })
+                    .collect(),
+                path: exe,
+            };
+            Some((name, resolution))
+        })
+        .collect();
+    // Most answer at once, but those that don't shouldn't hold up the rest:
+    if versions {
+        // Nothing is run from entries audit has errors for, like ones others can
+        // write to, where anything could have been put:
+        let unsafe_entries: HashSet<usize> = audit::audit(&paths)
+            .iter()
+            .filter(|f| f.severity == audit::Severity::Error)
+            .map(|f| f.index)
+            .collect();
+        let total = commands.len();
+        let mut all: Vec<&mut Resolution> = commands
+            .values_mut()
+            .filter(|r| !unsafe_entries.contains(&r.index))
+            .collect();
+        let skipped = total - all.len();
+        let threads = std::thread::available_parallelism().map_or(4, |n| n.get());
+        let chunk = all.len().div_ceil(threads).max(1);
+        std::thread::scope(|s| {
+            for part in all.chunks_mut(chunk) {
+                s.spawn(move || {
+                    for r in part.iter_mut() {
+                        r.version = probe_version(&r.path);
+                    }
+                });
+            }
+        });
+        if skipped > 0 {
+            print_warnings(&[format!(
+                "not running {} executables in entries audit reports errors for",
+                skipped
+            )]);
+        }
+    }
+    let time = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let map = ResolutionMap {
+        pathaid: env!("CARGO_PKG_VERSION"),
+        time: if DETERMINISTIC.get() == Some(&true) {
+            0
+        } else {
+            time
+        },
+        path: path.to_string_lossy().into_owned(),
+        commands,
+    };
+    let json = serde_json::to_string_pretty(&redact_json(&map)?)?;
+    if file == Path::new("-") {
+        println!("{}", json);
+        return Ok(());
+    }
+    state::write_atomic(file, json + "\n")?;
+    println!(
+        "wrote {} commands to {}",
+        map.commands.len(),
+        fmt_path(file, 0)
+    );
+
+    Ok(())
+}
+
+// Report every command provided by more than one entry, with the copy that runs
+// first and the shadowed ones after, noting copies that are the same file anyway
+fn shadow(path: &OsStr, plain: bool, format: Format) -> Result<()> {
+    let commands: BTreeMap<String, Vec<(usize, PathBuf)>> =
+        match from_daemon(daemon::Query::Shadow, path, None)? {
+            Some(commands) => commands,
+            None => {
+                let mut commands = pathops::scan_executables(&pathops::split(path));
+                commands.retain(|_, found| found.len() > 1);
+                commands
+            }
+        };
+    if format == Format::Json {
+        return print_json(&commands);
+    }
+    for (name, found) in commands.iter() {
+        if plain {
+            let exes: Vec<String> = found
+                .iter()
+                .map(|(_, exe)| redact(&exe.to_string_lossy()))
+                .collect();
+            println!("{} {}", redact(name), exes.join(" "));
+            continue;
+        }
+        println!("{}", redact(name).bold());
+        let winner = pathops::resolve(&found[0].1).ok();
+        let width = column_width(
+            found.last().map(|(i, _)| i.to_string().len()).unwrap_or(1),
+            3,
+        );
+        for (n, (i, exe)) in found.iter().enumerate() {
+            let position = format!("{:>width$}", i).magenta();
+            if n == 0 {
+                println!("  {}  {}", position, fmt_path(exe, 0));
+            } else if winner.is_some() && pathops::resolve(exe).ok() == winner {
+                println!(
+                    "  {}  {} {}",
+                    position,
+                    fmt_path(exe, 0),
+                    "(same file)".dimmed()
+                );
+            } else {
+                println!(
+                    "  {}  {} {}",
+                    position,
+                    fmt_path(exe, 1),
+                    "(shadowed)".dimmed()
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// Tell what a command name runs, or with all every executable it could run and
+// the position of the entry it is in, the ones after the first being shadowed
+fn which(path: &OsStr, name: &str, all: bool, plain: bool, format: Format) -> Result<()> {
+    ensure!(
+        !name.contains(std::path::is_separator),
+        "'{}' is a path, not a command name",
+        name
+    );
+    let found = pathops::find_executables(path, name);
+    ensure!(!found.is_empty(), "'{}' is not in any entry of PATH", name);
+    if format == Format::Json {
+        let found: Vec<serde_json::Value> = found
+            .iter()
+            .take(if all { usize::MAX } else { 1 })
+            .map(|(i, exe)| serde_json::json!({ "index": i, "path": exe }))
+            .collect();
+        return print_json(&found);
+    }
+    if !all {
+        println!("{}", redact(&found[0].1.to_string_lossy()));
+        return Ok(());
+    }
+    if plain {
+        for (i, exe) in found.iter() {
+            println!("{} {}", i, redact(&exe.to_string_lossy()));
+        }
+        return Ok(());
+    }
+    let width = column_width(
+        found.last().map(|(i, _)| i.to_string().len()).unwrap_or(1),
+        3,
+    );
+    for (n, (i, exe)) in found.iter().enumerate() {
+        let position = format!("{:>width$}", i);
+        if n == 0 {
+            println!("{}  {}", position.magenta(), fmt_path(exe, 0));
+        } else {
+            println!(
+                "{}  {} {}",
+                position.magenta(),
+                fmt_path(exe, 1),
+                "(shadowed)".dimmed()
+            );
+        }
+    }
+
+    Ok(())
+}
+
+// Remove entries that aren't existing directories, or that have no executables
+// (or nothing at all, for variables other than PATH) unless those are kept,
+// telling on stderr what goes. A dry run prints PATH as it was, so it can be
+// tried out in the same place as the real thing.
+fn prune(path: OsString, var: &str, keep_empty: bool, dry_run: bool, format: Format) -> Result<()> {
+    let paths = pathops::split_entries(&path);
+    let mut warnings: Vec<String> = Vec::new();
+    let mut kept: Vec<PathBuf> = Vec::new();
+    for p in paths.into_iter() {
+        // Entries given up on are kept, and reported as not checked:
+        let reason = if pathops::is_cwd_entry(&p) || pathops::is_skipped(&p) {
+            None
+        } else if !pathops::exists(&p) {
+            Some("is not an existing directory")
+        } else if keep_empty {
+            None
+        } else if var == "PATH" && pathops::is_empty(&p).unwrap_or(false) {
+            Some("has no executables")
+        } else if var != "PATH" && pathops::is_empty_dir(&p).unwrap_or(false) {
+            Some("is empty")
+        } else {
+            None
+        };
+        match reason {
+            Some(reason) => warnings.push(format!(
+                "{} '{}', which {}",
+                if dry_run { "would remove" } else { "removed" },
+                p.display(),
+                reason
+            )),
+            None => kept.push(p),
+        }
+    }
+    let new_path = if dry_run {
+        path.clone()
+    } else {
+        pathops::join(&kept)?
+    };
+    let change = Change {
+        old: path,
+        new: new_path,
+        index: None,
+        warnings,
+    };
+    print_change(change, format)
+}
+
+// Strip invisible characters and spaces at the ends of entries, dropping entries
+// that had nothing else
+fn clean(path: OsString, format: Format) -> Result<()> {
+    let mut warnings: Vec<String> = Vec::new();
+    let mut kept: Vec<PathBuf> = Vec::new();
+    for p in pathops::split_entries(&path).into_iter() {
+        let stripped = pathops::strip_invisible(&p);
+        if stripped == p {
+            kept.push(p);
+        } else if stripped.as_os_str().is_empty() {
+            warnings.push(format!("removed '{}'", pathops::escape_invisible(&p)));
+        } else {
+            warnings.push(format!(
+                "cleaned '{}' to '{}'",
+                pathops::escape_invisible(&p),
+                stripped.display()
+            ));
+            kept.push(stripped);
+        }
+    }
+    let change = Change {
+        new: pathops::join(&kept)?,
+        old: path,
+        index: None,
+        warnings,
+    };
+    print_change(change, format)
+}
+
+// Write entries without trailing separators, '.' segments and repeated separators,
+// and relative ones from the current directory if absolute
+fn normalize(path: OsString, absolute: bool, format: Format) -> Result<()> {
+    let mut warnings: Vec<String> = Vec::new();
+    let mut entries: Vec<PathBuf> = Vec::new();
+    for p in pathops::split(&path).into_iter() {
+        let mut normalized = pathops::normalize(&p);
+        // What the current directory is depends on where a command runs:
+        if absolute && normalized.is_relative() && !pathops::is_cwd_entry(&p) {
+            normalized = pathops::normalize(&pathops::absolute(&normalized)?);
+        }
+        if normalized.as_os_str() != p.as_os_str() {
+            warnings.push(format!(
+                "normalized '{}' to '{}'",
+                p.display(),
+                normalized.display()
+            ));
+        }
+        entries.push(normalized);
+    }
+    let change = Change {
+        new: pathops::join(&entries)?,
+        old: path,
+        index: None,
+        warnings,
+    };
+    print_change(change, format)
+}
+
+// Remove entries given as directories, matched as written or resolved, or as
+// #<position> like validate reports them, only the first match of each unless all
+fn remove_entries(path: OsString, targets: &[String], all: bool, format: Format) -> Result<()> {
+    let paths = pathops::split_entries(&path);
+    let mut warnings: Vec<String> = Vec::new();
+    let mut removed: HashSet<usize> = HashSet::new();
+    for target in targets {
+        let matches = target_matches(&paths, target)?;
+        if matches.is_empty() {
+            warnings.push(format!("nothing in PATH matches '{}'", target));
+        }
+        removed.extend(matches.into_iter().take(if all { usize::MAX } else { 1 }));
+    }
+    ensure!(
+        !removed.is_empty(),
+        "nothing in PATH matches {}",
+        targets
+            .iter()
+            .map(|t| format!("'{}'", t))
+            .collect::<Vec<String>>()
+            .join(", ")
+    );
+    let kept: Vec<PathBuf> = paths
+        .into_iter()
+        .enumerate()
+        .filter(|(i, _)| !removed.contains(i))
+        .map(|(_, p)| p)
+        .collect();
+    let change = Change {
+        new: pathops::join(&kept)?,
+        old: path,
+        index: None,
+        warnings,
+    };
+    print_change(change, format)
+}
+
+// Add or remove the bin directories (and friends) of an install prefix
+fn prefix(
+    prefix: impl AsRef<Path>,
+    vars: &[&str],
+    read: impl Fn(&str) -> Result<OsString>,
+    remove: bool,
+    format: Format,
+) -> Result<()> {
+    let prefix = pathops::absolute(prefix)?;
+    let prefix = prefix.as_path();
+    ensure!(
+        remove || pathops::exists(prefix),
+        format!("'{}' is not an existing directory", prefix.display())
+    );
+    let mut changes: Vec<(String, Change)> = Vec::new();
+    for &var in vars.iter() {
+        let path = read(var)?;
+        let mut new_path = path.clone();
+        let mut index = None;
+        let mut warnings: Vec<String> = Vec::new();
+        if remove {
+            new_path = pathops::remove_paths(&path, &pathops::prefix_paths(prefix, var))?;
+        } else {
+            for dir in pathops::prefix_dirs(prefix, var) {
+                match pathops::validate_addition(&new_path, &dir) {
+                    Ok(()) => {
+                        new_path = pathops::append_path(&new_path, &dir)?;
+                        index.get_or_insert(pathops::split(&new_path).len() - 1);
+                    }
+                    Err(e) => warnings.push(e.to_string()),
+                }
+            }
+        }
+        let change = Change {
+            old: path,
+            new: new_path,
+            index,
+            warnings,
+        };
+        changes.push((var.to_string(), change));
+    }
+    ensure!(
+        changes.iter().any(|(_, c)| c.old != c.new),
+        format!(
+            "nothing to {} for '{}'",
+            if remove { "remove" } else { "add" },
+            prefix.display()
+        )
+    );
+    if changes.len() == 1 && changes[0].0 == "PATH" {
+        print_change(changes.remove(0).1, format)
+    } else {
+        print_changes(changes, format)
+    }
+}
+
+// Run a command with PATH (or another variable) changed for it alone, with
+// additions put in front in the order given, anything matching the removal
+// patterns dropped and then deduplicated
+fn run_with_path(
+    path: OsString,
+    var: &str,
+    with: &[String],
+    without: &[String],
+    command: &[String],
+    allow_cwd: bool,
+) -> Result<()> {
+    let opts = AddOptions {
+        allow_cwd,
+        ..Default::default()
+    };
+    let mut warnings: Vec<String> = Vec::new();
+    let mut new_path = pathops::remove_matching(&path, without)?;
+    for dir in with.iter().rev() {
+        let dir = absolute_addition(dir, &opts, &mut warnings)?;
+        ensure!(
+            pathops::is_cwd_entry(Path::new(&dir)) || pathops::exists(Path::new(&dir)),
+            format!("'{}' is not an existing directory", dir)
+        );
+        new_path = pathops::prepend_path(&new_path, &dir)?;
+    }
+    let new_path = pathops::join(&pathops::dedup(&pathops::split(&new_path)))?;
+    print_warnings(&warnings);
+    let status = process::Command::new(&command[0])
+        .args(&command[1..])
+        .env(var, &new_path)
+        .status()
+        .with_context(|| format!("unable to run '{}'", command[0]))?;
+    process::exit(status.code().unwrap_or(1));
+}
+
+// Run one of the reporting commands for each variable
+fn run_all(
+    vars: &[&str],
+    read: impl Fn(&str) -> Result<OsString>,
+    command: &str,
+    allow_cwd: bool,
+    plain: bool,
+    format: Format,
+) -> Result<()> {
+    // Variables that can't be read are reported after the others, as the error:
+    let mut failed: Vec<String> = Vec::new();
+    let paths: Vec<(&str, OsString)> = vars
+        .iter()
+        .filter_map(|&var| match read(var) {
+            Ok(path) => Some((var, path)),
+            Err(e) => {
+                failed.push(format!("{:#}", e));
+                None
+            }
+        })
+        .collect();
+    // As JSON, one object with the result for each variable:
+    if format == Format::Json {
+        let mut results = serde_json::Map::new();
+        for (var, path) in paths.iter() {
+            let result = match command {
+                "validate" => serde_json::to_value(validate(path, var, allow_cwd)?)?,
+                _ => serde_json::to_value(pathops::entries(
+                    &pathops::split(path),
+                    command == "count",
+                ))?,
+            };
+            results.insert(var.to_string(), result);
+        }
+        print_json(&results)?;
+    // Plain, every line starts with the variable instead of under a heading:
+    } else if plain {
+        for (var, path) in paths.iter() {
+            let lines: Vec<String> = match command {
+                "validate" => validate(path, var, allow_cwd)?
+                    .iter()
+                    .map(plain_issue)
+                    .collect(),
+                "count" => pathops::entries(&pathops::split(path), true)
+                    .iter()
+                    .map(plain_count)
+                    .collect(),
+                _ => pathops::split(path)
+                    .iter()
+                    .map(|p| redact(&p.to_string_lossy()))
+                    .collect(),
+            };
+            for line in lines {
+                println!("{}\t{}", var, line);
+            }
+        }
+    } else {
+        for (i, (var, path)) in paths.iter().enumerate() {
+            if i > 0 {
+                println!();
+            }
+            println!("{}", var.bold());
+            match command {
+                "validate" => print_issues(&validate(path, var, allow_cwd)?, false, format)?,
+                "count" => count_exes(path, false, format)?,
+                _ => list_paths(path, false, false, &[], format)?,
+            }
+        }
+    }
+    ensure!(failed.is_empty(), failed.join("\n"));
+
+    Ok(())
+}
+
+// List the help topics below the options
+fn topics_help() -> String {
+    let width = HELP_TOPICS
+        .iter()
+        .map(|(name, _, _)| name.len())
+        .max()
+        .unwrap_or(0);
+    let topics: Vec<String> = HELP_TOPICS
+        .iter()
+        .map(|(name, summary, _)| format!("  {:<width$}  {}", name, summary))
+        .collect();
+    format!(
+        "Topics, read with '{} help <TOPIC>':\n{}",
+        env!("CARGO_PKG_NAME"),
+        topics.join("\n")
+    )
+}
+
+fn cli() -> Command {
+    Command::new(env!("CARGO_PKG_NAME"))
+        .author(env!("CARGO_PKG_AUTHORS"))
+        .about(env!("CARGO_PKG_DESCRIPTION"))
+        .version(env!("CARGO_PKG_VERSION"))
+        .disable_help_subcommand(true)
+        .after_help(topics_help())
+        .arg(
+            arg!(--format <FORMAT> "Output format, modulefile and lua only for commands that modify PATH")
+                .value_parser(["text", "json", "modulefile", "lua"])
+                .default_value("text")
+                .global(true),
+        )
+        .arg(
+            arg!(--shell <SHELL> "Print a modified PATH as a statement to eval in this shell")
+                .value_parser(rcfile::SHELL_NAMES)
+                .conflicts_with("format")
+                .global(true),
+        )
+        .arg(
+            arg!(--var <NAME> "Work on another path-like variable, like MANPATH or PYTHONPATH")
+                .default_value("PATH")
+                .global(true),
+        )
+        .arg(
+            arg!(--"allow-cwd" "Allow entries for the current directory ('' or '.'), refused by default")
+                .global(true),
+        )
+        .arg(
+            arg!(--scope <SCOPE> "Use PATH from the environment, or as a fresh login gets it from system files only")
+                .value_parser(["user", "system"])
+                .default_value("user")
+                .global(true),
+        )
+        .arg(
+            arg!(--input <STRING> "Work on this path string instead of the one in the environment")
+                .value_parser(value_parser!(OsString))
+                .conflicts_with("scope")
+                .global(true),
+        )
+        .arg(
+            arg!(--stdin "Read the path string from stdin, as is, as NAME=value or one entry per line")
+                .conflicts_with_all(["scope", "input"])
+                .global(true),
+        )
+        .arg(
+            arg!(--"ignore-case" "Compare entries regardless of case, the default on Windows and macOS")
+                .global(true),
+        )
+        .arg(
+            arg!(--"case-sensitive" "Compare entries with case, the default elsewhere, for case-sensitive volumes")
+                .conflicts_with("ignore-case")
+                .global(true),
+        )
+        .arg(
+            arg!(--"skip-slow-drives" "Don't look at entries on network or removable drives, which take long when disconnected (Windows)")
+                .global(true),
+        )
+        .arg(
+            arg!(--"max-probe-ms" <MS> "Give up on an entry that takes longer than MS milliseconds to look at, reporting it as unchecked")
+                .value_parser(value_parser!(u64))
+                .global(true),
+        )
+        .arg(
+            arg!(--deadline <MS> "Stop looking at entries MS milliseconds after starting, reporting the rest as unchecked")
+                .value_parser(value_parser!(u64))
+                .global(true),
+        )
+        .arg(
+            arg!(--root <DIR> "Look at entries under DIR, like a mounted image or container, following links as they would be inside it. PATH is composed from its files unless given with --input or --stdin")
+                .value_parser(value_parser!(PathBuf))
+                .conflicts_with_all(["replay", "scope"])
+                .global(true),
+        )
+        .arg(
+            arg!(--expand "Expand ~ and $VARIABLES in entries, as in an rc file, and give them expanded")
+                .global(true),
+        )
+        .arg(
+            arg!(--"keep-raw" "With --expand, give entries as written while checking them expanded")
+                .requires("expand")
+                .global(true),
+        )
+        .arg(arg!(--"no-hyperlinks" "Don't make entries clickable in terminals that support it").global(true))
+        .arg(arg!(-q --quiet "Don't print warnings (JSON output still has them)").global(true))
+        .arg(
+            arg!(--plain "No colors, arrows, labels or alignment, just one line per entry or problem with tabs between fields")
+                .global(true),
+        )
+        .arg(
+            arg!(--deterministic "No colors, links, timings or columns sized to fit, for comparing output in tests")
+                .global(true),
+        )
+        .arg(
+            arg!(--record <FILE> "Save what this run sees of PATH and the file system to FILE")
+                .value_parser(value_parser!(PathBuf))
+                .global(true),
+        )
+        .arg(
+            arg!(--replay <FILE> "Run against what was saved with --record instead of this system")
+                .value_parser(value_parser!(PathBuf))
+                .conflicts_with("record")
+                .global(true),
+        )
+        .arg(
+            arg!(--"redact-home" "Replace the home directory, user and host names in all output")
+                .global(true),
+        )
+        .arg(
+            arg!(--redact <TEXT> "Replace TEXT in all output, can be given several times")
+                .action(ArgAction::Append)
+                .global(true),
+        )
+        .subcommand(
+            Command::new("list")
+                .about("List entries (default)")
+                .arg(arg!(-l --long "Show position, permissions, owner, executables, modification time and file system type of each entry"))
+                .arg(
+                    arg!(--"by-mount" "Group entries by mount point, with executables counted")
+                        .conflicts_with_all(["long", "resolve"]),
+                )
+                .arg(arg!(--icons "Mark entries with icons for missing, protected, cargo and managed ones"))
+                .arg(arg!(-r --resolve "Print entries fully resolved").conflicts_with("long"))
+                .arg(
+                    arg!(--"keep-missing" "Keep entries that can't be resolved as they are")
+                        .requires("resolve"),
+                )
+                .arg(arg!(-j --join "Print resolved entries as one path string").requires("resolve"))
+                .arg(arg!(--missing "Only list entries that aren't accessible directories"))
+                .arg(arg!(--empty "Only list entries without executables, or anything for other variables"))
+                .arg(arg!(--duplicates "Only list entries already in PATH earlier, as written or resolved"))
+                .arg(arg!(--ok "Only list entries that are neither missing, empty nor duplicates"))
+                .group(
+                    ArgGroup::new("filter")
+                        .args(["missing", "empty", "duplicates", "ok"])
+                        .multiple(true)
+                        .conflicts_with_all(["by-mount", "resolve"]),
+                )
+                .after_help(
+                    "Filters can be combined to list entries matching any of them:
+  pathaid list --plain --missing --duplicates",
+                ),
+        )
+        .subcommand(Command::new("validate").about("Validate all entries"))
+        .subcommand(
+            Command::new("audit")
+                .about("Find entries that let others decide what runs")
+                .arg(
+                    arg!(--denylist <FILE> "Also find executables by the names and SHA-256 hashes in FILE, one per line")
+                        .value_parser(value_parser!(PathBuf)),
+                )
+                .arg(
+                    arg!(--report <FORMAT> "Print the findings as a report for CI pipelines instead")
+                        .value_parser(["sarif", "junit"]),
+                )
+                .after_help(
+                    "Finds world- and group-writable entries, entries owned by someone other than you
+or root, relative entries like '.', entries under the temporary directory or in a
+git working copy and setuid programs in directories others can write. Each finding
+has a severity, warn or error, and a code that stays the same between versions:
+  cwd, cwd-hidden, relative, world-writable, setuid-writable, denied   error
+  group-writable, foreign-owner, repository                            warn
+  temp-dir                                     warn, error if it doesn't exist
+
+Examples:
+  pathaid --format json audit
+  pathaid audit --denylist forbidden.txt --report sarif > audit.sarif",
+                ),
+        )
+        .subcommand(
+            Command::new("doctor")
+                .about("Run validate, shadow and audit together and summarize what they find")
+                .arg(
+                    arg!(--"fail-on" <SEVERITY> "Exit with status 3 if anything this severe or worse is found")
+                        .value_parser(["warn", "error"])
+                        .default_value("error"),
+                )
+                .arg(arg!(--"compare-last" "Only report problems that are new or resolved since the last run with this option, failing only on new ones"))
+                .after_help(
+                    "Missing, orphaned, unexpanded, cwd, invisible and max-path entries and the errors
+of audit count as errors, other problems as warnings. Shadowed commands are only
+mentioned. Problems accepted with 'pathaid ack' are listed apart and never fail it.
+
+Examples, failing a CI job on warnings too, and only on new problems:
+  pathaid doctor --fail-on warn
+  pathaid doctor --compare-last",
+                ),
+        )
+        .subcommand(
+            Command::new("ack")
+                .about("Accept a problem doctor finds, so it is still listed but no longer fails doctor")
+                .arg_required_else_help(true)
+                .arg(arg!([ID] "id of the problem, shown by doctor after its code").required_unless_present("list"))
+                .arg(
+                    arg!(--reason <TEXT> "Why the problem is accepted, listed with it")
+                        .required_unless_present_any(["list", "remove"]),
+                )
+                .arg(arg!(--expires <DATE> "Count the problem again from this date on, like 2025-12-31 (UTC)"))
+                .arg(
+                    arg!(--remove "Take back the acknowledgment of the problem")
+                        .conflicts_with_all(["reason", "expires"]),
+                )
+                .arg(
+                    arg!(--list "List the acknowledged problems")
+                        .conflicts_with_all(["ID", "reason", "expires", "remove"]),
+                )
+                .after_help(
+                    "A problem keeps its id as long as it is of the same kind about the same entry.
+Acknowledgments are kept with snapshots in the data directory.
+
+Example:
+  pathaid ack 3f2a9c1e --reason \"legacy build box\" --expires 2025-12-31",
+                ),
+        )
+        .subcommand(
+            Command::new("why-slow")
+                .about("Tell which files add entries, which entries are slow to look at and what cleaning up would save")
+                .arg(
+                    arg!(--"threshold-ms" <MS> "Count an entry as slow if looking at it takes longer than this")
+                        .value_parser(value_parser!(u64))
+                        .default_value("50"),
+                )
+                .after_help(
+                    "Files are read, not run, like with --root: login.defs as login does,
+environment.d as systemd does, /etc/environment as pam_env does and the profiles
+as the shell does, then the files of this user for the shell in SHELL. A shell
+looks in every entry before it gives up on a command, so a slow or needless entry
+costs one failed lookup each time.",
+                ),
+        )
+        .subcommand(
+            Command::new("explain")
+                .about("Tell where entries come from")
+                .arg_required_else_help(true)
+                .arg(arg!(<ENTRY>... "entries to explain"))
+                .arg(arg!(--package "Ask the package managers around which package installed each entry")),
+        )
+        .subcommand(
+            Command::new("dedup")
+                .about("Remove any duplicate entries")
+                .arg(
+                    arg!(--keep <WHICH> "Keep the first of the same entries, so nothing changes what runs, or the last, when later additions are meant to win")
+                        .value_parser(["first", "last"])
+                        .default_value("first"),
+                )
+                .arg(arg!(--"prefer-resolved" "Give the entries kept fully resolved rather than as written"))
+                .arg(arg!(--write "Also keep the result in the startup file of the login shell")),
+        )
+        .subcommand(Command::new("count").about("Count executables"))
+        .subcommand(
+            Command::new("all")
+                .about("Run list, validate or count for several path-like variables")
+                .arg(
+                    arg!(--vars <VARS> "Comma-separated variable names")
+                        .default_value("PATH,MANPATH,LD_LIBRARY_PATH"),
+                )
+                .subcommand(Command::new("list").about("List entries (default)"))
+                .subcommand(Command::new("validate").about("Validate all entries"))
+                .subcommand(Command::new("count").about("Count executables")),
+        )
+        .subcommand(
+            Command::new("prefix")
+                .about("Add or remove the directories of an install prefix and print the result")
+                .subcommand_required(true)
+                .arg(
+                    arg!(--vars [VARS] "Comma-separated variables to update, all supported if none given")
+                        .default_value("PATH")
+                        .default_missing_value("PATH,MANPATH,PKG_CONFIG_PATH,LD_LIBRARY_PATH")
+                        .global(true),
+                )
+                .subcommand(
+                    Command::new("add")
+                        .about("Append <PREFIX>/bin, <PREFIX>/sbin and so on")
+                        .arg_required_else_help(true)
+                        .arg(arg!(<PREFIX> "install prefix")),
+                )
+                .subcommand(
+                    Command::new("remove")
+                        .about("Remove all directories belonging to <PREFIX>")
+                        .arg_required_else_help(true)
+                        .arg(arg!(<PREFIX> "install prefix")),
+                ),
+        )
+        .subcommand(
+            Command::new("env")
+                .about("Run a command with a modified PATH")
+                .arg_required_else_help(true)
+                .arg(
+                    arg!(--with <DIR> "Directory to put in front, can be given several times")
+                        .action(ArgAction::Append),
+                )
+                .arg(
+                    arg!(--without <GLOB> "Remove entries matching GLOB, can be given several times")
+                        .action(ArgAction::Append),
+                )
+                .arg(
+                    arg!(<COMMAND> "Command to run and its arguments, after --")
+                        .num_args(1..)
+                        .last(true),
+                )
+                .after_help(
+                    "Examples:
+  pathaid env --with ./node_modules/.bin -- eslint .
+  pathaid env --without '/opt/conda/*' -- python3 --version",
+                ),
+        )
+        .subcommand(
+            Command::new("query")
+                .about("List the entries matching an expression")
+                .arg_required_else_help(true)
+                .arg(arg!(<EXPR> "expression over the fields of each entry"))
+                .after_help(
+                    "Fields: index, path, resolved, status (cwd, missing, empty or ok), origin (module,
+stow, system or user), fs, duplicate and executables. Compare them with ==, !=, <,
+<=, >, >= or ~ (glob match), and combine with &&, || and !.
+
+Examples:
+  pathaid query 'status == missing && origin != system'
+  pathaid query 'fs ~ nfs* || executables > 500'
+  pathaid --format json query duplicate",
+                ),
+        )
+        .subcommand(
+            Command::new("diff")
+                .about("Show the entries added, removed and moved in another path string")
+                .arg_required_else_help(true)
+                .arg(arg!(<OTHER> "path string, file with one, - for stdin, or snapshot name"))
+                .after_help(
+                    "Examples:
+  pathaid diff before-conda
+  ssh host 'echo $PATH' | pathaid diff -",
+                ),
+        )
+        .subcommand(
+            Command::new("export")
+                .about("Write what every command name runs to a file, to compare machines over time")
+                .arg(
+                    arg!(--"resolution-map" <FILE> "Write each command and the executable it runs as JSON, - for stdout")
+                        .value_parser(value_parser!(PathBuf))
+                        .required(true),
+                )
+                .arg(arg!(--hash "Add the SHA-256 of each executable"))
+                .arg(arg!(--"probe-versions" "Run every executable found with --version and add the first line it prints"))
+                .after_help(
+                    "--probe-versions runs every program that a command name finds, except those in
+entries audit reports errors for, such as ones others can write to.
+
+Example:
+  pathaid export --resolution-map $(hostname).json --hash",
+                ),
+        )
+        .subcommand(
+            Command::new("shadow")
+                .about("List commands found in several entries, and which of them runs"),
+        )
+        .subcommand(
+            Command::new("which")
+                .about("Find the executable a command name runs")
+                .arg_required_else_help(true)
+                .arg(arg!(<NAME> "command name"))
+                .arg(arg!(-a --all "List every match with the position of its entry")),
+        )
+        .subcommand(
+            Command::new("prune")
+                .about("Remove entries that don't exist or have no executables and print the result")
+                .arg(arg!(--"keep-empty" "Keep directories without executables"))
+                .arg(arg!(-n --"dry-run" "Only tell what would be removed, and print PATH unchanged"))
+                .after_help(
+                    "Examples:
+  pathaid prune --dry-run
+  export PATH=$(pathaid prune)",
+                ),
+        )
+        .subcommand(
+            Command::new("clean")
+                .about("Strip invisible characters and spaces at the ends of entries and print the result")
+                .after_help(
+                    "Removes zero-width spaces, byte order marks, direction marks and control characters,
+turns non-breaking spaces into plain ones and trims the ends of each entry.
+
+Example:
+  export PATH=$(pathaid clean)",
+                ),
+        )
+        .subcommand(
+            Command::new("normalize")
+                .about("Write entries the same way, without trailing or repeated separators, and print the result")
+                .arg(arg!(--absolute "Also make relative entries absolute from the current directory"))
+                .after_help(
+                    "Entries are compared this way everywhere, so /usr/bin/ and /usr/bin are the same
+entry to validate and dedup. '..' is kept, as it isn't the same directory once
+links are followed, and so are entries for the current directory, even with
+--absolute.
+
+Example:
+  export PATH=$(pathaid normalize)",
+                ),
+        )
+        .subcommand(
+            Command::new("remove")
+                .about("Remove directories from PATH and print the result")
+                .arg_required_else_help(true)
+                .arg(arg!(<PATH>... "directories to remove, as written, resolved or as #<position>"))
+                .arg(arg!(--all "Remove every match instead of only the first"))
+                .after_help(
+                    "Examples:
+  pathaid remove ~/old/bin         remove the first entry for ~/old/bin
+  pathaid remove --all /usr/bin    remove every copy of /usr/bin
+  pathaid remove '#3'              remove the entry at position 3, as validate and which count",
+                ),
+        )
+        .subcommand(
+            Command::new("append")
+                .about("Add directories to end of PATH, in the order given, and print the result")
+                .arg_required_else_help(true)
+                .arg(arg!(<PATH>... "directories to add, each may hold several separated like in PATH"))
+                .arg(arg!(--module "Print a 'module load' instead if a module provides the directory"))
+                .arg(arg!(--"keep-relative" "Add a relative directory as it is instead of making it absolute"))
+                .arg(arg!(--promote "Move the directory if PATH already has it"))
+                .arg(arg!(--create "Create the directory if it doesn't exist"))
+                .arg(arg!(--force "Add the directory even if it doesn't exist"))
+                .arg(arg!(--write "Also keep the result in the startup file of the login shell").conflicts_with("module"))
+                .after_help(
+                    "Examples:
+  export PATH=$(pathaid append ~/.local/bin ~/go/bin)   ~/go/bin ends up last
+  pathaid append --promote /usr/local/bin     move it to the end if already there
+  pathaid --format modulefile append /opt/tool/bin
+  eval \"$(pathaid append --shell bash ~/bin)\"       quoted for the shell, set in place",
+                ),
+        )
+        .subcommand(
+            Command::new("prepend")
+                .about("Add directories to front of PATH, in the order given, and print the result")
+                .arg_required_else_help(true)
+                .arg(arg!(<PATH>... "directories to add, each may hold several separated like in PATH"))
+                .arg(arg!(--module "Print a 'module load' instead if a module provides the directory"))
+                .arg(arg!(--"keep-relative" "Add a relative directory as it is instead of making it absolute"))
+                .arg(arg!(--promote "Move the directory if PATH already has it"))
+                .arg(arg!(--create "Create the directory if it doesn't exist"))
+                .arg(arg!(--force "Add the directory even if it doesn't exist"))
+                .arg(arg!(--write "Also keep the result in the startup file of the login shell").conflicts_with("module"))
+                .after_help(
+                    "Examples:
+  export PATH=$(pathaid prepend ~/bin ~/.cargo/bin)   ~/bin ends up first
+  pathaid prepend --create ~/.local/bin
+  pathaid prepend --write ~/bin       also set it in ~/.bashrc, ~/.zshrc, config.fish or ~/.profile",
+                ),
+        )
+        .subcommand(
+            Command::new("insert")
+                .about("Add directories at a position in PATH, in the order given, and print the result")
+                .arg_required_else_help(true)
+                .arg(arg!(<PATH>... "directories to add, each may hold several separated like in PATH"))
+                .arg(
+                    arg!(--at <INDEX> "Position the first directory ends up at, counting from 0")
+                        .value_parser(value_parser!(usize)),
+                )
+                .arg(arg!(--before <ENTRY> "Add in front of this entry, as written, as #<position> or resolved"))
+                .arg(arg!(--after <ENTRY> "Add right after this entry, as written, as #<position> or resolved"))
+                .group(ArgGroup::new("position").args(["at", "before", "after"]).required(true))
+                .arg(arg!(--"keep-relative" "Add a relative directory as it is instead of making it absolute"))
+                .arg(arg!(--promote "Move the directory if PATH already has it"))
+                .arg(arg!(--create "Create the directory if it doesn't exist"))
+                .arg(arg!(--force "Add the directory even if it doesn't exist"))
+                .arg(arg!(--write "Also keep the result in the startup file of the login shell"))
+                .after_help(
+                    "Examples:
+  export PATH=$(pathaid insert ~/.local/bin --after /usr/bin)
+  pathaid insert --promote /opt/tool/bin --before /opt/homebrew/bin",
+                ),
+        )
+        .subcommand(
+            Command::new("move")
+                .about("Move an entry to another position in PATH and print the result")
+                .arg_required_else_help(true)
+                .arg(arg!(<ENTRY> "entry to move, as written, as #<position> or resolved"))
+                .arg(arg!(--"to-front" "Move it first"))
+                .arg(arg!(--"to-back" "Move it last"))
+                .arg(
+                    arg!(--to <INDEX> "Position it ends up at, counting from 0")
+                        .value_parser(value_parser!(usize)),
+                )
+                .arg(arg!(--before <OTHER> "Move it in front of another entry"))
+                .arg(arg!(--after <OTHER> "Move it right after another entry"))
+                .group(
+                    ArgGroup::new("position")
+                        .args(["to-front", "to-back", "to", "before", "after"])
+                        .required(true),
+                )
+                .after_help(
+                    "Examples:
+  export PATH=$(pathaid move /opt/homebrew/bin --to-front)
+  pathaid move '#7' --after /usr/bin",
+                ),
+        )
+        .subcommand(
+            Command::new("edit")
+                .about("Reorder, remove and disable entries in the terminal and print the result")
+                .arg(arg!(--tui "Edit in a full-screen list, with the status of each entry").required(true))
+                .after_help(
+                    "Example:
+  export PATH=$(pathaid edit --tui)",
+                ),
+        )
+        .subcommand(
+            Command::new("top")
+                .about("Show the entries with their status, refreshed as they change, like htop"),
+        )
+        .subcommand(
+            Command::new("lint-script")
+                .about("Check the changes to PATH in a shell script without running it")
+                .arg_required_else_help(true)
+                .arg(arg!(<FILE> "script to check").value_parser(value_parser!(PathBuf)))
+                .after_help(
+                    "Finds PATH set without $PATH, directories added twice, empty entries,
+unquoted expansions in export and prepending inside loops.
+
+Example:
+  pathaid lint-script ~/.bashrc",
+                ),
+        )
+        .subcommand(
+            Command::new("lint-ci")
+                .about("Check the changes to PATH in GitHub Actions workflows or GitLab CI configs")
+                .arg_required_else_help(true)
+                .arg(arg!(<FILE>... "workflows or configs to check").value_parser(value_parser!(PathBuf)))
+                .after_help(
+                    "Checks the scripts of each job like lint-script, and also finds PATH set in
+env: or variables:, exported in a GitHub step that later steps won't see, written
+to $GITHUB_ENV, and directories added in several jobs.
+
+Example:
+  pathaid lint-ci .github/workflows/*.yml",
+                ),
+        )
+        .subcommand(
+            Command::new("lint-build")
+                .about("Check a Makefile or justfile for recipes changing PATH or using undeclared tools")
+                .arg_required_else_help(true)
+                .arg(arg!(<FILE> "Makefile or justfile to check").value_parser(value_parser!(PathBuf)))
+                .arg(arg!(--requires <TOOLS> "Comma-separated tools the repo declares, anything else recipes run is reported"))
+                .after_help(
+                    "Checks recipes like lint-script, and also finds PATH set without the PATH the build
+started with and PATH changed at the end of a recipe line, which the next line
+won't see. With --requires, tools that are neither declared nor shell builtins or
+POSIX utilities are reported at their first use.
+
+Example:
+  pathaid lint-build Makefile --requires go,protoc,docker",
+                ),
+        )
+        .subcommand(
+            Command::new("stats")
+                .about("Show how pathaid itself is used, from a local file only")
+                .arg(arg!(--self "Show the commands run and how long they took").required_unless_present_any(["enable", "disable"]))
+                .arg(arg!(--enable "Start keeping stats"))
+                .arg(arg!(--disable "Stop keeping stats and remove them").conflicts_with("enable")),
+        )
+        .subcommand(
+            Command::new("history")
+                .about("Show when entries were added, went missing or changed, as seen by validate")
+                .arg(arg!([ENTRY] "entry to show the whole history of"))
+                .arg(arg!(--enable "Start keeping history, in a local file only"))
+                .arg(arg!(--disable "Stop keeping history and remove it").conflicts_with("enable")),
+        )
+        .subcommand(
+            Command::new("snapshot")
+                .about("Save PATH under a name, to get it back after it got mangled")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("save")
+                        .about("Save the path string, replacing a snapshot with the same name")
+                        .arg_required_else_help(true)
+                        .arg(arg!(<NAME> "name of the snapshot")),
+                )
+                .subcommand(
+                    Command::new("restore")
+                        .about("Print the saved path string, or with --shell a statement to eval")
+                        .arg_required_else_help(true)
+                        .arg(arg!(<NAME> "name of the snapshot")),
+                )
+                .subcommand(Command::new("list").about("List the saved snapshots"))
+                .after_help(
+                    "Example:
+  pathaid snapshot save before-conda
+  eval \"$(pathaid snapshot restore before-conda --shell bash)\"",
+                ),
+        )
+        .subcommand(
+            Command::new("daemon")
+                .about("Keep entries scanned and answer count and shadow (and editors) over a local socket"),
+        )
+        .subcommand(
+            Command::new("serve")
+                .about("Answer list, validate, which and simulate as JSON-RPC, for editors and linters")
+                .arg(arg!(--stdio "Read requests from standard input, one per line").required(true))
+                .after_help(
+                    "Example:
+  echo '{\"jsonrpc\": \"2.0\", \"id\": 1, \"method\": \"which\", \"params\": {\"name\": \"ls\"}}' | pathaid serve --stdio",
+                ),
+        )
+        .subcommand(
+            Command::new("gc")
+                .about("Remove files left over in the state directory")
+                .arg(arg!(-n --"dry-run" "Only show what would be removed")),
+        )
+        .subcommand(
+            Command::new("setup")
+                .about("Detect the shells in use and offer to install completions for them")
+                .arg(arg!(-y --yes "Install everything without asking")),
+        )
+        .subcommand(
+            Command::new("completions")
+                .about("Print a shell completion script, or install it where the shell looks")
+                .arg_required_else_help(true)
+                .arg(arg!(<SHELL> "shell to complete for").value_parser(value_parser!(Shell)))
+                .arg(arg!(--install "Write the script to the shell's completion directory")),
+        )
+        .subcommand(
+            Command::new("help")
+                .about("Print the help of a command, or read about a topic")
+                .arg(arg!([TOPIC]... "command or topic")),
+        )
+}
+
+/// Run pathaid with the arguments of this process, as the binary does
+pub fn main() -> Result<()> {
+    let matches = cli().get_matches();
+    let mut redactions: Vec<(String, String)> = Vec::new();
+    if matches.get_flag("redact-home") {
+        redactions.extend(identity_redactions());
+    }
+    if let Some(texts) = matches.get_many::<String>("redact") {
+        redactions.extend(texts.map(|t| (t.clone(), String::from("<redacted>"))));
+    }
+    // Links would give away what is redacted from the text:
+    let deterministic = matches.get_flag("deterministic");
+    let hyperlinks = !matches.get_flag("no-hyperlinks")
+        && !matches.get_flag("plain")
+        && matches.get_one::<String>("format").map(String::as_str) != Some("json")
+        && matches.subcommand_name() != Some("serve")
+        && !deterministic
+        && redactions.is_empty()
+        && supports_hyperlinks();
+    REDACTIONS.set(redactions).unwrap();
+    QUIET.set(matches.get_flag("quiet")).unwrap();
+    HYPERLINKS.set(hyperlinks).unwrap();
+    DETERMINISTIC.set(deterministic).unwrap();
+    VAR.set(matches.get_one::<String>("var").unwrap().clone())
+        .unwrap();
+
+    if matches.get_flag("ignore-case") {
+        pathops::set_ignore_case(true);
+    } else if matches.get_flag("case-sensitive") {
+        pathops::set_ignore_case(false);
+    }
+    if matches.get_flag("skip-slow-drives") {
+        pathops::skip_slow_drives();
+    }
+    if matches.get_flag("expand") {
+        pathops::set_expand(true);
+    }
+    let ms = |id| {
+        matches
+            .get_one::<u64>(id)
+            .map(|ms| Duration::from_millis(*ms))
+    };
+    pathops::set_probe_budget(
+        ms("max-probe-ms"),
+        ms("deadline").map(|d| Instant::now() + d),
+    );
+
+    if let Some(root) = matches.get_one::<PathBuf>("root") {
+        pathops::set_root(root)?;
+    }
+
+    if let Some(file) = matches.get_one::<PathBuf>("replay") {
+        fixture::replay(file)?;
+    } else if matches.contains_id("record") {
+        fixture::record();
+    }
+
+    let start = Instant::now();
+    let result = run(&matches);
+    // Anything given up on is reported, validate and doctor already list it:
+    let reported = matches!(matches.subcommand_name(), Some("validate" | "doctor"));
+    let skipped: Vec<String> = pathops::skipped_entries()
+        .into_iter()
+        .map(|(p, skipped)| {
+            format!(
+                "'{}' wasn't checked, {}",
+                redact(&p.to_string_lossy()),
+                skipped.reason()
+            )
+        })
+        .collect();
+    if !reported {
+        print_warnings(&skipped);
+    }
+    // Failed runs are the ones most worth recording:
+    if let Some(file) = matches.get_one::<PathBuf>("record") {
+        fixture::save(file)?;
+    }
+    state::record_run(matches.subcommand_name().unwrap_or("list"), start.elapsed());
+    result.map_err(|e| {
+        let hint = e
+            .downcast_ref::<pathops::Error>()
+            .and_then(pathops::Error::hint)
+            .map(|h| format!("\nhint: {}", h));
+        anyhow!(redact(&format!("{:#}{}", e, hint.unwrap_or_default())))
+    })
+}
+
+// Longer explanations than fit in the help of a single command, as name, summary
+// and a function writing the text so it can use the same constants as the code
+type HelpTopic = (&'static str, &'static str, fn() -> String);
+
+const HELP_TOPICS: &[HelpTopic] = &[
+    (
+        "ordering",
+        "How the order of entries decides what runs",
+        help_ordering,
+    ),
+    (
+        "security",
+        "Which entries are refused or reported and why",
+        help_security,
+    ),
+    (
+        "exit-codes",
+        "What the exit status of each command means",
+        help_exit_codes,
+    ),
+];
+
+// Exit status, and when it is given
+#[derive(Clone, Copy)]
+enum Exit {
+    Ok = 0,
+    // Any error returned from run()
+    Failed = 1,
+    // Given by clap
+    Usage = 2,
+    Problems = 3,
+}
+
+impl Exit {
+    const ALL: [Exit; 4] = [Exit::Ok, Exit::Failed, Exit::Usage, Exit::Problems];
+
+    fn code(self) -> i32 {
+        self as i32
+    }
+
+    fn meaning(self) -> &'static str {
+        match self {
+            Exit::Ok => "the command ran, also when validate reports problems",
+            Exit::Failed => "the command failed, the message tells why and often how to fix it",
+            Exit::Usage => "the command line couldn't be parsed",
+            Exit::Problems => "doctor found problems at or above the severity given with --fail-on",
+        }
+    }
+}
+
+// Rows of cells, indented and with all but the last column padded to line up
+fn table(rows: &[Vec<&str>]) -> String {
+    let mut widths: Vec<usize> = Vec::new();
+    for row in rows.iter() {
+        for (i, cell) in row.iter().enumerate().take(row.len() - 1) {
+            match widths.get_mut(i) {
+                Some(w) => *w = (*w).max(cell.len()),
+                None => widths.push(cell.len()),
+            }
+        }
+    }
+    rows.iter()
+        .map(|row| {
+            let mut line = String::new();
+            for (i, cell) in row.iter().enumerate() {
+                match i + 1 == row.len() {
+                    true => line.push_str(&format!("  {}", cell)),
+                    false => line.push_str(&format!("  {:<w$}", cell, w = widths[i])),
+                }
+            }
+            line
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+// The issue codes validate reports under a help topic
+fn topic_codes(topic: &str) -> String {
+    let rows: Vec<Vec<&str>> = ISSUE_CODES
+        .iter()
+        .filter(|c| c.topic == Some(topic))
+        .map(|c| vec![c.code, c.severity.label(), c.summary])
+        .collect();
+    table(&rows)
+}
+
+fn help_ordering() -> String {
+    format!(
+        "A command is looked up in each entry from first to last, and the first match is run.
+Anything in an earlier entry shadows commands of the same name further back.
+
+  append   adds entries last, so they lose against everything already there
+  prepend  adds entries first, in the order given, so they win
+  dedup    keeps the first copy of each entry, which is the one that was in effect
+
+An entry that is already in PATH is refused by append and prepend, as adding it
+again wouldn't change anything. Use --promote to move it instead.
+
+validate and doctor point out entries whose position changes the outcome:
+
+{}",
+        topic_codes("ordering")
+    )
+}
+
+fn help_security() -> String {
+    format!(
+        "Entries for the current directory ('' or '.') are refused by default, as {}.
+Use --allow-cwd to accept them anyway.
+
+Relative directories are made absolute when added, unless --keep-relative is given,
+since they would otherwise be looked up from wherever a command is run.
+
+To share output without giving away who you are, use --redact-home to replace the
+home directory, user and host names, and --redact for anything else.
+
+Stats are only kept after 'stats --self --enable', in a local file only.
+
+validate and doctor report:
+
+{}
+
+audit looks for entries that let someone else decide what runs, and doctor runs
+it too. Give 'audit --denylist FILE' a file of command names and SHA-256 hashes,
+one per line, to also find executables that mustn't be reachable. Findings are:
+
+{}",
+        pathops::CWD_RATIONALE,
+        topic_codes("security"),
+        table(
+            &audit::RULES
+                .iter()
+                .map(|r| vec![r.code, r.severity.label(), r.summary])
+                .collect::<Vec<_>>()
+        )
+    )
+}
+
+fn help_exit_codes() -> String {
+    let codes: Vec<String> = Exit::ALL.iter().map(|e| e.code().to_string()).collect();
+    let mut rows: Vec<Vec<&str>> = Exit::ALL
+        .iter()
+        .zip(codes.iter())
+        .map(|(e, code)| vec![code.as_str(), e.meaning()])
+        .collect();
+    rows.push(vec![
+        "other",
+        "env exits with the status of the command it ran",
+    ]);
+    table(&rows)
+}
+
+// Print the help of a (sub)command, or one of the topics
+fn help(words: &[String]) -> Result<()> {
+    let Some(first) = words.first() else {
+        cli().print_help()?;
+        return Ok(());
+    };
+    if let Some((_, _, text)) = HELP_TOPICS.iter().find(|(name, _, _)| name == first) {
+        println!("{}", text());
+        return Ok(());
+    }
+    let mut command = cli();
+    for word in words.iter() {
+        command = match command.find_subcommand(word) {
+            Some(sub) => sub.clone(),
+            None => {
+                let topics: Vec<&str> = HELP_TOPICS.iter().map(|(name, _, _)| *name).collect();
+                bail!(
+                    "'{}' is neither a command nor a topic, the topics are {}",
+                    words.join(" "),
+                    topics.join(", ")
+                )
+            }
+        };
+    }
+    command
+        .bin_name(format!("{} {}", env!("CARGO_PKG_NAME"), words.join(" ")))
+        .print_help()?;
+
+    Ok(())
+}
+
+// Where each shell picks up completions for a user without any configuration,
+// except for zsh and PowerShell which need to be pointed at the script
+fn completion_file(shell: Shell, home: &Path) -> Result<PathBuf> {
+    let name = env!("CARGO_PKG_NAME");
+    let xdg = |var, default| match env::var_os(var).filter(|d| !d.is_empty()) {
+        Some(dir) => PathBuf::from(dir),
+        None => home.join(default),
+    };
+    Ok(match shell {
+        Shell::Bash => xdg("XDG_DATA_HOME", ".local/share")
+            .join("bash-completion/completions")
+            .join(name),
+        Shell::Zsh => home.join(".zfunc").join(format!("_{}", name)),
+        Shell::Fish => xdg("XDG_CONFIG_HOME", ".config")
+            .join("fish/completions")
+            .join(format!("{}.fish", name)),
+        Shell::PowerShell => {
+            rcfile::powershell_profile(home).with_file_name(format!("{}.completions.ps1", name))
+        }
+        _ => bail!("don't know where {} looks for completions", shell),
+    })
+}
+
+// Keep a new path in a managed block of the startup file of the login shell,
+// copying the file aside first as anything else in it is the user's own
+fn write_rc(path: &OsStr) -> Result<()> {
+    let path = pathops::into_string(path.to_os_string())?;
+    let home = state::home_dir()?;
+    let shell = env::var_os("SHELL").and_then(Shell::from_shell_path);
+    let file = rcfile::rc_file(shell, &home);
+    // Dotfile managers link the file to their own copy, which is what to change:
+    let file = fs::canonicalize(&file).unwrap_or(file);
+    let _lock = state::lock()?;
+    let contents = match fs::read_to_string(&file) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e).with_context(|| format!("unable to read '{}'", file.display())),
+    };
+    let line = rcfile::path_line(shell, var_name(), &path, &home);
+    let updated = rcfile::update_block(&contents, &line);
+    let quiet = QUIET.get() == Some(&true);
+    if updated == contents {
+        if !quiet {
+            eprintln!("{} already sets this {}", fmt_path(&file, 0), var_name());
+        }
+        return Ok(());
+    }
+    if file.exists() {
+        let mut backup = file.clone().into_os_string();
+        backup.push(".pathaid.bak");
+        fs::copy(&file, &backup)
+            .with_context(|| format!("unable to back up '{}'", file.display()))?;
+    } else if let Some(dir) = file.parent() {
+        fs::create_dir_all(dir).with_context(|| format!("unable to create '{}'", dir.display()))?;
+    }
+    state::write_atomic(&file, updated)?;
+    if !quiet {
+        eprintln!(
+            "wrote {} to {}, new sessions will use it",
+            var_name(),
+            fmt_path(&file, 0)
+        );
+    }
+    Ok(())
+}
+
+// Write the completion script and report where it went and what else is needed
+fn install_completions(shell: Shell) -> Result<()> {
+    let home = state::home_dir()?;
+    let file = completion_file(shell, &home)?;
+    let _lock = state::lock()?;
+    let mut script: Vec<u8> = Vec::new();
+    clap_complete::generate(shell, &mut cli(), env!("CARGO_PKG_NAME"), &mut script);
+    if let Some(dir) = file.parent() {
+        fs::create_dir_all(dir).with_context(|| format!("unable to create '{}'", dir.display()))?;
+    }
+    state::write_atomic(&file, script)?;
+    println!("wrote {}", fmt_path(&file, 0));
+    match shell {
+        Shell::Zsh => println!(
+            "add 'fpath=(~/.zfunc $fpath)' before 'compinit' in ~/.zshrc if it isn't there"
+        ),
+        Shell::PowerShell => {
+            // The profile has to source the script, add that once:
+            let profile = rcfile::powershell_profile(&home);
+            let line = format!(". \"{}\"", file.display());
+            let contents = fs::read_to_string(&profile).unwrap_or_default();
+            if !contents.lines().any(|l| l.trim() == line) {
+                let mut contents = contents;
+                if !contents.is_empty() && !contents.ends_with('\n') {
+                    contents.push('\n');
+                }
+                contents.push_str(&line);
+                contents.push('\n');
+                state::write_atomic(&profile, contents)?;
+                println!("added the script to {}", fmt_path(&profile, 0));
+            }
+        }
+        _ => {}
+    }
+    println!("completions are available in new {} sessions", shell);
+
+    Ok(())
+}
+
+// Ask a yes or no question, where anything but yes is no
+fn confirm(question: &str) -> Result<bool> {
+    print!("{} [y/N] ", question);
+    io::stdout().flush()?;
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
+// Find the shells in use: the login shell first and then any other installed
+fn detect_shells(path: &OsStr) -> Vec<Shell> {
+    let mut shells: Vec<Shell> = Vec::new();
+    if let Some(shell) = env::var_os("SHELL").and_then(Shell::from_shell_path) {
+        shells.push(shell);
+    }
+    for (name, shell) in [
+        ("bash", Shell::Bash),
+        ("zsh", Shell::Zsh),
+        ("fish", Shell::Fish),
+        ("pwsh", Shell::PowerShell),
+    ] {
+        if !shells.contains(&shell) && pathops::find_executable(path, name).is_some() {
+            shells.push(shell);
+        }
+    }
+    shells
+}
+
+// Walk through installing what is worth having for each shell found
+fn setup(path: &OsStr, yes: bool) -> Result<()> {
+    ensure!(
+        yes || io::stdin().is_terminal(),
+        "setup asks questions, use --yes to run it without a terminal"
+    );
+    let shells = detect_shells(path);
+    ensure!(!shells.is_empty(), "no supported shell found");
+    let names: Vec<String> = shells.iter().map(Shell::to_string).collect();
+    println!("found {}", names.join(", "));
+    for shell in shells {
+        let question = format!("install completions for {}?", shell);
+        if yes || confirm(&question)? {
+            install_completions(shell)?;
+        }
+    }
+
+    Ok(())
+}
+
+// Show how often each command has been run and how long it took
+fn self_stats(format: Format) -> Result<()> {
+    let Some(stats) = state::read_stats()? else {
+        println!("stats are off, turn them on with 'stats --self --enable'");
+        return Ok(());
+    };
+    // Timings and the time stats were turned on differ from run to run:
+    let deterministic = DETERMINISTIC.get() == Some(&true);
+    if format == Format::Json {
+        let stats = match deterministic {
+            true => {
+                let runs: serde_json::Map<String, serde_json::Value> = stats
+                    .commands
+                    .iter()
+                    .map(|(command, c)| (command.clone(), serde_json::json!({ "runs": c.runs })))
+                    .collect();
+                serde_json::json!({ "commands": runs })
+            }
+            false => serde_json::to_value(&stats)?,
+        };
+        println!("{}", serde_json::to_string_pretty(&stats)?);
+        return Ok(());
+    }
+    let width = column_width(
+        stats.commands.keys().map(String::len).max().unwrap_or(0),
+        12,
+    );
+    for (command, c) in stats.commands.iter() {
+        if deterministic {
+            println!("{:<width$}  {} runs", command, c.runs);
+            continue;
+        }
+        let avg = c.total_ms / c.runs.max(1);
+        println!(
+            "{:<width$}  {} runs, {} ms on average, {} ms at most",
+            command, c.runs, avg, c.max_ms
+        );
+    }
+
+    Ok(())
+}
+
+fn fmt_severity(severity: audit::Severity) -> ColoredString {
+    match severity {
+        audit::Severity::Error => severity.label().red().bold(),
+        audit::Severity::Warn => severity.label().yellow().bold(),
+        audit::Severity::Info => severity.label().dimmed(),
+    }
+}
+
+// Print security findings as 'severity: message [code]', or as JSON
+// Where a finding is, as a URL for SARIF
+fn finding_url(f: &audit::Finding) -> String {
+    let p = f.file.as_ref().unwrap_or(&f.path);
+    file_url(&pathops::absolute(p).unwrap_or_else(|_| p.to_path_buf()))
+}
+
+// Static analysis results format 2.1.0, as code scanning dashboards take them
+fn sarif(findings: &[audit::Finding]) -> serde_json::Value {
+    let mut codes: Vec<&str> = findings.iter().map(|f| f.code).collect();
+    codes.sort();
+    codes.dedup();
+    let results: Vec<serde_json::Value> = findings
+        .iter()
+        .map(|f| {
+            serde_json::json!({
+                "ruleId": f.code,
+                "level": match f.severity {
+                    audit::Severity::Error => "error",
+                    audit::Severity::Warn => "warning",
+                    audit::Severity::Info => "note",
+                },
+                "message": { "text": f.message },
+                "locations": [{
+                    "physicalLocation": { "artifactLocation": { "uri": finding_url(f) } }
+                }],
+                "properties": { "index": f.index },
+            })
+        })
+        .collect();
+    serde_json::json!({
+        "version": "2.1.0",
+        "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": env!("CARGO_PKG_NAME"),
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "rules": codes.iter().map(|c| serde_json::json!({ "id": c })).collect::<Vec<_>>(),
+                }
+            },
+            "results": results,
+        }]
+    })
+}
+
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+// One test case per entry, failing with the findings about it
+fn junit(paths: &[PathBuf], findings: &[audit::Finding]) -> String {
+    let failed: HashSet<usize> = findings.iter().map(|f| f.index).collect();
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str(&format!(
+        "<testsuites name=\"{}\" tests=\"{}\" failures=\"{}\">\n",
+        env!("CARGO_PKG_NAME"),
+        paths.len(),
+        failed.len()
+    ));
+    xml.push_str(&format!(
+        "  <testsuite name=\"audit\" tests=\"{}\" failures=\"{}\">\n",
+        paths.len(),
+        failed.len()
+    ));
+    for (i, p) in paths.iter().enumerate() {
+        let name = xml_escape(&format!("{} {}", i, p.display()));
+        let about: Vec<&audit::Finding> = findings.iter().filter(|f| f.index == i).collect();
+        let Some(first) = about.first() else {
+            xml.push_str(&format!(
+                "    <testcase classname=\"audit\" name=\"{}\"/>\n",
+                name
+            ));
+            continue;
+        };
+        let details: Vec<String> = about
+            .iter()
+            .map(|f| format!("{}: {} [{}]", f.severity.label(), f.message, f.code))
+            .collect();
+        xml.push_str(&format!(
+            "    <testcase classname=\"audit\" name=\"{}\">\n      <failure type=\"{}\" message=\"{}\">{}</failure>\n    </testcase>\n",
+            name,
+            first.code,
+            xml_escape(&first.message),
+            xml_escape(&details.join("\n"))
+        ));
+    }
+    xml.push_str("  </testsuite>\n</testsuites>");
+    xml
+}
+
+fn audit(
+    path: &OsStr,
+    denylist: Option<&Path>,
+    report: Option<&str>,
+    format: Format,
+) -> Result<()> {
+    let paths = pathops::split_entries(path);
+    let mut findings = audit::audit(&paths);
+    if let Some(file) = denylist {
+        let contents = fs::read_to_string(file)
+            .with_context(|| format!("unable to read '{}'", file.display()))?;
+        let denylist = audit::Denylist::parse(&contents);
+        ensure!(
+            !denylist.is_empty(),
+            "'{}' has no names or hashes to look for",
+            file.display()
+        );
+        findings.extend(audit::deny(&paths, &denylist));
+    }
+    match report {
+        Some("sarif") => return print_json(&sarif(&findings)),
+        Some("junit") => {
+            println!("{}", redact(&junit(&paths, &findings)));
+            return Ok(());
+        }
+        _ => {}
+    }
+    if format == Format::Json {
+        return print_json(&findings);
+    }
+    for f in findings.iter() {
+        println!(
+            "{}: {} [{}]",
+            fmt_severity(f.severity),
+            redact(&f.message),
+            f.code
+        );
+    }
+    Ok(())
+}
+
+// A kind of issue validate reports: errors break lookups rather than just slow
+// them down, and the topic is the help topic listing it
+struct IssueCode {
+    code: &'static str,
+    severity: audit::Severity,
+    topic: Option<&'static str>,
+    summary: &'static str,
+}
+
+const fn issue_code(
+    code: &'static str,
+    severity: audit::Severity,
+    topic: Option<&'static str>,
+    summary: &'static str,
+) -> IssueCode {
+    IssueCode {
+        code,
+        severity,
+        topic,
+        summary,
+    }
+}
+
+const ERROR: audit::Severity = audit::Severity::Error;
+const WARN: audit::Severity = audit::Severity::Warn;
+const ORDERING: Option<&str> = Some("ordering");
+const SECURITY: Option<&str> = Some("security");
+
+const ISSUE_CODES: [IssueCode; 31] = [
+    issue_code(
+        "cwd",
+        ERROR,
+        SECURITY,
+        "an entry for the current directory ('' or '.')",
+    ),
+    issue_code(
+        "invisible",
+        ERROR,
+        SECURITY,
+        "invisible characters or spaces, in an entry that looks like another",
+    ),
+    issue_code(
+        "unexpanded",
+        ERROR,
+        SECURITY,
+        "a ~ or $NAME written as is, a relative directory of that name",
+    ),
+    issue_code("missing", ERROR, None, "not an accessible directory"),
+    issue_code(
+        "orphaned",
+        ERROR,
+        None,
+        "left over from a package that is no longer installed",
+    ),
+    issue_code(
+        "drive-unavailable",
+        ERROR,
+        None,
+        "on a drive that is disconnected or has no media",
+    ),
+    issue_code(
+        "max-path",
+        ERROR,
+        None,
+        "executables with paths longer than MAX_PATH",
+    ),
+    issue_code(
+        "max-path-margin",
+        WARN,
+        None,
+        "little room left for file names within MAX_PATH",
+    ),
+    issue_code(
+        "unchecked",
+        WARN,
+        None,
+        "given up on for --max-probe-ms or --deadline",
+    ),
+    issue_code(
+        "empty",
+        WARN,
+        None,
+        "no executables, or nothing at all for other variables",
+    ),
+    issue_code("read-only", WARN, None, "on a read-only mount"),
+    issue_code("immutable", WARN, None, "marked immutable"),
+    issue_code(
+        "case-collision",
+        WARN,
+        None,
+        "executables only differing by case",
+    ),
+    issue_code(
+        "unicode-normalization",
+        WARN,
+        None,
+        "written in another Unicode form than an entry that looks the same",
+    ),
+    issue_code(
+        "same-directory",
+        WARN,
+        None,
+        "the same directory as another entry, through a link or bind mount",
+    ),
+    issue_code(
+        "duplicate",
+        WARN,
+        ORDERING,
+        "included more than once, only the first copy has any effect",
+    ),
+    issue_code(
+        "duplicate-resolved",
+        WARN,
+        ORDERING,
+        "the same directory as an earlier entry once resolved",
+    ),
+    issue_code(
+        "brew-duplicate",
+        WARN,
+        None,
+        "a versioned Homebrew directory also in PATH by its opt link",
+    ),
+    issue_code(
+        "brew-pinned",
+        WARN,
+        None,
+        "a Homebrew directory pinned to one version",
+    ),
+    issue_code(
+        "rustup-toolchain",
+        WARN,
+        ORDERING,
+        "a rustup toolchain ahead of the rustup proxies",
+    ),
+    issue_code(
+        "java-missing",
+        WARN,
+        None,
+        "JAVA_HOME is set but no java is in PATH",
+    ),
+    issue_code(
+        "java-mismatch",
+        WARN,
+        ORDERING,
+        "the java that runs isn't the JDK in JAVA_HOME",
+    ),
+    issue_code(
+        "java-later",
+        WARN,
+        ORDERING,
+        "the JDK in JAVA_HOME comes after another java",
+    ),
+    issue_code(
+        "shim-conflict",
+        WARN,
+        ORDERING,
+        "a command both in an entry and in the shims of a version manager",
+    ),
+    issue_code(
+        "shim-registry",
+        WARN,
+        None,
+        "shims missing from the registry PATH",
+    ),
+    issue_code(
+        "tool-dir-missing",
+        WARN,
+        None,
+        "where pipx or uv put commands isn't in PATH",
+    ),
+    issue_code(
+        "tool-dir-repeated",
+        WARN,
+        ORDERING,
+        "where pipx or uv put commands is in PATH more than once",
+    ),
+    issue_code(
+        "winget-not-in-path",
+        WARN,
+        None,
+        "where winget puts commands isn't in PATH",
+    ),
+    issue_code(
+        "winget-broken-link",
+        WARN,
+        None,
+        "a winget link to a package that is gone",
+    ),
+    issue_code(
+        "session-only",
+        WARN,
+        None,
+        "only in this session, new processes won't get it",
+    ),
+    issue_code(
+        "registry-only",
+        WARN,
+        None,
+        "in the registry but not in this session",
+    ),
+];
+
+fn issue_severity(code: &str) -> audit::Severity {
+    ISSUE_CODES
+        .iter()
+        .find(|c| c.code == code)
+        .map_or(WARN, |c| c.severity)
+}
+
+// A problem found by one of the checks doctor runs
+#[derive(Serialize)]
+struct Diagnosis {
+    id: String,
+    check: &'static str,
+    severity: audit::Severity,
+    code: &'static str,
+    path: Option<PathBuf>,
+    message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    acknowledged: Option<state::Ack>,
+}
+
+impl Diagnosis {
+    fn new(
+        check: &'static str,
+        severity: audit::Severity,
+        code: &'static str,
+        path: Option<PathBuf>,
+        message: String,
+    ) -> Self {
+        Diagnosis {
+            id: state::finding_id(check, code, path.as_deref()),
+            check,
+            severity,
+            code,
+            path,
+            message,
+            acknowledged: None,
+        }
+    }
+
+    // Acknowledged problems are listed but never fail doctor
+    fn fails(&self, fail_on: audit::Severity) -> bool {
+        self.acknowledged.is_none() && self.severity >= fail_on
+    }
+}
+
+// Run validate, shadow and audit, in that order
+fn diagnose(path: &OsStr, var: &str, allow_cwd: bool) -> Result<Vec<Diagnosis>> {
+    let paths = pathops::split_entries(path);
+    let mut found: Vec<Diagnosis> = Vec::new();
+    for issue in validate(path, var, allow_cwd)? {
+        found.push(Diagnosis::new(
+            "validate",
+            issue_severity(issue.code),
+            issue.code,
+            issue.path,
+            issue.message,
+        ));
+    }
+    if var == "PATH" {
+        let shadowed: Vec<String> = pathops::scan_executables(&paths)
+            .into_iter()
+            .filter(|(_, exes)| {
+                let winner = pathops::resolve(&exes[0].1).ok();
+                exes[1..]
+                    .iter()
+                    .any(|(_, e)| pathops::resolve(e).ok() != winner)
+            })
+            .map(|(name, _)| name)
+            .collect();
+        if !shadowed.is_empty() {
+            found.push(Diagnosis::new(
+                "shadow",
+                audit::Severity::Info,
+                "shadowed",
+                None,
+                format!(
+                    "{} commands run from an earlier entry than others with the same name, like {}, see 'pathaid shadow'",
+                    shadowed.len(),
+                    shadowed.iter().take(3).cloned().collect::<Vec<String>>().join(", ")
+                ),
+            ));
+        }
+    }
+    for f in audit::audit(&paths) {
+        // Already reported by validate:
+        if matches!(f.code, "cwd" | "cwd-hidden") && !allow_cwd {
+            continue;
+        }
+        found.push(Diagnosis::new(
+            "audit",
+            f.severity,
+            f.code,
+            Some(f.path),
+            f.message,
+        ));
+    }
+    Ok(found)
+}
+
+// Mark the problems that are acknowledged, giving the acknowledgments that have
+// expired, whose problems count again
+fn acknowledge(found: &mut [Diagnosis]) -> Result<Vec<state::Ack>> {
+    let mut expired: Vec<state::Ack> = Vec::new();
+    let acks = state::read_acks()?;
+    for d in found.iter_mut() {
+        match acks.iter().find(|a| a.id == d.id) {
+            Some(ack) if ack.is_expired() => expired.push(ack.clone()),
+            Some(ack) => d.acknowledged = Some(ack.clone()),
+            None => {}
+        }
+    }
+    Ok(expired)
+}
+
+// Run validate, shadow and audit in one go and report by check, telling if
+// anything that isn't acknowledged is at least as severe as fail_on
+fn doctor(
+    path: &OsStr,
+    var: &str,
+    allow_cwd: bool,
+    fail_on: audit::Severity,
+    compare_last: bool,
+    format: Format,
+) -> Result<bool> {
+    let mut found = diagnose(path, var, allow_cwd)?;
+    let expired = acknowledge(&mut found)?;
+    if compare_last {
+        return doctor_changes(var, &found, fail_on, format);
+    }
+    let (acknowledged, found): (Vec<Diagnosis>, Vec<Diagnosis>) =
+        found.into_iter().partition(|d| d.acknowledged.is_some());
+    let count = |severity| found.iter().filter(|d| d.severity == severity).count();
+    let (errors, warnings) = (count(audit::Severity::Error), count(audit::Severity::Warn));
+    let failed = found.iter().any(|d| d.fails(fail_on));
+    if format == Format::Json {
+        print_json(&serde_json::json!({
+            "errors": errors,
+            "warnings": warnings,
+            "failed": failed,
+            "problems": found,
+            "acknowledged": acknowledged,
+            "expired": expired,
+        }))?;
+        return Ok(failed);
+    }
+    for check in ["validate", "shadow", "audit"] {
+        let in_check: Vec<&Diagnosis> = found.iter().filter(|d| d.check == check).collect();
+        let status = if in_check.is_empty() {
+            "ok".green()
+        } else {
+            format!("{} found", in_check.len()).yellow()
+        };
+        println!("{} {}", format!("{:<8}", check).bold(), status);
+        for d in in_check {
+            println!(
+                "  {}: {} [{} {}]",
+                fmt_severity(d.severity),
+                redact(&d.message),
+                d.code,
+                d.id
+            );
+        }
+    }
+    if !acknowledged.is_empty() {
+        println!(
+            "{} {}",
+            "acknowledged".bold(),
+            acknowledged.len().to_string().dimmed()
+        );
+        for d in acknowledged.iter() {
+            let Some(ack) = &d.acknowledged else {
+                continue;
+            };
+            let until = ack
+                .expires
+                .map(|e| format!(", until {}", state::format_time(e)))
+                .unwrap_or_default();
+            println!(
+                "  {}: {} [{} {}] {}{}",
+                d.severity.label().dimmed(),
+                redact(&d.message),
+                d.code,
+                d.id,
+                ack.reason.italic(),
+                until
+            );
+        }
+    }
+    for ack in expired.iter() {
+        println!(
+            "note: the acknowledgment of {} expired on {}: {}",
+            ack.id,
+            state::format_time(ack.expires.unwrap_or_default()),
+            ack.reason
+        );
+    }
+    println!("{} errors, {} warnings", errors, warnings);
+    Ok(failed)
+}
+
+// Accept a problem doctor finds now, so it stops failing on it, or change the
+// reason and expiry of one accepted before
+fn ack(
+    path: &OsStr,
+    var: &str,
+    allow_cwd: bool,
+    id: &str,
+    reason: &str,
+    expires: Option<&str>,
+) -> Result<()> {
+    let expires = expires.map(state::parse_date).transpose()?;
+    let found = diagnose(path, var, allow_cwd)?;
+    let (code, problem, message) = match found.iter().find(|d| d.id == id) {
+        Some(d) => (d.code.to_string(), d.path.clone(), redact(&d.message)),
+        None => match state::read_acks()?.into_iter().find(|a| a.id == id) {
+            Some(a) => {
+                let about = match &a.path {
+                    Some(p) => redact(&p.to_string_lossy()),
+                    None => id.to_string(),
+                };
+                (a.code, a.path, about)
+            }
+            None => bail!(
+                "doctor finds no problem with id {} now, the ids are shown after the codes",
+                id
+            ),
+        },
+    };
+    if QUIET.get() != Some(&true) {
+        eprintln!("acknowledged: {} [{}]", message, code);
+    }
+    state::save_ack(state::Ack {
+        id: id.to_string(),
+        reason: reason.to_string(),
+        time: std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0),
+        expires,
+        code,
+        path: problem,
+    })
+}
+
+fn list_acks(plain: bool, format: Format) -> Result<()> {
+    let acks = state::read_acks()?;
+    if format == Format::Json {
+        return print_json(&acks);
+    }
+    for ack in acks.iter() {
+        let path = ack
+            .path
+            .as_ref()
+            .map(|p| format!(" {}", fmt_path(p, 0)))
+            .unwrap_or_default();
+        let expires = match ack.expires {
+            Some(e) if ack.is_expired() => format!("expired {}", state::format_time(e)),
+            Some(e) => format!("until {}", state::format_time(e)),
+            None => String::from("no expiry"),
+        };
+        if plain {
+            println!("{}\t{}\t{}\t{}", ack.id, ack.code, ack.reason, expires);
+        } else {
+            println!(
+                "{} {}{}: {} ({})",
+                ack.id.bold(),
+                ack.code,
+                path,
+                ack.reason,
+                expires
+            );
+        }
+    }
+    Ok(())
+}
+
+// Report only what is new or resolved since the last run, so a CI job or prompt
+// can warn about regressions without repeating known problems, and keep what was
+// found for the next run. Only new problems can fail it.
+fn doctor_changes(
+    var: &str,
+    found: &[Diagnosis],
+    fail_on: audit::Severity,
+    format: Format,
+) -> Result<bool> {
+    let key = match pathops::root() {
+        Some(root) => format!("{} under {}", var, root.display()),
+        None => var.to_string(),
+    };
+    let problems: Vec<state::Problem> = found
+        .iter()
+        .map(|d| state::Problem {
+            check: d.check.to_string(),
+            severity: d.severity.label().to_string(),
+            code: d.code.to_string(),
+            path: d.path.clone(),
+            message: d.message.clone(),
+        })
+        .collect();
+    let last = state::last_doctor_run(&key)?;
+    let before = last
+        .as_ref()
+        .map(|r| r.problems.as_slice())
+        .unwrap_or_default();
+    let (new, resolved) = state::compare_problems(before, &problems);
+    let failed = new.iter().any(|&i| found[i].fails(fail_on));
+    if format == Format::Json {
+        print_json(&serde_json::json!({
+            "since": last.as_ref().map(|r| r.time),
+            "failed": failed,
+            "new": new.iter().map(|&i| &found[i]).collect::<Vec<_>>(),
+            "resolved": resolved.iter().map(|&i| &before[i]).collect::<Vec<_>>(),
+        }))?;
+    } else {
+        match &last {
+            Some(run) => println!(
+                "since {}: {} new, {} resolved",
+                state::format_time(run.time),
+                new.len(),
+                resolved.len()
+            ),
+            None => println!("no earlier run to compare with, so everything found is new"),
+        }
+        for &i in new.iter() {
+            let d = &found[i];
+            let acknowledged = match d.acknowledged {
+                Some(_) => " (acknowledged)",
+                None => "",
+            };
+            println!(
+                "{} {}: {} [{} {}]{}",
+                "+".red(),
+                fmt_severity(d.severity),
+                redact(&d.message),
+                d.code,
+                d.id,
+                acknowledged
+            );
+        }
+        for &i in resolved.iter() {
+            let p = &before[i];
+            println!(
+                "{} {}: {} [{}]",
+                "-".green(),
+                p.severity.dimmed(),
+                redact(&p.message),
+                p.code
+            );
+        }
+    }
+    state::save_doctor_run(&key, problems)?;
+    Ok(failed)
+}
+
+// Print what lint found in a file, as file:line: message like compilers do
+fn print_findings(file: &Path, findings: &[lint::Finding], format: Format) -> Result<()> {
+    if format == Format::Json {
+        return print_json(&findings);
+    }
+    for f in findings.iter() {
+        println!(
+            "{}:{}: {} [{}]",
+            redact(&file.to_string_lossy()),
+            f.line,
+            f.message,
+            f.code
+        );
+    }
+    Ok(())
+}
+
+fn lint_script(file: &Path, format: Format) -> Result<()> {
+    let contents =
+        fs::read_to_string(file).with_context(|| format!("unable to read '{}'", file.display()))?;
+    print_findings(file, &lint::lint_script(&contents), format)
+}
+
+// Check a Makefile, or a justfile if named like one, against the tools required
+fn lint_build(file: &Path, requires: Option<&str>, format: Format) -> Result<()> {
+    let contents =
+        fs::read_to_string(file).with_context(|| format!("unable to read '{}'", file.display()))?;
+    let name = file
+        .file_name()
+        .map(|n| n.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    let just = name == "justfile" || name == ".justfile" || name.ends_with(".just");
+    let requires: Option<Vec<String>> = requires.map(|r| {
+        r.split(',')
+            .map(str::trim)
+            .filter(|t| !t.is_empty())
+            .map(String::from)
+            .collect()
+    });
+    let findings = lint::lint_build(&contents, just, requires.as_deref());
+    print_findings(file, &findings, format)
+}
+
+fn lint_ci(files: &[PathBuf], format: Format) -> Result<()> {
+    let mut all: Vec<serde_json::Value> = Vec::new();
+    for file in files.iter() {
+        let contents = fs::read_to_string(file)
+            .with_context(|| format!("unable to read '{}'", file.display()))?;
+        let findings = lint::lint_ci(&contents);
+        if format == Format::Json {
+            all.extend(findings.iter().map(|f| {
+                let mut value = serde_json::json!(f);
+                value["file"] = serde_json::json!(file);
+                value
+            }));
+        } else {
+            print_findings(file, &findings, format)?;
+        }
+    }
+    if format == Format::Json {
+        print_json(&all)?;
+    }
+    Ok(())
+}
+
+// What the methods of serve --stdio can be given, each using what it needs
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RpcParams {
+    // The path string to look at instead of the one pathaid was started with
+    path: Option<String>,
+    name: Option<String>,
+    #[serde(default)]
+    remove: Vec<String>,
+    #[serde(default)]
+    prepend: Vec<String>,
+    #[serde(default)]
+    append: Vec<String>,
+}
+
+const RPC_METHODS: [&str; 4] = ["list", "validate", "which", "simulate"];
+
+// Answer a method of serve --stdio with what the command of the same name prints
+// as JSON, simulate giving the path after removing, prepending and appending
+fn rpc_method(
+    path: &OsStr,
+    allow_cwd: bool,
+    method: &str,
+    params: serde_json::Value,
+) -> Option<Result<serde_json::Value>> {
+    if !RPC_METHODS.contains(&method) {
+        return None;
+    }
+    let answer = || -> Result<serde_json::Value> {
+        let params: RpcParams = rpc::params(params)?;
+        let path = params.path.as_deref().map(OsStr::new).unwrap_or(path);
+        Ok(match method {
+            "list" => serde_json::to_value(pathops::entries(&pathops::split(path), true))?,
+            "validate" => serde_json::to_value(validate(path, "PATH", allow_cwd)?)?,
+            "which" => {
+                let Some(name) = params.name else {
+                    return Err(rpc::invalid_params("which needs a name"));
+                };
+                serde_json::to_value(pathops::find_executables(path, &name))?
+            }
+            _ => {
+                let dirs = |d: &[String]| d.iter().map(PathBuf::from).collect::<Vec<PathBuf>>();
+                let mut new_path = pathops::remove_paths(path, &dirs(&params.remove))?;
+                if !params.prepend.is_empty() {
+                    new_path = pathops::prepend_paths(&new_path, &dirs(&params.prepend))?;
+                }
+                if !params.append.is_empty() {
+                    new_path = pathops::append_paths(&new_path, &dirs(&params.append))?;
+                }
+                let issues = validate(&new_path, "PATH", allow_cwd)?;
+                serde_json::json!({"path": pathops::into_string(new_path)?, "issues": issues})
+            }
+        })
+    };
+    Some(answer())
+}
+
+// Show the timeline of one entry, or when each entry was last seen to change
+fn history(entry: Option<&str>, format: Format) -> Result<()> {
+    let Some(history) = state::read_history()? else {
+        println!("history is off, turn it on with 'history --enable'");
+        return Ok(());
+    };
+    let describe = |e: &state::Event| {
+        let change = match e.change {
+            state::Change::Added => String::from("added to PATH"),
+            state::Change::Missing => String::from("went missing"),
+            state::Change::Returned => String::from("is back"),
+            state::Change::Executables => String::from("executables changed"),
+            state::Change::Removed => String::from("removed from PATH"),
+        };
+        match e.executables {
+            Some(n) if e.change != state::Change::Missing => {
+                format!("{}, {} executables", change, n)
+            }
+            _ => change,
+        }
+    };
+    let Some(entry) = entry else {
+        if format == Format::Json {
+            return print_json(&history);
+        }
+        for (p, events) in history.entries.iter() {
+            if let Some(last) = events.last() {
+                println!(
+                    "{}  {} {}",
+                    state::format_time(last.time).dimmed(),
+                    fmt_path(p, 0),
+                    describe(last)
+                );
+            }
+        }
+        return Ok(());
+    };
+    let p = PathBuf::from(entry);
+    let events = history
+        .entries
+        .get(&p)
+        .with_context(|| format!("there is no history of '{}'", entry))?;
+    if format == Format::Json {
+        return print_json(events);
+    }
+    for e in events.iter() {
+        println!("{}  {}", state::format_time(e.time).dimmed(), describe(e));
+    }
+
+    Ok(())
+}
+
+// Clean up the state directory and tell how much space that gave back
+// The snapshot is of the variable it was saved from, whatever --var is now
+fn restore_snapshot(name: &str, format: Format) -> Result<()> {
+    let snapshot = state::read_snapshot(name)?;
+    let change = Change {
+        old: pathops::get_var(&snapshot.var).unwrap_or_default(),
+        new: snapshot.path.into(),
+        index: None,
+        warnings: Vec::new(),
+    };
+    match format {
+        Format::Text | Format::Json => print_change(change, format),
+        _ => print_changes(vec![(snapshot.var, change)], format),
+    }
+}
+
+fn list_snapshots(format: Format) -> Result<()> {
+    let snapshots = state::list_snapshots()?;
+    if format == Format::Json {
+        return print_json(&snapshots);
+    }
+    for s in snapshots.iter() {
+        println!(
+            "{}  {} of {}, {} entries",
+            state::format_time(s.time).dimmed(),
+            s.name.bold(),
+            s.var,
+            pathops::split_entries(&s.path).len()
+        );
+    }
+
+    Ok(())
+}
+
+fn gc(dry_run: bool) -> Result<()> {
+    let removed = state::gc(dry_run)?;
+    for (file, _) in removed.iter() {
+        println!(
+            "{} {}",
+            if dry_run { "would remove" } else { "removed" },
+            fmt_path(file, 1)
+        );
+    }
+    let total: u64 = removed.iter().map(|(_, size)| size).sum();
+    println!(
+        "{} {} bytes in {} files",
+        if dry_run {
+            "would reclaim"
+        } else {
+            "reclaimed"
+        },
+        total,
+        removed.len()
+    );
+
+    Ok(())
+}
+
+// Get the path string to work on: given with --input or on stdin, to look at one
+// from another machine, a log or an image, or else from the environment or as
+// composed from system files only. Variables other than PATH are often unset,
+// which is the same as having no entries.
+fn read_input(matches: &ArgMatches, var: &str) -> Result<OsString> {
+    if let Some(input) = matches.get_one::<OsString>("input") {
+        return Ok(input.clone());
+    }
+    if matches.get_flag("stdin") {
+        let mut input = Vec::new();
+        io::stdin()
+            .read_to_end(&mut input)
+            .context("unable to read the path string from stdin")?;
+        return parse_input(&input, var);
+    }
+    if let Some(root) = pathops::root() {
+        ensure!(
+            var == "PATH",
+            "only PATH can be composed from the files under --root, give {} with --input",
+            var
+        );
+        return Ok(rootfs::compose_path(root)?.path.into());
+    }
+    let system_scope = matches.get_one::<String>("scope").map(String::as_str) == Some("system");
+    match var {
+        "PATH" if system_scope => Ok(pathops::system_path()?),
+        "PATH" => Ok(pathops::get_path()?),
+        _ if system_scope => bail!("only PATH can be composed from system files"),
+        _ => Ok(pathops::get_var(var).unwrap_or_default()),
+    }
+}
+
+// The comma-separated variables given with --vars, of which there can only be one
+// when the path string is given with --input or --stdin
+fn var_list<'a>(matches: &ArgMatches, subm: &'a ArgMatches) -> Result<Vec<&'a str>> {
+    let vars: Vec<&str> = subm
+        .get_one::<String>("vars")
+        .unwrap()
+        .split(',')
+        .map(str::trim)
+        .filter(|v| !v.is_empty())
+        .collect();
+    ensure!(
+        vars.len() <= 1 || !(matches.contains_id("input") || matches.get_flag("stdin")),
+        "--input and --stdin give one path string, name a single variable with --vars"
+    );
+    Ok(vars)
+}
+
+fn expand_entries(path: &OsStr) -> Result<OsString> {
+    let entries: Vec<PathBuf> = pathops::split(path)
+        .iter()
+        .map(|p| pathops::expand(p))
+        .collect();
+    Ok(pathops::join(&entries)?)
+}
+
+// Take a captured path string as it is, as NAME=value like env prints it, or as
+// one entry per line like list prints it
+fn parse_input(input: &[u8], var: &str) -> Result<OsString> {
+    let lines: Vec<&[u8]> = input
+        .split(|&b| b == b'\n')
+        .map(|l| l.strip_suffix(b"\r").unwrap_or(l))
+        .filter(|l| !l.trim_ascii().is_empty())
+        .collect();
+    match lines.as_slice() {
+        [] => Ok(OsString::new()),
+        [line] => {
+            let prefix = format!("{}=", var);
+            os_string(line.strip_prefix(prefix.as_bytes()).unwrap_or(line))
+        }
+        _ => Ok(pathops::join(
+            &lines
+                .iter()
+                .map(|l| os_string(l).map(PathBuf::from))
+                .collect::<Result<Vec<PathBuf>>>()?,
+        )?),
+    }
+}
+
+// Bytes read as they are, which only Unix can keep when they aren't valid Unicode
+#[cfg(unix)]
+fn os_string(bytes: &[u8]) -> Result<OsString> {
+    use std::os::unix::ffi::OsStrExt;
+    Ok(OsStr::from_bytes(bytes).to_os_string())
+}
+
+#[cfg(not(unix))]
+fn os_string(bytes: &[u8]) -> Result<OsString> {
+    let text = String::from_utf8_lossy(bytes);
+    match text {
+        std::borrow::Cow::Borrowed(text) => Ok(text.into()),
+        std::borrow::Cow::Owned(text) => Err(pathops::Error::NotUnicode(text).into()),
+    }
+}
+
+// Dispatch to the command given on the command line
+fn run(matches: &ArgMatches) -> Result<()> {
+    let allow_cwd = matches.get_flag("allow-cwd");
+    // Colors are already off for NO_COLOR, CLICOLOR=0 or when not writing to a
+    // terminal, unless CLICOLOR_FORCE is set
+    let plain = matches.get_flag("plain");
+    if plain || matches.get_flag("deterministic") {
+        colored::control::set_override(false);
+    }
+    let add_options = |subm: &ArgMatches| AddOptions {
+        module: subm.get_flag("module"),
+        keep_relative: subm.get_flag("keep-relative"),
+        allow_cwd,
+        promote: subm.get_flag("promote"),
+        create: subm.get_flag("create"),
+        force: subm.get_flag("force"),
+        write: subm.get_flag("write"),
+    };
+    let shell = matches
+        .get_one::<String>("shell")
+        .and_then(|s| rcfile::syntax(s));
+    let format = match (
+        shell,
+        matches.get_one::<String>("format").map(String::as_str),
+    ) {
+        (Some(syntax), _) => Format::Shell(syntax),
+        (_, Some("json")) => Format::Json,
+        (_, Some("modulefile")) => Format::Modulefile,
+        (_, Some("lua")) => Format::Lua,
+        _ => Format::Text,
+    };
+    // Messages in JSON are plain text:
+    if format == Format::Json {
+        colored::control::set_override(false);
+    }
+    let system_scope = matches.get_one::<String>("scope").map(String::as_str) == Some("system");
+    let var = var_name();
+    // Without --keep-raw entries are expanded once, so everything gives them expanded:
+    let expand = matches.get_flag("expand") && !matches.get_flag("keep-raw");
+    let read_var = |var: &str| {
+        let path = read_input(matches, var)?;
+        match expand {
+            true => expand_entries(&path),
+            false => Ok(path),
+        }
+    };
+    let path = || read_var(var);
+    // Only the environment's own PATH is compared with the registry or kept history of:
+    let from_env = !system_scope
+        && !matches.contains_id("input")
+        && !matches.get_flag("stdin")
+        && !matches.contains_id("root");
+    if let Some(("diff", subm)) = matches.subcommand() {
+        ensure!(
+            !(matches.get_flag("stdin") && subm.get_one::<String>("OTHER").unwrap() == "-"),
+            "only one of the path strings can be read from stdin"
+        );
+    }
+    if let Some(command @ ("serve" | "edit" | "top")) = matches.subcommand_name() {
+        ensure!(
+            !matches.get_flag("stdin"),
+            "{} reads the terminal or its own input, use --input instead of --stdin",
+            command
+        );
+    }
+    if let Some(command @ ("which" | "shadow" | "count" | "export")) = matches.subcommand_name() {
+        ensure!(
+            var == "PATH",
+            "{} looks for executables and only works on PATH",
+            command
+        );
+    }
+    match matches.subcommand() {
+        Some(("validate", _)) => {
+            let path = path()?;
+            #[allow(unused_mut)]
+            let mut issues = validate(&path, var, allow_cwd)?;
+            #[cfg(windows)]
+            if from_env && var == "PATH" {
+                validate_drift(&path, &mut issues)?;
+            }
+            print_issues(&issues, plain, format)?;
+            if from_env && var == "PATH" {
+                state::record_history(&pathops::entries(&pathops::split(&path), true));
+            }
+        }
+        Some(("audit", subm)) => audit(
+            &path()?,
+            subm.get_one::<PathBuf>("denylist").map(PathBuf::as_path),
+            subm.get_one::<String>("report").map(String::as_str),
+            format,
+        )?,
+        Some(("doctor", subm)) => {
+            let fail_on = match subm.get_one::<String>("fail-on").map(String::as_str) {
+                Some("warn") => audit::Severity::Warn,
+                _ => audit::Severity::Error,
+            };
+            let compare_last = subm.get_flag("compare-last");
+            if doctor(&path()?, var, allow_cwd, fail_on, compare_last, format)? {
+                io::stdout().flush()?;
+                process::exit(Exit::Problems.code());
+            }
+        }
+        Some(("ack", subm)) => {
+            let id = subm.get_one::<String>("ID");
+            match id {
+                _ if subm.get_flag("list") => list_acks(plain, format)?,
+                Some(id) if subm.get_flag("remove") => {
+                    if !state::remove_ack(id)? {
+                        bail!("no problem with id {} is acknowledged", id);
+                    }
+                }
+                Some(id) => ack(
+                    &path()?,
+                    var,
+                    allow_cwd,
+                    id,
+                    subm.get_one::<String>("reason").unwrap(),
+                    subm.get_one::<String>("expires").map(String::as_str),
+                )?,
+                None => unreachable!("clap requires ID unless --list is given"),
+            }
+        }
+        Some(("dedup", subm)) => {
+            let keep = match subm.get_one::<String>("keep").map(String::as_str) {
+                Some("last") => pathops::Keep::Last,
+                _ => pathops::Keep::First,
+            };
+            dedup(
+                path()?,
+                keep,
+                subm.get_flag("prefer-resolved"),
+                subm.get_flag("write"),
+                format,
+            )?
+        }
+        Some(("count", _)) => count_exes(&path()?, plain, format)?,
+        Some(("all", subm)) => {
+            let vars = var_list(matches, subm)?;
+            let command = subm.subcommand_name().unwrap_or("list");
+            // Unset is an error here, as it's asked for by name:
+            let read = |var: &str| {
+                if from_env {
+                    pathops::get_var(var)?;
+                }
+                read_var(var)
+            };
+            run_all(&vars, read, command, allow_cwd, plain, format)?;
+        }
+        Some(("prefix", subm)) => {
+            let (action, subm) = subm.subcommand().unwrap();
+            let p = subm.get_one::<String>("PREFIX").unwrap();
+            let vars = var_list(matches, subm)?;
+            prefix(p, &vars, read_var, action == "remove", format)?;
+        }
+        Some(("env", subm)) => {
+            let values = |id| {
+                subm.get_many::<String>(id)
+                    .map(|v| v.cloned().collect())
+                    .unwrap_or_else(Vec::new)
+            };
+            run_with_path(
+                path()?,
+                var,
+                &values("with"),
+                &values("without"),
+                &values("COMMAND"),
+                allow_cwd,
+            )?;
+        }
+        Some(("query", subm)) => {
+            let expr = subm.get_one::<String>("EXPR").unwrap();
+            query(&path()?, expr, plain, format)?;
+        }
+        Some(("diff", subm)) => {
+            let other = read_other(subm.get_one::<String>("OTHER").unwrap(), var)?;
+            diff(&path()?, &other, format)?
+        }
+        Some(("shadow", _)) => shadow(&path()?, plain, format)?,
+        Some(("export", subm)) => export_resolution_map(
+            &path()?,
+            subm.get_one::<PathBuf>("resolution-map").unwrap(),
+            subm.get_flag("hash"),
+            subm.get_flag("probe-versions"),
+        )?,
+        Some(("which", subm)) => {
+            let name = subm.get_one::<String>("NAME").unwrap();
+            which(&path()?, name, subm.get_flag("all"), plain, format)?;
+        }
+        Some(("prune", subm)) => prune(
+            path()?,
+            var,
+            subm.get_flag("keep-empty"),
+            subm.get_flag("dry-run"),
+            format,
+        )?,
+        Some(("clean", _)) => clean(path()?, format)?,
+        Some(("normalize", subm)) => normalize(path()?, subm.get_flag("absolute"), format)?,
+        Some(("remove", subm)) => {
+            let targets: Vec<String> = subm.get_many::<String>("PATH").unwrap().cloned().collect();
+            remove_entries(path()?, &targets, subm.get_flag("all"), format)?;
+        }
+        Some(("append", subm)) => {
+            let p: Vec<String> = subm.get_many::<String>("PATH").unwrap().cloned().collect();
+            append_paths(path()?, &p, add_options(subm), format)?;
+        }
+        Some(("prepend", subm)) => {
+            let p: Vec<String> = subm.get_many::<String>("PATH").unwrap().cloned().collect();
+            prepend_paths(path()?, &p, add_options(subm), format)?;
+        }
+        Some(("insert", subm)) => {
+            let p: Vec<String> = subm.get_many::<String>("PATH").unwrap().cloned().collect();
+            let position = Position::from_matches(subm);
+            let opts = AddOptions {
+                keep_relative: subm.get_flag("keep-relative"),
+                allow_cwd,
+                promote: subm.get_flag("promote"),
+                create: subm.get_flag("create"),
+                force: subm.get_flag("force"),
+                write: subm.get_flag("write"),
+                ..Default::default()
+            };
+            insert_paths(path()?, &p, position, opts, format)?;
+        }
+        Some(("move", subm)) => {
+            let entry = subm.get_one::<String>("ENTRY").unwrap();
+            move_path(path()?, entry, Position::from_matches(subm), format)?;
+        }
+        Some(("lint-script", subm)) => {
+            lint_script(subm.get_one::<PathBuf>("FILE").unwrap(), format)?
+        }
+        Some(("lint-build", subm)) => lint_build(
+            subm.get_one::<PathBuf>("FILE").unwrap(),
+            subm.get_one::<String>("requires").map(String::as_str),
+            format,
+        )?,
+        Some(("lint-ci", subm)) => {
+            let files: Vec<PathBuf> = subm.get_many::<PathBuf>("FILE").unwrap().cloned().collect();
+            lint_ci(&files, format)?
+        }
+        Some(("edit", _)) => {
+            let path = path()?;
+            let Some(new_path) = tui::edit(&path, var)? else {
+                bail!("editing was cancelled, {} is unchanged", var);
+            };
+            let change = Change {
+                old: path,
+                new: new_path,
+                index: None,
+                warnings: Vec::new(),
+            };
+            print_change(change, format)?;
+        }
+        Some(("top", _)) => tui::top(&path()?, var)?,
+        Some(("list", subm)) if subm.get_flag("resolve") => list_resolved(
+            &path()?,
+            subm.get_flag("keep-missing"),
+            subm.get_flag("join"),
+            format,
+        )?,
+        Some(("list", subm)) if subm.get_flag("by-mount") => list_by_mount(&path()?, format)?,
+        // The entries in JSON have all there is to list, except what --long adds:
+        Some(("list", subm)) if format == Format::Json && !subm.get_flag("long") => {
+            list_paths(&path()?, plain, false, &list_filters(subm), format)?
+        }
+        Some(("stats", subm)) if subm.get_flag("enable") => state::set_stats_enabled(true)?,
+        Some(("stats", subm)) if subm.get_flag("disable") => state::set_stats_enabled(false)?,
+        Some(("stats", _)) => self_stats(format)?,
+        Some(("history", subm)) if subm.get_flag("enable") => state::set_history_enabled(true)?,
+        Some(("history", subm)) if subm.get_flag("disable") => state::set_history_enabled(false)?,
+        Some(("history", subm)) => {
+            history(subm.get_one::<String>("ENTRY").map(String::as_str), format)?
+        }
+        Some(("gc", subm)) => gc(subm.get_flag("dry-run"))?,
+        Some(("snapshot", subm)) => match subm.subcommand() {
+            Some(("save", subm)) => {
+                let name = subm.get_one::<String>("NAME").unwrap();
+                let snapshot = state::save_snapshot(name, var, &pathops::into_string(path()?)?)?;
+                println!(
+                    "saved {} as '{}', {} entries",
+                    snapshot.var,
+                    snapshot.name,
+                    pathops::split_entries(&snapshot.path).len()
+                );
+            }
+            Some(("restore", subm)) => {
+                restore_snapshot(subm.get_one::<String>("NAME").unwrap(), format)?
+            }
+            _ => list_snapshots(format)?,
+        },
+        Some(("daemon", _)) => daemon::serve()?,
+        Some(("serve", _)) => {
+            // Messages in the answers are plain text:
+            colored::control::set_override(false);
+            let path = path()?;
+            rpc::serve(io::stdin().lock(), io::stdout().lock(), |method, params| {
+                rpc_method(&path, allow_cwd, method, params)
+            })?;
+        }
+        Some(("help", subm)) => {
+            let words: Vec<String> = subm
+                .get_many::<String>("TOPIC")
+                .map(|w| w.cloned().collect())
+                .unwrap_or_default();
+            help(&words)?;
+        }
+        Some(("why-slow", subm)) => {
+            let threshold = *subm.get_one::<u64>("threshold-ms").unwrap();
+            why_slow(&path()?, threshold, format)?;
+        }
+        Some(("explain", subm)) => {
+            let entries: Vec<String> = subm.get_many::<String>("ENTRY").unwrap().cloned().collect();
+            explain(&path()?, &entries, subm.get_flag("package"))?;
+        }
+        Some(("setup", subm)) => setup(&pathops::get_path()?, subm.get_flag("yes"))?,
+        Some(("completions", subm)) => {
+            let shell = *subm.get_one::<Shell>("SHELL").unwrap();
+            if subm.get_flag("install") {
+                install_completions(shell)?;
+            } else {
+                clap_complete::generate(
+                    shell,
+                    &mut cli(),
+                    env!("CARGO_PKG_NAME"),
+                    &mut io::stdout(),
+                );
+            }
+        }
+        Some(("list", subm)) if subm.get_flag("long") => list_long(
+            &path()?,
+            plain,
+            subm.get_flag("icons"),
+            &list_filters(subm),
+            format,
+        )?,
+        Some(("list", subm)) => list_paths(
+            &path()?,
+            plain,
+            subm.get_flag("icons"),
+            &list_filters(subm),
+            format,
+        )?,
+        _ => list_paths(&path()?, plain, false, &[], format)?,
+    }
+
+    Ok(())
+}
@@ -0,0 +1,232 @@
+/*
+Summary of daemon functions
+
+Scanning every entry of a long PATH for executables takes a while, so a daemon can
+keep the result and answer over a Unix socket in the state directory instead. Each
+request and response is one line of JSON:
+
+  {"query": "status", "path": "/usr/bin:/bin"}
+  {"query": "shadow", "path": "/usr/bin:/bin"}
+  {"query": "which", "path": "/usr/bin:/bin", "name": "ls"}
+
+and the answer is {"ok": ...} or {"error": "..."}. A scan is kept for each PATH
+asked about and redone once the modification time of any of its entries changes,
+which adding or removing a file does, or once it is MAX_AGE old. Changing a file
+in place, like chmod +x, only shows in the next scan after that.
+
+# answer requests until stopped
+serve() -> Result<()>
+
+# ask a running daemon, or None if there isn't one so the caller does the work
+request(Request) -> Option<Result<serde_json::Value>>
+*/
+
+use crate::pathops::{self, PathEntry};
+use crate::state;
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+use std::time::{Duration, Instant, SystemTime};
+
+#[cfg(unix)]
+use anyhow::Context;
+#[cfg(unix)]
+use std::io::{BufRead, BufReader, Write};
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+
+#[cfg(unix)]
+const SOCKET_FILE: &str = "daemon.sock";
+// Different PATHs kept at once, more than a few terminals and editors will ask about
+const MAX_SCANS: usize = 16;
+// How long a scan is used for when the directories look the same
+const MAX_AGE: Duration = Duration::from_secs(30);
+// How long a client waits for an answer before doing the work itself
+#[cfg(unix)]
+const TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum Query {
+    // Every entry with its number of executables
+    Status,
+    // Every command found in more than one entry
+    Shadow,
+    // The executables a name could run, entries resolving to the same directory once
+    Which,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Request {
+    pub query: Query,
+    pub path: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Response {
+    Ok(serde_json::Value),
+    Error(String),
+}
+
+struct Scan {
+    entries: Vec<PathEntry>,
+    commands: BTreeMap<String, Vec<(usize, PathBuf)>>,
+    modified: Vec<Option<SystemTime>>,
+    time: Instant,
+}
+
+fn modified(paths: &[PathBuf]) -> Vec<Option<SystemTime>> {
+    paths
+        .iter()
+        .map(|p| p.metadata().and_then(|m| m.modified()).ok())
+        .collect()
+}
+
+fn scan(paths: &[PathBuf]) -> Scan {
+    Scan {
+        entries: pathops::entries(paths, true),
+        commands: pathops::scan_executables(paths),
+        modified: modified(paths),
+        time: Instant::now(),
+    }
+}
+
+// Answer from the kept scan of the PATH asked about, scanning it first if needed
+fn answer(scans: &mut HashMap<String, Scan>, request: &Request) -> Result<serde_json::Value> {
+    let paths = pathops::split(&request.path);
+    let stale = scans
+        .get(&request.path)
+        .is_none_or(|s| s.modified != modified(&paths) || s.time.elapsed() > MAX_AGE);
+    if stale {
+        if scans.len() >= MAX_SCANS {
+            scans.clear();
+        }
+        scans.insert(request.path.clone(), scan(&paths));
+    }
+    let scan = &scans[&request.path];
+    Ok(match request.query {
+        Query::Status => serde_json::to_value(&scan.entries)?,
+        Query::Shadow => {
+            let shadowed: BTreeMap<&String, &Vec<(usize, PathBuf)>> = scan
+                .commands
+                .iter()
+                .filter(|(_, found)| found.len() > 1)
+                .collect();
+            serde_json::to_value(shadowed)?
+        }
+        Query::Which => {
+            let Some(name) = &request.name else {
+                bail!("which needs a name");
+            };
+            let key = pathops::command_name(&PathBuf::from(name)).unwrap_or_default();
+            serde_json::to_value(scan.commands.get(&key).cloned().unwrap_or_default())?
+        }
+    })
+}
+
+#[cfg(unix)]
+fn socket_file() -> Result<PathBuf> {
+    Ok(state::state_dir()?.join(SOCKET_FILE))
+}
+
+#[cfg(unix)]
+fn handle(stream: UnixStream, scans: &mut HashMap<String, Scan>) -> Result<()> {
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    let mut line = String::new();
+    BufReader::new(&stream).read_line(&mut line)?;
+    // Connecting without asking anything is how a running daemon is detected:
+    if line.trim().is_empty() {
+        return Ok(());
+    }
+    let response = match serde_json::from_str::<Request>(&line) {
+        Ok(request) => match answer(scans, &request) {
+            Ok(value) => Response::Ok(value),
+            Err(e) => Response::Error(format!("{:#}", e)),
+        },
+        Err(e) => Response::Error(format!("not a valid request: {}", e)),
+    };
+    let mut stream = &stream;
+    writeln!(stream, "{}", serde_json::to_string(&response)?)?;
+    Ok(())
+}
+
+// One request at a time, as answering from a kept scan is quick anyway
+#[cfg(unix)]
+pub fn serve() -> Result<()> {
+    let file = socket_file()?;
+    if file.exists() {
+        if UnixStream::connect(&file).is_ok() {
+            bail!("a daemon is already running on '{}'", file.display());
+        }
+        // Left behind by a daemon that was killed:
+        std::fs::remove_file(&file)
+            .with_context(|| format!("unable to remove '{}'", file.display()))?;
+    }
+    std::fs::create_dir_all(state::state_dir()?)?;
+    let listener = UnixListener::bind(&file)
+        .with_context(|| format!("unable to listen on '{}'", file.display()))?;
+    eprintln!("listening on '{}'", file.display());
+    let mut scans: HashMap<String, Scan> = HashMap::new();
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => {
+                if let Err(e) = handle(stream, &mut scans) {
+                    eprintln!("request failed: {:#}", e);
+                }
+            }
+            Err(e) => eprintln!("unable to accept a connection: {}", e),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn serve() -> Result<()> {
+    bail!("the daemon is only supported on Unix for now")
+}
+
+#[cfg(unix)]
+pub fn request(request: &Request) -> Option<Result<serde_json::Value>> {
+    let mut stream = UnixStream::connect(socket_file().ok()?).ok()?;
+    stream.set_read_timeout(Some(TIMEOUT)).ok()?;
+    stream.set_write_timeout(Some(TIMEOUT)).ok()?;
+    writeln!(stream, "{}", serde_json::to_string(request).ok()?).ok()?;
+    let mut line = String::new();
+    BufReader::new(&stream).read_line(&mut line).ok()?;
+    match serde_json::from_str(&line).ok()? {
+        Response::Ok(value) => Some(Ok(value)),
+        Response::Error(e) => Some(Err(anyhow::anyhow!("daemon: {}", e))),
+    }
+}
+
+#[cfg(not(unix))]
+pub fn request(_request: &Request) -> Option<Result<serde_json::Value>> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_answer_rescans() {
+        let dir = std::env::temp_dir().join(format!("pathaid-daemon-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut scans: HashMap<String, Scan> = HashMap::new();
+        let request = Request {
+            query: Query::Status,
+            path: dir.to_string_lossy().into_owned(),
+            name: None,
+        };
+        let status = answer(&mut scans, &request).unwrap();
+        assert_eq!(status[0]["executables"], 0);
+        assert_eq!(scans.len(), 1);
+        std::fs::remove_dir_all(&dir).unwrap();
+        let status = answer(&mut scans, &request).unwrap();
+        assert_eq!(status[0]["exists"], false)
+    }
+}
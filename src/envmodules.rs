@@ -0,0 +1,329 @@
+/*
+Summary of envmodules functions
+
+Entries in PATH are often put there by environment-modules/Lmod or point into a
+GNU Stow farm. Those are controlled by another tool and shouldn't be edited by hand.
+
+# get the modules currently loaded and the modulefiles they came from
+loaded_modules() -> Vec<(String, PathBuf)>
+
+# find what manages an entry, if anything
+manager(Path, loaded: Vec<(String, PathBuf)>) -> Option<Manager>
+
+# find a module under MODULEPATH that would add a directory to PATH
+find_available_module(Path) -> Option<String>
+
+# render the difference between two lists of entries as modulefile commands, if they can express it
+modulefile_lines(var: str, old: Vec<PathBuf>, new: Vec<PathBuf>, lua: bool) -> Option<Vec<String>>
+*/
+
+use crate::pathops::resolve;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// How deep to look for modulefiles below each MODULEPATH directory
+const MAX_MODULE_DEPTH: usize = 4;
+
+pub enum Manager {
+    // Name of the loaded module, if it could be told
+    Module(Option<String>),
+    // The stow directory the entry points into
+    Stow(PathBuf),
+}
+
+impl fmt::Display for Manager {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Manager::Module(Some(name)) => write!(f, "module {}", name),
+            Manager::Module(None) => write!(f, "module"),
+            Manager::Stow(dir) => write!(f, "stow {}", dir.display()),
+        }
+    }
+}
+
+// Modules as loaded by both environment-modules and Lmod, paired with their files
+pub fn loaded_modules() -> Vec<(String, PathBuf)> {
+    let names = env::var("LOADEDMODULES").unwrap_or_default();
+    let files = env::var("_LMFILES_").unwrap_or_default();
+    names
+        .split(':')
+        .zip(files.split(':'))
+        .filter(|(name, file)| !name.is_empty() && !file.is_empty())
+        .map(|(name, file)| (name.to_string(), PathBuf::from(file)))
+        .collect()
+}
+
+// Get the directories a modulefile (Tcl or Lua) adds to PATH, skipping any
+// that are built from variables since those can't be known without running it
+pub fn path_directives(contents: &str) -> Vec<PathBuf> {
+    let mut dirs: Vec<PathBuf> = Vec::new();
+
+    for line in contents.lines().map(str::trim) {
+        if line.starts_with("prepend_path") || line.starts_with("append_path") {
+            // Lua: prepend_path("PATH", "/opt/tool/bin")
+            let args: Vec<&str> = line
+                .trim_start_matches(|c| c != '(' && c != '{')
+                .trim_matches(['(', '{', ')', '}'])
+                .split(',')
+                .map(str::trim)
+                .collect();
+            if let [var, dir, ..] = args.as_slice() {
+                let literal = |s: &str| s.len() > 2 && s.starts_with('"') && s.ends_with('"');
+                if *var == "\"PATH\"" && literal(dir) {
+                    dirs.push(PathBuf::from(dir.trim_matches('"')));
+                }
+            }
+        } else if line.starts_with("prepend-path") || line.starts_with("append-path") {
+            // Tcl: prepend-path [--delim :] PATH /opt/tool/bin [...]
+            let mut words = line.split_whitespace().skip(1);
+            let mut var = words.next();
+            while let Some(w) = var.filter(|w| w.starts_with('-')) {
+                if w == "--delim" || w == "-d" {
+                    words.next();
+                }
+                var = words.next();
+            }
+            if var == Some("PATH") {
+                dirs.extend(words.flat_map(|w| w.split(':')).map(PathBuf::from));
+            }
+        }
+    }
+    dirs.retain(|d| !d.to_string_lossy().contains(['$', '[', '(']));
+    dirs
+}
+
+fn modulefile_dirs(file: &Path) -> Vec<PathBuf> {
+    match fs::read_to_string(file) {
+        Ok(contents) => path_directives(&contents),
+        _ => Vec::new(),
+    }
+}
+
+// Compare directories both literally and resolved
+fn same_dir(a: &Path, b: &Path) -> bool {
+    a == b
+        || match (resolve(a), resolve(b)) {
+            (Ok(x), Ok(y)) => x == y,
+            _ => false,
+        }
+}
+
+// Lmod keeps a reference count for every entry it has added
+fn lmod_entries() -> Vec<PathBuf> {
+    let counts = env::var("__LMOD_REF_COUNT_PATH").unwrap_or_default();
+    counts
+        .split(';')
+        .filter_map(|c| c.rsplit_once(':'))
+        .map(|(dir, _)| PathBuf::from(dir))
+        .collect()
+}
+
+// Look for a directory marked with .stow (or named stow) above the resolved entry
+fn stow_dir(path: &Path) -> Option<PathBuf> {
+    let res = resolve(path).ok()?;
+    res.ancestors()
+        .skip(1)
+        .find(|a| a.join(".stow").exists() || a.file_name().is_some_and(|n| n == "stow"))
+        .map(Path::to_path_buf)
+}
+
+// Check if entry was added by a loaded module or is part of a stow farm
+pub fn manager(path: &Path, loaded: &[(String, PathBuf)]) -> Option<Manager> {
+    for (name, file) in loaded {
+        if modulefile_dirs(file).iter().any(|d| same_dir(d, path)) {
+            return Some(Manager::Module(Some(name.clone())));
+        }
+    }
+    if lmod_entries().iter().any(|d| same_dir(d, path)) {
+        return Some(Manager::Module(None));
+    }
+    stow_dir(path).map(Manager::Stow)
+}
+
+fn find_in_module_dir(root: &Path, dir: &Path, target: &Path, depth: usize) -> Option<String> {
+    let entries = fs::read_dir(dir).ok()?;
+    for entry in entries.flatten() {
+        let p = entry.path();
+        if entry.file_name().to_string_lossy().starts_with('.') {
+            continue;
+        }
+        if p.is_dir() {
+            if depth < MAX_MODULE_DEPTH {
+                if let Some(name) = find_in_module_dir(root, &p, target, depth + 1) {
+                    return Some(name);
+                }
+            }
+        } else if modulefile_dirs(&p).iter().any(|d| same_dir(d, target)) {
+            let name = p.strip_prefix(root).unwrap_or(&p).with_extension("");
+            return Some(name.to_string_lossy().into_owned());
+        }
+    }
+    None
+}
+
+// Search MODULEPATH for a module that puts dir in PATH
+pub fn find_available_module(dir: &Path) -> Option<String> {
+    let module_path = env::var_os("MODULEPATH")?;
+    env::split_paths(&module_path).find_map(|root| find_in_module_dir(&root, &root, dir, 0))
+}
+
+// A word Tcl takes as written: braced where that is enough, as nothing but braces
+// and backslashes are special inside them, and otherwise with every character
+// that would be substituted or split on escaped
+fn tcl_word(s: &str) -> String {
+    const SPECIAL: &str = "$[]{}\\\";";
+    if !s.contains(|c: char| c.is_whitespace() || SPECIAL.contains(c)) {
+        return s.to_string();
+    }
+    if !s.contains(['{', '}', '\\']) {
+        return format!("{{{}}}", s);
+    }
+    let mut word = String::new();
+    for c in s.chars() {
+        match c {
+            '\n' => word.push_str("\\n"),
+            c if c.is_whitespace() || SPECIAL.contains(c) => {
+                word.push('\\');
+                word.push(c);
+            }
+            c => word.push(c),
+        }
+    }
+    word
+}
+
+// A Lua string literal
+fn lua_string(s: &str) -> String {
+    let escaped = s
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n");
+    format!("\"{}\"", escaped)
+}
+
+// Write the entries added to and removed from var as modulefile commands, in Tcl
+// (understood by both environment-modules and Lmod) or Lmod's Lua. The commands
+// only add and remove directories, so a change that reorders entries or drops a
+// repeated one has no such commands, and gives None.
+pub fn modulefile_lines(
+    var: &str,
+    old: &[PathBuf],
+    new: &[PathBuf],
+    lua: bool,
+) -> Option<Vec<String>> {
+    let line = |cmd: &str, dir: &Path| {
+        let dir = dir.to_string_lossy();
+        if lua {
+            format!(
+                "{}({}, {})",
+                cmd.replace('-', "_"),
+                lua_string(var),
+                lua_string(&dir)
+            )
+        } else {
+            format!("{} {} {}", cmd, tcl_word(var), tcl_word(&dir))
+        }
+    };
+    let mut lines: Vec<String> = Vec::new();
+    // What loading the module does to old, to check it ends up as new:
+    let mut loaded: Vec<PathBuf> = Vec::new();
+
+    // Anything added before the first of the old entries has to be prepended
+    // in reverse to end up in the same order:
+    let first_kept = new
+        .iter()
+        .position(|p| old.contains(p))
+        .unwrap_or(new.len());
+    for p in new[..first_kept].iter().rev() {
+        lines.push(line("prepend-path", p));
+        loaded.insert(0, p.clone());
+    }
+    let mut removed: Vec<&PathBuf> = Vec::new();
+    for p in old.iter() {
+        // remove-path takes out every copy:
+        if new.contains(p) {
+            loaded.push(p.clone());
+        } else if !removed.contains(&p) {
+            removed.push(p);
+        }
+    }
+    for p in new[first_kept..].iter().filter(|p| !old.contains(p)) {
+        lines.push(line("append-path", p));
+        loaded.push(p.clone());
+    }
+    for p in removed {
+        lines.push(line("remove-path", p));
+    }
+    (loaded == new).then_some(lines)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_path_directives_tcl() {
+        let contents = "#%Module\nprepend-path PATH /opt/gcc/bin\n\
+            append-path --delim : PATH /opt/gcc/sbin:/opt/gcc/libexec\n\
+            prepend-path MANPATH /opt/gcc/share/man\n\
+            prepend-path PATH $root/bin\n";
+        let expected: Vec<PathBuf> = ["/opt/gcc/bin", "/opt/gcc/sbin", "/opt/gcc/libexec"]
+            .iter()
+            .map(PathBuf::from)
+            .collect();
+        assert_eq!(path_directives(contents), expected)
+    }
+
+    #[test]
+    fn test_path_directives_lua() {
+        let contents = "prepend_path(\"PATH\", \"/opt/gcc/bin\")\n\
+            prepend_path(\"MANPATH\", \"/opt/gcc/share/man\")\n\
+            append_path(\"PATH\", pathJoin(root, \"bin\"))\n";
+        assert_eq!(
+            path_directives(contents),
+            vec![PathBuf::from("/opt/gcc/bin")]
+        )
+    }
+
+    #[test]
+    fn test_modulefile_lines() {
+        let old: Vec<PathBuf> = ["/usr/bin", "/bin"].iter().map(PathBuf::from).collect();
+        let new: Vec<PathBuf> = ["/a", "/b c", "/usr/bin", "/z"]
+            .iter()
+            .map(PathBuf::from)
+            .collect();
+        let tcl = [
+            "prepend-path PATH {/b c}",
+            "prepend-path PATH /a",
+            "append-path PATH /z",
+            "remove-path PATH /bin",
+        ];
+        assert_eq!(modulefile_lines("PATH", &old, &new, false).unwrap(), tcl);
+        let lua = modulefile_lines("PATH", &old, &new, true).unwrap();
+        assert_eq!(lua[0], "prepend_path(\"PATH\", \"/b c\")");
+        // Dropping the second /x, or moving /a, can't be written as commands:
+        let repeated: Vec<PathBuf> = ["/x", "/a", "/x"].iter().map(PathBuf::from).collect();
+        assert!(modulefile_lines("PATH", &repeated, &repeated[..2], false).is_none());
+        let moved: Vec<PathBuf> = ["/x", "/a"].iter().map(PathBuf::from).collect();
+        assert!(
+            modulefile_lines("PATH", &moved, &[moved[1].clone(), moved[0].clone()], false)
+                .is_none()
+        )
+    }
+
+    #[test]
+    fn test_tcl_word() {
+        assert_eq!(tcl_word("/opt/bin"), "/opt/bin");
+        assert_eq!(tcl_word("/opt/$x [y]"), "{/opt/$x [y]}");
+        assert_eq!(tcl_word("/a {b}\\$c"), "/a\\ \\{b\\}\\\\\\$c");
+        assert_eq!(lua_string("C:\\\"x\""), "\"C:\\\\\\\"x\\\"\"")
+    }
+
+    #[test]
+    fn test_stow_dir() {
+        let exe_dir = env::current_exe().unwrap().parent().unwrap().to_path_buf();
+        assert!(stow_dir(&exe_dir).is_none())
+    }
+}
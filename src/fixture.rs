@@ -0,0 +1,219 @@
+/*
+Summary of fixture functions
+
+What a run saw of the environment and the file system can be recorded to a file
+and replayed later instead of looking at the real ones, so a problem seen on one
+machine can be reproduced on another. Only what goes through pathops is covered:
+variables, resolving entries, checking for directories and listing executables.
+
+# start recording observations
+record()
+
+# replay the observations in a fixture file instead of making them
+replay(Path) -> Result<()>
+
+# write what was recorded to a fixture file, if recording
+save(Path) -> Result<()>
+
+# check if observations are being recorded or replayed
+active() -> bool
+
+# get an environment variable, recorded or replayed
+var(name: str, real: fn) -> Option<OsString>
+
+# resolve a path, recorded or replayed
+resolve(Path, real: fn) -> io::Result<PathBuf>
+
+# check if a path is a directory, recorded or replayed
+is_dir(Path, real: fn) -> bool
+
+# check if a path is an executable file, recorded or replayed
+is_executable(Path, real: fn) -> bool
+
+# list the executables of a directory, recorded or replayed
+executables(Path, real: fn) -> io::Result<Vec<PathBuf>>
+*/
+
+#[cfg(feature = "cli")]
+use anyhow::{ensure, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+#[cfg(feature = "cli")]
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+#[cfg(feature = "cli")]
+use std::sync::{Mutex, OnceLock};
+
+// Bumped when a fixture can no longer be read by an older or newer version
+#[cfg(feature = "cli")]
+const FIXTURE_VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize, Default)]
+struct Fixture {
+    version: u32,
+    // Variables as read, None if unset
+    vars: BTreeMap<String, Option<String>>,
+    // Resolved paths, None if they couldn't be resolved
+    resolved: BTreeMap<PathBuf, Option<PathBuf>>,
+    dirs: BTreeMap<PathBuf, bool>,
+    executable_files: BTreeMap<PathBuf, bool>,
+    // Executables in a directory, None if it couldn't be read
+    executables: BTreeMap<PathBuf, Option<Vec<PathBuf>>>,
+}
+
+#[cfg(feature = "cli")]
+enum Mode {
+    Record(Mutex<Fixture>),
+    Replay(Fixture),
+}
+
+#[cfg(feature = "cli")]
+static MODE: OnceLock<Mode> = OnceLock::new();
+
+#[cfg(feature = "cli")]
+pub fn record() {
+    let fixture = Fixture {
+        version: FIXTURE_VERSION,
+        ..Default::default()
+    };
+    let _ = MODE.set(Mode::Record(Mutex::new(fixture)));
+}
+
+#[cfg(feature = "cli")]
+pub fn replay(file: &Path) -> Result<()> {
+    let contents =
+        fs::read_to_string(file).with_context(|| format!("unable to read '{}'", file.display()))?;
+    let fixture: Fixture = serde_json::from_str(&contents)
+        .with_context(|| format!("'{}' is not a valid fixture", file.display()))?;
+    ensure!(
+        fixture.version == FIXTURE_VERSION,
+        "'{}' is a fixture of version {}, only version {} can be replayed",
+        file.display(),
+        fixture.version,
+        FIXTURE_VERSION
+    );
+    let _ = MODE.set(Mode::Replay(fixture));
+    Ok(())
+}
+
+#[cfg(feature = "cli")]
+pub fn active() -> bool {
+    MODE.get().is_some()
+}
+
+// Only the binary records and replays, the library alone always asks the system
+#[cfg(not(feature = "cli"))]
+pub fn active() -> bool {
+    false
+}
+
+#[cfg(feature = "cli")]
+pub fn save(file: &Path) -> Result<()> {
+    let Some(Mode::Record(fixture)) = MODE.get() else {
+        return Ok(());
+    };
+    let fixture = fixture.lock().unwrap();
+    fs::write(file, serde_json::to_string_pretty(&*fixture)?)
+        .with_context(|| format!("unable to write '{}'", file.display()))
+}
+
+// Look up an observation when replaying, or make it and keep it when recording.
+// Anything not in a replayed fixture is taken as missing.
+#[cfg(feature = "cli")]
+fn observe<K: Ord + Clone, V: Clone>(
+    map: impl Fn(&mut Fixture) -> &mut BTreeMap<K, V>,
+    replayed: impl Fn(&Fixture) -> Option<V>,
+    key: &K,
+    real: impl FnOnce() -> V,
+    missing: V,
+) -> V {
+    match MODE.get() {
+        Some(Mode::Replay(fixture)) => replayed(fixture).unwrap_or(missing),
+        Some(Mode::Record(fixture)) => {
+            let value = real();
+            map(&mut fixture.lock().unwrap()).insert(key.clone(), value.clone());
+            value
+        }
+        None => real(),
+    }
+}
+
+#[cfg(not(feature = "cli"))]
+fn observe<K, V>(
+    _map: impl Fn(&mut Fixture) -> &mut BTreeMap<K, V>,
+    _replayed: impl Fn(&Fixture) -> Option<V>,
+    _key: &K,
+    real: impl FnOnce() -> V,
+    _missing: V,
+) -> V {
+    real()
+}
+
+fn not_found() -> io::Error {
+    io::Error::new(io::ErrorKind::NotFound, "not in the replayed fixture")
+}
+
+// Fixtures keep variables as text, so only when recording or replaying is a value
+// that isn't valid Unicode changed
+pub fn var(name: &str, real: impl FnOnce() -> Option<OsString>) -> Option<OsString> {
+    if !active() {
+        return real();
+    }
+    let value = observe(
+        |f| &mut f.vars,
+        |f| f.vars.get(name).cloned(),
+        &name.to_string(),
+        || real().map(|v| v.to_string_lossy().into_owned()),
+        None,
+    );
+    value.map(OsString::from)
+}
+
+pub fn resolve(path: &Path, real: impl FnOnce() -> io::Result<PathBuf>) -> io::Result<PathBuf> {
+    let mut error = None;
+    let value = observe(
+        |f| &mut f.resolved,
+        |f| f.resolved.get(path).cloned(),
+        &path.to_path_buf(),
+        || real().map_err(|e| error = Some(e)).ok(),
+        None,
+    );
+    value.ok_or_else(|| error.unwrap_or_else(not_found))
+}
+
+pub fn is_dir(path: &Path, real: impl FnOnce() -> bool) -> bool {
+    observe(
+        |f| &mut f.dirs,
+        |f| f.dirs.get(path).copied(),
+        &path.to_path_buf(),
+        real,
+        false,
+    )
+}
+
+pub fn is_executable(path: &Path, real: impl FnOnce() -> bool) -> bool {
+    observe(
+        |f| &mut f.executable_files,
+        |f| f.executable_files.get(path).copied(),
+        &path.to_path_buf(),
+        real,
+        false,
+    )
+}
+
+pub fn executables(
+    path: &Path,
+    real: impl FnOnce() -> io::Result<Vec<PathBuf>>,
+) -> io::Result<Vec<PathBuf>> {
+    let mut error = None;
+    let value = observe(
+        |f| &mut f.executables,
+        |f| f.executables.get(path).cloned(),
+        &path.to_path_buf(),
+        || real().map_err(|e| error = Some(e)).ok(),
+        None,
+    );
+    value.ok_or_else(|| error.unwrap_or_else(not_found))
+}
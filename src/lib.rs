@@ -0,0 +1,53 @@
+//! Check and change PATH, and other path-like variables, from Rust.
+//!
+//! [`PathSet`] holds the entries of a variable and does what the pathaid
+//! commands validate, dedup, append, prepend and remove do, with the same code.
+//! Only validate has more to say, about packages installed on the system. The
+//! functions in [`pathops`] do the same for path strings, and everything that
+//! fails does so with a [`pathops::Error`].
+//!
+//! ```no_run
+//! let path = pathaid::PathSet::from_env()?;
+//! for issue in path.validate()? {
+//!     println!("{}", issue.message);
+//! }
+//! # Ok::<(), pathaid::Error>(())
+//! ```
+//!
+//! The command line is the default `cli` feature, turn default features off to
+//! build only the library without its dependencies.
+
+mod fixture;
+pub mod pathops;
+mod pathset;
+
+pub use pathops::{Error, Issue, Result};
+pub use pathset::PathSet;
+
+// The rest is what the pathaid binary is made of, and with it the dependencies
+// only a command line needs
+#[cfg(feature = "cli")]
+mod audit;
+#[cfg(feature = "cli")]
+#[doc(hidden)]
+pub mod cli;
+#[cfg(feature = "cli")]
+mod daemon;
+#[cfg(feature = "cli")]
+mod envmodules;
+#[cfg(feature = "cli")]
+mod lint;
+#[cfg(feature = "cli")]
+mod packages;
+#[cfg(feature = "cli")]
+mod query;
+#[cfg(feature = "cli")]
+mod rcfile;
+#[cfg(feature = "cli")]
+mod rootfs;
+#[cfg(feature = "cli")]
+mod rpc;
+#[cfg(feature = "cli")]
+mod state;
+#[cfg(feature = "cli")]
+mod tui;
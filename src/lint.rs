@@ -0,0 +1,940 @@
+/*
+Summary of lint functions
+
+Look for changes to PATH in shell scripts without running them. Commands are
+split into words much like a shell does, but nothing is expanded, so only what
+is written out can be checked:
+
+  overwrite     PATH is set without $PATH, dropping everything before it
+  duplicate     a directory is added that an earlier line already added
+  cwd           an empty entry, which means the current directory
+  unquoted      an expansion in an export that some shells split on spaces
+  loop-prepend  prepending in a loop, putting the last directory first
+
+# find problems with PATH changes in a shell script
+lint_script(contents: str) -> Vec<Finding>
+
+CI configs of GitHub Actions and GitLab CI are read line by line, without a full
+YAML parser, to check their scripts the same way and also find:
+
+  step-only     PATH exported in a GitHub step, which later steps don't see
+  github-env    PATH written to $GITHUB_ENV instead of adding to $GITHUB_PATH
+  ci-variable   PATH set in env: or variables:, where $PATH isn't the runner's
+  repeated      the same directory added in several jobs
+
+# find problems with PATH changes in a GitHub Actions workflow or GitLab CI config
+lint_ci(contents: str) -> Vec<Finding>
+
+Makefiles and justfiles have their recipes checked like scripts, and also:
+
+  recipe-path   PATH changed at the end of a recipe line, while each line runs
+                in a shell of its own
+  tool          a recipe runs a tool that isn't in the declared toolchain, when
+                one is given, nor a shell builtin or POSIX utility
+
+# find problems with PATH and tools in a Makefile, or a justfile if just
+lint_build(contents: str, just: bool, requires: Option<Vec<String>>) -> Vec<Finding>
+*/
+
+use crate::pathops;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+
+#[derive(Serialize, Debug)]
+pub struct Finding {
+    // Counted from 1, like editors do
+    pub line: usize,
+    pub code: &'static str,
+    pub message: String,
+}
+
+// A word of a command with the quotes taken away
+#[derive(Debug, Default)]
+struct Word {
+    text: String,
+    // If anything starting with $ or ` was outside double quotes
+    unquoted_expansion: bool,
+}
+
+// Builtins that take assignments as arguments
+const DECLARATIONS: [&str; 5] = ["export", "declare", "typeset", "readonly", "local"];
+
+// Words that can come before a command on the same line
+const KEYWORDS: [&str; 7] = ["if", "elif", "then", "else", "do", "{", "!"];
+
+// Split a line into commands and those into words, ignoring comments. Words keep
+// $(...) and ${...} whole, as separators inside them are not the line's own.
+fn commands(line: &str) -> Vec<Vec<Word>> {
+    let mut commands: Vec<Vec<Word>> = Vec::new();
+    let mut words: Vec<Word> = Vec::new();
+    let mut word: Option<Word> = None;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => words.extend(word.take()),
+            '#' if word.is_none() => break,
+            ';' | '&' | '|' => {
+                words.extend(word.take());
+                chars.next_if(|&n| n == c);
+                if !words.is_empty() {
+                    commands.push(std::mem::take(&mut words));
+                }
+            }
+            '\'' => {
+                let w = word.get_or_insert_with(Word::default);
+                for ch in chars.by_ref() {
+                    if ch == '\'' {
+                        break;
+                    }
+                    w.text.push(ch);
+                }
+            }
+            '"' => {
+                let w = word.get_or_insert_with(Word::default);
+                while let Some(ch) = chars.next() {
+                    match ch {
+                        '"' => break,
+                        '\\' => w.text.extend(chars.next()),
+                        _ => w.text.push(ch),
+                    }
+                }
+            }
+            '\\' => word
+                .get_or_insert_with(Word::default)
+                .text
+                .extend(chars.next()),
+            '$' | '`' => {
+                let w = word.get_or_insert_with(Word::default);
+                w.unquoted_expansion = true;
+                w.text.push(c);
+                // Keep a substitution or braced expansion together:
+                let (open, close) = match chars.peek() {
+                    Some('(') => ('(', ')'),
+                    Some('{') => ('{', '}'),
+                    _ if c == '`' => ('`', '`'),
+                    _ => continue,
+                };
+                let mut depth = 0;
+                if c != '`' {
+                    w.text.extend(chars.next());
+                    depth = 1;
+                }
+                for ch in chars.by_ref() {
+                    w.text.push(ch);
+                    if ch == close && (open == close || depth == 1) {
+                        break;
+                    } else if ch == close {
+                        depth -= 1;
+                    } else if ch == open {
+                        depth += 1;
+                    }
+                }
+            }
+            _ => word.get_or_insert_with(Word::default).text.push(c),
+        }
+    }
+    words.extend(word.take());
+    if !words.is_empty() {
+        commands.push(words);
+    }
+    commands
+}
+
+// The ways of writing $PATH and of adding to it only when set
+fn normalize(value: &str) -> String {
+    value
+        .replace("${PATH:+:$PATH}", ":$PATH")
+        .replace("${PATH:+:${PATH}}", ":$PATH")
+        .replace("${PATH:+$PATH:}", "$PATH:")
+        .replace("${PATH:+${PATH}:}", "$PATH:")
+        .replace("${PATH}", "$PATH")
+}
+
+// Directories are compared as entries are, so /opt/bin/ was added with /opt/bin
+fn dir_key(dir: &str) -> String {
+    pathops::normalize(Path::new(dir))
+        .to_string_lossy()
+        .into_owned()
+}
+
+// Follows the changes to PATH through a script
+#[derive(Default)]
+struct Linter {
+    findings: Vec<Finding>,
+    // Where each directory was first added
+    added: HashMap<String, usize>,
+    // How many loops the current line is in
+    loops: usize,
+}
+
+impl Linter {
+    fn finding(&mut self, line: usize, code: &'static str, message: String) {
+        self.findings.push(Finding {
+            line,
+            code,
+            message,
+        });
+    }
+
+    fn assignment(
+        &mut self,
+        line: usize,
+        value: &str,
+        appending: bool,
+        exported: bool,
+        word: &Word,
+    ) {
+        let value = normalize(value);
+        let parts: Vec<&str> = value.split(':').collect();
+        let first_ref = parts.iter().position(|p| *p == "$PATH");
+        if first_ref.is_none() && !appending {
+            self.finding(
+                line,
+                "overwrite",
+                format!(
+                    "PATH is set to '{}' without $PATH, dropping whatever it had",
+                    value
+                ),
+            );
+        }
+        // An empty part is the current directory, unless it is the separator
+        // written first by += or the start of an otherwise empty value:
+        let empty = parts
+            .iter()
+            .enumerate()
+            .any(|(i, p)| p.is_empty() && !(appending && i == 0) && parts.len() > 1);
+        if empty {
+            self.finding(
+                line,
+                "cwd",
+                String::from("an empty entry refers to the current directory"),
+            );
+        }
+        if exported && word.unquoted_expansion {
+            self.finding(
+                line,
+                "unquoted",
+                String::from(
+                    "the value isn't quoted and some shells split it on spaces after export, \
+                     quote it or assign before exporting",
+                ),
+            );
+        }
+        if self.loops > 0 && first_ref.is_some_and(|i| i > 0) {
+            self.finding(
+                line,
+                "loop-prepend",
+                String::from("prepending in a loop puts the directory of the last iteration first"),
+            );
+        }
+        for part in parts.iter().filter(|p| !p.is_empty() && **p != "$PATH") {
+            match self.added.get(&dir_key(part)) {
+                // The same line in a loop is expected to run more than once:
+                Some(&first) if first != line => self.finding(
+                    line,
+                    "duplicate",
+                    format!("'{}' was already added on line {}", part, first),
+                ),
+                Some(_) => {}
+                None => {
+                    self.added.insert(dir_key(part), line);
+                }
+            }
+        }
+    }
+
+    // Check a command, telling if it changes PATH
+    fn command(&mut self, line: usize, words: &[Word]) -> bool {
+        let start = words
+            .iter()
+            .position(|w| !KEYWORDS.contains(&w.text.as_str()))
+            .unwrap_or(words.len());
+        let words = &words[start..];
+        let Some(first) = words.first() else {
+            return false;
+        };
+        match first.text.as_str() {
+            "for" | "while" | "until" | "select" => self.loops += 1,
+            "done" => self.loops = self.loops.saturating_sub(1),
+            _ => {}
+        }
+        // Assignments come first, or as arguments to export and the like:
+        let exported = DECLARATIONS.contains(&first.text.as_str());
+        // Assignments before a command only set the variable for that command:
+        if !exported && words.iter().any(|w| !is_assignment(&w.text)) {
+            return false;
+        }
+        let assignments = words
+            .iter()
+            .skip(usize::from(exported))
+            .filter(|w| !w.text.starts_with('-'));
+        let mut changed = false;
+        for word in assignments {
+            let (value, appending) = if let Some(v) = word.text.strip_prefix("PATH=") {
+                (v, false)
+            } else if let Some(v) = word.text.strip_prefix("PATH+=") {
+                (v, true)
+            } else if exported || word.text.contains('=') {
+                continue;
+            } else {
+                break;
+            };
+            self.assignment(line, value, appending, exported, word);
+            changed = true;
+        }
+        changed
+    }
+}
+
+pub fn lint_script(contents: &str) -> Vec<Finding> {
+    let mut linter = Linter::default();
+    for (i, line) in contents.lines().enumerate() {
+        for words in commands(line) {
+            let _ = linter.command(i + 1, &words);
+        }
+    }
+    linter.findings
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Provider {
+    GitHub,
+    GitLab,
+}
+
+// Keys holding shell commands, run one step at a time in GitHub and as one
+// script per job in GitLab
+const SCRIPT_KEYS: [&str; 4] = ["run", "script", "before_script", "after_script"];
+
+// A line of YAML split into a key and a value where it has a key
+struct YamlLine<'a> {
+    // Where a leading "- " is, or the content if there is none
+    dash: usize,
+    item: bool,
+    // Where the content after any "- " starts
+    indent: usize,
+    key: Option<&'a str>,
+    value: &'a str,
+}
+
+fn yaml_line(line: &str) -> YamlLine<'_> {
+    let trimmed = line.trim_start();
+    let dash = line.len() - trimmed.len();
+    let item = trimmed == "-" || trimmed.starts_with("- ");
+    let content = if item {
+        trimmed[1..].trim_start()
+    } else {
+        trimmed
+    };
+    let indent = line.len() - content.len();
+    // A key is a plain word followed by a colon and a space or the end:
+    let colon = content.find(':').filter(|&i| {
+        let key = &content[..i];
+        !key.is_empty()
+            && !key.contains(|c: char| c.is_whitespace() || "\"'{[".contains(c))
+            && content[i + 1..].chars().next().is_none_or(|c| c == ' ')
+    });
+    let (key, value) = match colon {
+        Some(i) => (Some(&content[..i]), content[i + 1..].trim()),
+        None => (None, content.trim()),
+    };
+    YamlLine {
+        dash,
+        item,
+        indent,
+        key,
+        value,
+    }
+}
+
+// The text of a scalar written on one line, without quotes or a trailing comment
+fn scalar(value: &str) -> String {
+    if let Some(inner) = value.strip_prefix('\'').and_then(|v| v.strip_suffix('\'')) {
+        return inner.replace("''", "'");
+    }
+    if let Some(inner) = value.strip_prefix('"').and_then(|v| v.strip_suffix('"')) {
+        return inner.replace("\\\"", "\"").replace("\\\\", "\\");
+    }
+    match value.find(" #") {
+        Some(i) => value[..i].trim_end().to_string(),
+        None => value.to_string(),
+    }
+}
+
+// If a value starts a block scalar, with the lines below it as the text
+fn is_block(value: &str) -> bool {
+    value.starts_with(['|', '>']) && value[1..].chars().all(|c| "+-0123456789".contains(c))
+}
+
+// Follows the jobs of a CI config and the shells their scripts run in
+struct Ci {
+    provider: Provider,
+    findings: Vec<Finding>,
+    // The directories each job adds, with the line each was first added on
+    jobs: BTreeMap<String, HashMap<String, usize>>,
+    // The shell commands run in, with the job and the line it started on
+    shell: Linter,
+    current: Option<(String, usize)>,
+    // If step-only was already reported for the current shell
+    step_only: bool,
+}
+
+impl Ci {
+    // Keep what a shell found once the next one starts
+    fn flush(&mut self) {
+        let shell = std::mem::take(&mut self.shell);
+        self.findings.extend(shell.findings);
+        if let Some((job, _)) = self.current.take() {
+            let added = self.jobs.entry(job).or_default();
+            for (dir, line) in shell.added {
+                added.entry(dir).or_insert(line);
+            }
+        }
+        self.step_only = false;
+    }
+
+    fn shell_line(&mut self, job: &str, start: usize, line: usize, text: &str) {
+        let start = match self.provider {
+            Provider::GitHub => start,
+            Provider::GitLab => 0,
+        };
+        if self.current.as_ref() != Some(&(job.to_string(), start)) {
+            self.flush();
+            self.current = Some((job.to_string(), start));
+        }
+        for words in commands(text) {
+            let changed = self.shell.command(line, &words);
+            if self.provider == Provider::GitHub {
+                if changed && !self.step_only {
+                    self.step_only = true;
+                    self.shell.finding(
+                        line,
+                        "step-only",
+                        String::from(
+                            "a change to PATH only lasts for this step, append the directory \
+                             to $GITHUB_PATH for later steps to see it",
+                        ),
+                    );
+                }
+                self.github_file(job, line, &words);
+            }
+        }
+    }
+
+    // Check what echo writes to $GITHUB_PATH or $GITHUB_ENV
+    fn github_file(&mut self, job: &str, line: usize, words: &[Word]) {
+        if words.first().is_none_or(|w| w.text != "echo") {
+            return;
+        }
+        let redirect = words.iter().enumerate().find_map(|(i, w)| {
+            let rest = w.text.strip_prefix('>')?;
+            let rest = rest.strip_prefix('>').unwrap_or(rest);
+            let file = match rest {
+                "" => words.get(i + 1)?.text.as_str(),
+                _ => rest,
+            };
+            Some((i, file.trim_start_matches('$').trim_matches(['{', '}'])))
+        });
+        let Some((end, file)) = redirect else {
+            return;
+        };
+        let args: Vec<&str> = words[1..end]
+            .iter()
+            .map(|w| w.text.as_str())
+            .filter(|a| !a.starts_with('-'))
+            .collect();
+        match file {
+            "GITHUB_PATH" => {
+                let dir = dir_key(&args.join(" "));
+                let added = self.jobs.entry(job.to_string()).or_default();
+                match added.get(&dir) {
+                    Some(&first) if first != line => self.findings.push(Finding {
+                        line,
+                        code: "duplicate",
+                        message: format!("'{}' was already added on line {}", dir, first),
+                    }),
+                    Some(_) => {}
+                    None => {
+                        added.insert(dir, line);
+                    }
+                }
+            }
+            "GITHUB_ENV" if args.iter().any(|a| a.starts_with("PATH=")) => {
+                self.findings.push(Finding {
+                    line,
+                    code: "github-env",
+                    message: String::from(
+                        "PATH written to $GITHUB_ENV replaces it for later steps, \
+                         append the directory to $GITHUB_PATH instead",
+                    ),
+                })
+            }
+            _ => {}
+        }
+    }
+
+    fn variable(&mut self, line: usize) {
+        let message = match self.provider {
+            Provider::GitHub => {
+                "PATH set in env: replaces the runner's PATH as $PATH isn't expanded there, \
+                 append the directory to $GITHUB_PATH in a step instead"
+            }
+            Provider::GitLab => {
+                "PATH set in variables: is expanded from CI variables and not the runner's \
+                 environment, export it in before_script instead"
+            }
+        };
+        self.findings.push(Finding {
+            line,
+            code: "ci-variable",
+            message: String::from(message),
+        });
+    }
+
+    // Report directories added by more than one job, at the first line adding them
+    fn repeated(&mut self) {
+        let mut dirs: BTreeMap<&String, Vec<(&String, usize)>> = BTreeMap::new();
+        for (job, added) in self.jobs.iter() {
+            for (dir, &line) in added.iter() {
+                dirs.entry(dir).or_default().push((job, line));
+            }
+        }
+        let hint = match self.provider {
+            Provider::GitHub => "a composite action or reusable workflow can add it once",
+            Provider::GitLab => "add it once in before_script under default:",
+        };
+        for (dir, found) in dirs.iter().filter(|(_, found)| found.len() > 1) {
+            let names: Vec<&str> = found.iter().map(|(job, _)| job.as_str()).collect();
+            self.findings.push(Finding {
+                line: found.iter().map(|(_, line)| *line).min().unwrap_or(0),
+                code: "repeated",
+                message: format!(
+                    "'{}' is added in {} jobs ({}), {}",
+                    dir,
+                    found.len(),
+                    names.join(", "),
+                    hint
+                ),
+            });
+        }
+    }
+}
+
+pub fn lint_ci(contents: &str) -> Vec<Finding> {
+    let top_level = |key: &str| {
+        contents
+            .lines()
+            .any(|l| l.starts_with(key) && l[key.len()..].starts_with(':'))
+    };
+    let provider = if top_level("jobs") || top_level("on") {
+        Provider::GitHub
+    } else {
+        Provider::GitLab
+    };
+    let mut ci = Ci {
+        provider,
+        findings: Vec::new(),
+        jobs: BTreeMap::new(),
+        shell: Linter::default(),
+        current: None,
+        step_only: false,
+    };
+    // The keys the current line is nested in, with their indentation
+    let mut keys: Vec<(usize, String)> = Vec::new();
+    // The indentation a block scalar is nested under, and if it holds commands
+    let mut block: Option<(usize, bool)> = None;
+    // The indentation of a script key whose commands are listed below it
+    let mut script_list: Option<usize> = None;
+    // The line the current script key is on
+    let mut script_start = 0;
+    for (i, text) in contents.lines().enumerate() {
+        let number = i + 1;
+        let job = match provider {
+            Provider::GitHub if keys.first().is_some_and(|(_, k)| k == "jobs") => {
+                keys.get(1).map(|(_, k)| k.clone())
+            }
+            Provider::GitHub => None,
+            Provider::GitLab => keys.first().map(|(_, k)| k.clone()),
+        };
+        let indent = text.len() - text.trim_start().len();
+        if let Some((parent, commands)) = block {
+            if text.trim().is_empty() || indent > parent {
+                if let (true, Some(job)) = (commands, &job) {
+                    ci.shell_line(job, script_start, number, text.trim());
+                }
+                continue;
+            }
+            block = None;
+        }
+        if text.trim().is_empty() || text.trim_start().starts_with('#') {
+            continue;
+        }
+        let line = yaml_line(text);
+        if let Some(key_indent) = script_list {
+            if line.item && line.dash >= key_indent {
+                if is_block(line.value) && line.key.is_none() {
+                    block = Some((line.dash, true));
+                } else if let Some(job) = &job {
+                    ci.shell_line(job, script_start, number, &scalar(line.value));
+                }
+                continue;
+            }
+            script_list = None;
+        }
+        let Some(key) = line.key else {
+            continue;
+        };
+        while keys.last().is_some_and(|(i, _)| *i >= line.indent) {
+            keys.pop();
+        }
+        let parent = keys.last().map(|(_, k)| k.as_str());
+        if key == "PATH" && matches!(parent, Some("env" | "variables")) {
+            ci.variable(number);
+        }
+        keys.push((line.indent, key.to_string()));
+        let commands = SCRIPT_KEYS.contains(&key);
+        if commands {
+            script_start = number;
+        }
+        if is_block(line.value) {
+            block = Some((line.indent, commands));
+        } else if commands && line.value.is_empty() {
+            script_list = Some(line.indent);
+        } else if commands {
+            // The job is only known once the key is on the stack:
+            let job = match provider {
+                Provider::GitHub => keys.get(1).filter(|_| keys[0].1 == "jobs"),
+                Provider::GitLab => keys.first(),
+            };
+            if let Some((_, job)) = job.cloned() {
+                ci.shell_line(&job, script_start, number, &scalar(line.value));
+            }
+        }
+    }
+    ci.flush();
+    ci.repeated();
+    ci.findings.sort_by_key(|f| f.line);
+    ci.findings
+}
+
+// Commands any build can count on: shell builtins and POSIX utilities
+const BASE_TOOLS: [&str; 66] = [
+    ".", ":", "[", "alias", "awk", "basename", "break", "cat", "cd", "chmod", "cmp", "command",
+    "continue", "cp", "cut", "date", "diff", "dirname", "echo", "env", "eval", "exec", "exit",
+    "export", "expr", "false", "find", "grep", "head", "id", "kill", "ln", "local", "ls", "mkdir",
+    "mv", "od", "printf", "pwd", "read", "readonly", "return", "rm", "rmdir", "sed", "set", "sh",
+    "shift", "sleep", "sort", "tail", "tee", "test", "touch", "tr", "trap", "true", "type",
+    "umask", "uname", "unset", "wait", "wc", "xargs", "make", "just",
+];
+
+// Words of compound commands, which aren't tools
+const CONTROL: [&str; 8] = ["for", "while", "until", "case", "done", "fi", "esac", "}"];
+
+// How a Makefile or justfile line sets PATH for the recipes, giving the value and
+// if it adds to what was there
+fn build_variable(line: &str, just: bool) -> Option<(&str, bool)> {
+    let rest = line.strip_prefix("export ").unwrap_or(line);
+    let rest = rest.strip_prefix("override ").unwrap_or(rest).trim_start();
+    let rest = rest.strip_prefix("PATH")?.trim_start();
+    let ops: &[&str] = if just {
+        &[":="]
+    } else {
+        &["::=", ":=", "?=", "+=", "!=", "="]
+    };
+    let op = ops.iter().find(|op| rest.starts_with(**op))?;
+    Some((rest[op.len()..].trim(), *op == "+="))
+}
+
+// If a value refers to PATH, as make or just writes it
+fn keeps_path(value: &str, just: bool) -> bool {
+    if just {
+        [
+            "env_var('PATH')",
+            "env_var(\"PATH\")",
+            "env('PATH'",
+            "env(\"PATH\"",
+        ]
+        .iter()
+        .any(|r| value.contains(r))
+    } else {
+        value.contains("$(PATH)") || value.contains("${PATH}")
+    }
+}
+
+// If a word sets a variable, or adds to it with +=
+fn is_assignment(word: &str) -> bool {
+    word.split_once('=').is_some_and(|(name, _)| {
+        let name = name.strip_suffix('+').unwrap_or(name);
+        !name.is_empty() && name.chars().all(|c| c.is_alphanumeric() || c == '_')
+    })
+}
+
+// How the lines of a recipe body are run
+#[derive(Clone, Copy, PartialEq)]
+enum Body {
+    Lines,
+    // By a shell named on a #! line, all in one
+    Shell,
+    // By some other interpreter named on a #! line
+    Other,
+}
+
+// Follows the recipes of a Makefile or justfile
+struct Build<'a> {
+    linter: Linter,
+    just: bool,
+    requires: Option<&'a [String]>,
+    // Tools already reported, each at its first use only
+    reported: Vec<String>,
+}
+
+impl Build<'_> {
+    // Check a line of a recipe, which runs in a shell of its own unless shared
+    fn recipe_line(&mut self, line: usize, text: &str, shared: bool) {
+        let text = text.trim_start_matches(['@', '-', '+']);
+        // In make $$ is the shell's $, and $(PATH) the PATH make started with:
+        let text = if self.just {
+            text.to_string()
+        } else {
+            text.replace("$(PATH)", "$PATH").replace("$$", "$")
+        };
+        let commands = commands(&text);
+        for (i, words) in commands.iter().enumerate() {
+            let changed = self.linter.command(line, words);
+            if changed && !shared && i + 1 == commands.len() {
+                self.linter.finding(
+                    line,
+                    "recipe-path",
+                    String::from(
+                        "PATH is changed at the end of the line, but each line of a recipe \
+                         runs in a shell of its own, so the next line won't see it",
+                    ),
+                );
+            }
+            self.tool(line, words);
+        }
+    }
+
+    fn tool(&mut self, line: usize, words: &[Word]) {
+        let Some(requires) = self.requires else {
+            return;
+        };
+        let name = words
+            .iter()
+            .map(|w| w.text.as_str())
+            .find(|w| !KEYWORDS.contains(w) && !is_assignment(w));
+        let Some(name) = name else {
+            return;
+        };
+        // Variables, templates and tools of the repo itself are left alone:
+        let known = BASE_TOOLS.contains(&name)
+            || CONTROL.contains(&name)
+            || name.starts_with(['$', '{'])
+            || name.contains('/')
+            || requires.iter().any(|r| r == name);
+        if known || self.reported.iter().any(|r| r == name) {
+            return;
+        }
+        self.reported.push(name.to_string());
+        self.linter.finding(
+            line,
+            "tool",
+            format!(
+                "'{}' isn't in the declared toolchain ({}), so the build depends on whatever PATH has",
+                name,
+                requires.join(", ")
+            ),
+        );
+    }
+}
+
+pub fn lint_build(contents: &str, just: bool, requires: Option<&[String]>) -> Vec<Finding> {
+    let mut build = Build {
+        linter: Linter::default(),
+        just,
+        requires,
+        reported: Vec::new(),
+    };
+    // With .ONESHELL: make runs all lines of a recipe in one shell
+    let one_shell = !just && contents.lines().any(|l| l.trim() == ".ONESHELL:");
+    let mut body: Option<Body> = None;
+    // A recipe line ending in \ so far, with the line it started on
+    let mut continued: Option<(usize, String)> = None;
+    for (i, text) in contents.lines().enumerate() {
+        let number = i + 1;
+        let recipe = if just {
+            text.starts_with([' ', '\t']) && !text.trim().is_empty()
+        } else {
+            text.starts_with('\t')
+        };
+        if !recipe {
+            body = None;
+            continued = None;
+            let overwrite = build_variable(text, just)
+                .filter(|(value, appending)| !appending && !keeps_path(value, just));
+            if let Some((value, _)) = overwrite {
+                build.linter.finding(
+                    number,
+                    "overwrite",
+                    format!(
+                        "PATH is set to {} without the PATH the build was started with",
+                        value
+                    ),
+                );
+            }
+            continue;
+        }
+        let text = text.trim();
+        if body.is_none() {
+            let shebang = text.strip_prefix("#!").filter(|_| just);
+            body = Some(match shebang {
+                Some(s) if ["sh", "bash", "zsh"].iter().any(|sh| s.ends_with(sh)) => Body::Shell,
+                Some(_) => Body::Other,
+                None => Body::Lines,
+            });
+            if shebang.is_some() {
+                continue;
+            }
+        }
+        if body == Some(Body::Other) {
+            continue;
+        }
+        let (start, line) = match continued.take() {
+            Some((start, before)) => (start, format!("{} {}", before, text)),
+            None => (number, text.to_string()),
+        };
+        if let Some(rest) = line.strip_suffix('\\') {
+            continued = Some((start, rest.to_string()));
+            continue;
+        }
+        build.recipe_line(start, &line, one_shell || body == Some(Body::Shell));
+    }
+    build.linter.findings.sort_by_key(|f| f.line);
+    build.linter.findings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn codes(script: &str) -> Vec<(usize, &'static str)> {
+        lint_script(script)
+            .iter()
+            .map(|f| (f.line, f.code))
+            .collect()
+    }
+
+    #[test]
+    fn test_lint_script() {
+        let script = r#"#!/bin/sh
+PATH="$HOME/bin:$PATH"   # fine
+export PATH=/usr/bin:/bin
+PATH=$PATH:$HOME/bin
+export PATH=$HOME/.cargo/bin:$PATH
+for d in /opt/*/bin; do PATH="$d:$PATH"; done
+PATH="/x${PATH:+:$PATH}"; PATH=":$PATH"
+PATH=/opt/tool/bin tool --version
+PATH+=":/opt/tool/bin"
+PATH="$PATH:/opt/tool//bin/"
+"#;
+        assert_eq!(
+            codes(script),
+            vec![
+                (3, "overwrite"),
+                (4, "duplicate"),
+                (5, "unquoted"),
+                (6, "loop-prepend"),
+                (7, "cwd"),
+                (10, "duplicate"),
+            ]
+        )
+    }
+
+    #[test]
+    fn test_lint_ci() {
+        let codes = |config: &str| -> Vec<(usize, &'static str)> {
+            lint_ci(config).iter().map(|f| (f.line, f.code)).collect()
+        };
+        let github = r#"on: push
+jobs:
+  build:
+    env:
+      PATH: /opt/bin
+    steps:
+      - run: echo "$HOME/.local/bin" >> $GITHUB_PATH
+      - run: |
+          export PATH="$HOME/tool:$PATH"
+          echo "PATH=$PATH" >> "$GITHUB_ENV"
+  test:
+    steps:
+      - run: echo "$HOME/.local/bin" >> "$GITHUB_PATH"
+"#;
+        assert_eq!(
+            codes(github),
+            vec![
+                (5, "ci-variable"),
+                (7, "repeated"),
+                (9, "step-only"),
+                (10, "github-env"),
+            ]
+        );
+        let gitlab = r#"variables:
+  PATH: "/opt/bin:$PATH"
+build:
+  before_script:
+    - export PATH="$HOME/bin:$PATH"
+  script:
+  - export PATH=/usr/bin
+  - |
+    export PATH="$HOME/bin:$PATH"
+"#;
+        assert_eq!(
+            codes(gitlab),
+            vec![(2, "ci-variable"), (7, "overwrite"), (9, "duplicate")]
+        )
+    }
+
+    #[test]
+    fn test_lint_build() {
+        let makefile = "PATH := /usr/bin
+export PATH := $(CURDIR)/bin:$(PATH)
+
+build:
+\texport PATH=$$HOME/go/bin:$$PATH
+\t@PATH=\"$$PWD/bin:$$PATH\" protoc --version && go build ./...
+\tnode \\
+\t  script.js
+";
+        let requires = vec![String::from("go"), String::from("protoc")];
+        let findings: Vec<(usize, &'static str)> = lint_build(makefile, false, Some(&requires))
+            .iter()
+            .map(|f| (f.line, f.code))
+            .collect();
+        assert_eq!(
+            findings,
+            vec![
+                (1, "overwrite"),
+                (5, "unquoted"),
+                (5, "recipe-path"),
+                (7, "tool")
+            ]
+        );
+        let justfile = "export PATH := \"bin:\" + env_var('PATH')
+
+run:
+    #!/usr/bin/env python3
+    import os
+";
+        assert!(lint_build(justfile, true, Some(&requires)).is_empty())
+    }
+
+    #[test]
+    fn test_commands() {
+        let words = commands(r#"a "b c" 'd;e' $(f; g) # h"#);
+        let texts: Vec<&str> = words[0].iter().map(|w| w.text.as_str()).collect();
+        assert_eq!(texts, vec!["a", "b c", "d;e", "$(f; g)"]);
+        assert!(!words[0][1].unquoted_expansion);
+        assert!(words[0][3].unquoted_expansion)
+    }
+}
@@ -0,0 +1,406 @@
+/*
+Summary of packages functions
+
+Directories in PATH are often installed by a package manager, which is then the
+one to ask before removing them or anything in them.
+
+# find the packages owning an entry, or failing that its executables
+owners(Path) -> Vec<Owner>
+
+# find the package a missing entry was left behind by, if it is uninstalled
+orphaned_by(Path) -> Option<Owner>
+
+# get the version independent opt path for a Homebrew Cellar path
+brew_opt_path(Path) -> Option<PathBuf>
+
+# get the name of the rustup toolchain a bin directory belongs to
+rustup_toolchain(Path) -> Option<String>
+
+# get the directory holding cargo's binaries and rustup's proxies
+cargo_bin() -> Option<PathBuf>
+
+# get the directories Python tool installers put commands in, for those installed
+python_tool_dirs(path_var: OsStr) -> Vec<(str, PathBuf)>
+
+# tell if an entry is a directory of shims from Scoop or Chocolatey
+shim_manager(Path) -> Option<str>
+
+# get the apps (Scoop) or commands (Chocolatey) a shim directory has shims for
+shimmed_apps(Path) -> Vec<String>
+
+# get the command names an entry provides, compared as the platform does
+command_names(Path) -> Vec<String>
+
+# get the directory winget links the commands of installed packages into
+winget_links() -> Option<PathBuf>
+
+# find links in a directory whose target is gone
+broken_links(Path) -> Vec<PathBuf>
+*/
+
+use crate::pathops::{command_name, find_executable, get_path, list_executables, resolve};
+use crate::state::home_dir;
+use std::collections::BTreeSet;
+use std::env;
+use std::ffi::OsStr;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::process;
+
+// How many executables to ask about when nothing owns the directory itself
+const MAX_EXECUTABLES: usize = 50;
+
+#[derive(Debug, PartialEq)]
+pub struct Owner {
+    // Name of the package manager
+    pub manager: String,
+    pub packages: Vec<String>,
+}
+
+// Package managers that keep a database of the files they installed, and how to
+// get the owning package names out of what they print
+const DATABASES: [(&str, &[&str]); 3] = [
+    ("dpkg", &["-S"]),
+    ("rpm", &["-qf", "--queryformat", "%{NAME}\n"]),
+    ("pacman", &["-Qoq"]),
+];
+
+fn parse_owners(manager: &str, output: &str) -> Vec<String> {
+    let mut packages: BTreeSet<String> = BTreeSet::new();
+    for line in output.lines().map(str::trim).filter(|l| !l.is_empty()) {
+        match manager {
+            // coreutils, bash: /usr/bin
+            "dpkg" => {
+                if let Some((names, _)) = line.split_once(": ") {
+                    if !line.starts_with("diversion by") {
+                        packages.extend(names.split(", ").map(String::from));
+                    }
+                }
+            }
+            _ if line.contains(char::is_whitespace) => {}
+            _ => {
+                packages.insert(line.to_string());
+            }
+        }
+    }
+    packages.into_iter().collect()
+}
+
+fn query_database(manager: &str, args: &[&str], paths: &[PathBuf]) -> Option<Owner> {
+    let output = process::Command::new(manager)
+        .args(args)
+        .args(paths)
+        .stderr(process::Stdio::null())
+        .output()
+        .ok()?;
+    // Some paths not being owned makes the query fail, but the rest are still there:
+    let packages = parse_owners(manager, &String::from_utf8_lossy(&output.stdout));
+    if packages.is_empty() {
+        return None;
+    }
+    Some(Owner {
+        manager: manager.to_string(),
+        packages,
+    })
+}
+
+// Package managers installing into a directory per package can be read off the
+// path alone: <prefix>/Cellar/<formula>/<version> for Homebrew,
+// scoop/apps/<app>/<version> for Scoop and WinGet/Packages/<id>_<source> for WinGet
+fn owner_from_layout(path: &Path) -> Option<(Owner, PathBuf)> {
+    let names: Vec<String> = path
+        .components()
+        .filter_map(|c| match c {
+            Component::Normal(n) => Some(n.to_string_lossy().into_owned()),
+            _ => None,
+        })
+        .collect();
+    let after = |marker: &[&str]| {
+        names
+            .windows(marker.len() + 1)
+            .find(|w| {
+                w[..marker.len()]
+                    .iter()
+                    .zip(marker)
+                    .all(|(a, b)| a.eq_ignore_ascii_case(b))
+            })
+            .map(|w| w[marker.len()].clone())
+    };
+    let (manager, package, dir) = if let Some(formula) = after(&["Cellar"]) {
+        ("brew", formula.clone(), formula)
+    } else if let Some(app) = after(&["scoop", "apps"]) {
+        ("scoop", app.clone(), app)
+    } else if let Some(dir) = after(&["WinGet", "Packages"]) {
+        let id = dir.split_once('_').map_or(dir.as_str(), |(id, _)| id);
+        ("winget", id.to_string(), dir)
+    } else {
+        return None;
+    };
+    let package_dir = path
+        .ancestors()
+        .find(|a| a.file_name().is_some_and(|n| n.to_string_lossy() == dir))?
+        .to_path_buf();
+    Some((
+        Owner {
+            manager: manager.to_string(),
+            packages: vec![package],
+        },
+        package_dir,
+    ))
+}
+
+// An entry that is gone from a package directory that is gone too was left behind
+// when the package was uninstalled, as uninstallers rarely touch shell startup files
+pub fn orphaned_by(path: &Path) -> Option<Owner> {
+    if path.exists() {
+        return None;
+    }
+    let (owner, package_dir) = owner_from_layout(path)?;
+    (!package_dir.exists()).then_some(owner)
+}
+
+// Ask every package manager around which packages installed the entry, and if
+// none did, which installed the executables in it
+pub fn owners(path: &Path) -> Vec<Owner> {
+    let res = resolve(path).unwrap_or(path.to_path_buf());
+    if let Some((owner, _)) = owner_from_layout(&res) {
+        return vec![owner];
+    }
+    let search = get_path().unwrap_or_default();
+    let databases: Vec<(&str, &[&str])> = DATABASES
+        .into_iter()
+        .filter(|(manager, _)| find_executable(&search, manager).is_some())
+        .collect();
+    let mut owners: Vec<Owner> = databases
+        .iter()
+        .filter_map(|(manager, args)| query_database(manager, args, std::slice::from_ref(&res)))
+        .collect();
+    if owners.is_empty() {
+        let mut exes = list_executables(path).unwrap_or_default();
+        exes.sort();
+        exes.truncate(MAX_EXECUTABLES);
+        if !exes.is_empty() {
+            owners = databases
+                .iter()
+                .filter_map(|(manager, args)| query_database(manager, args, &exes))
+                .collect();
+        }
+    }
+    owners
+}
+
+// Homebrew keeps <prefix>/opt/<formula> pointing at the installed version in
+// <prefix>/Cellar/<formula>/<version>, which goes away on the next upgrade
+pub fn brew_opt_path(path: &Path) -> Option<PathBuf> {
+    let mut components = path.components();
+    let mut prefix = PathBuf::new();
+    while let Some(c) = components.next() {
+        if c.as_os_str() == "Cellar" {
+            let formula = components.next()?;
+            // Skip the version:
+            components.next()?;
+            let opt = prefix.join("opt").join(formula);
+            return Some(opt.join(components.as_path()));
+        }
+        prefix.push(c);
+    }
+    None
+}
+
+// Toolchains live in <RUSTUP_HOME>/toolchains/<name>/bin, RUSTUP_HOME being
+// ~/.rustup unless set
+pub fn rustup_toolchain(path: &Path) -> Option<String> {
+    let name = path
+        .parent()
+        .filter(|_| path.ends_with("bin"))?
+        .file_name()?
+        .to_string_lossy()
+        .into_owned();
+    let toolchains = path.parent()?.parent()?;
+    let home = toolchains.parent()?;
+    let is_rustup_home = match env::var_os("RUSTUP_HOME") {
+        Some(h) if !h.is_empty() => home == Path::new(&h) || home.ends_with(".rustup"),
+        _ => home.ends_with(".rustup"),
+    };
+    (toolchains.ends_with("toolchains") && is_rustup_home).then_some(name)
+}
+
+// The rustup proxies (cargo, rustc and so on) that pick a toolchain per project
+// are in <CARGO_HOME>/bin, CARGO_HOME being ~/.cargo unless set
+pub fn cargo_bin() -> Option<PathBuf> {
+    match env::var_os("CARGO_HOME").filter(|h| !h.is_empty()) {
+        Some(home) => Some(PathBuf::from(home).join("bin")),
+        None => Some(home_dir().ok()?.join(".cargo").join("bin")),
+    }
+}
+
+// pipx and uv both link the commands of the tools they install into ~/.local/bin
+// by default, unless told otherwise by their own variable or XDG_BIN_HOME (uv)
+pub fn python_tool_dirs(path_var: &OsStr) -> Vec<(&'static str, PathBuf)> {
+    let var = |name| {
+        env::var_os(name)
+            .filter(|v| !v.is_empty())
+            .map(PathBuf::from)
+    };
+    let Ok(home) = home_dir() else {
+        return Vec::new();
+    };
+    let local_bin = home.join(".local").join("bin");
+    let mut dirs: Vec<(&'static str, PathBuf)> = Vec::new();
+    if find_executable(path_var, "pipx").is_some() {
+        dirs.push(("pipx", var("PIPX_BIN_DIR").unwrap_or(local_bin.clone())));
+    }
+    if find_executable(path_var, "uv").is_some() {
+        let dir = var("UV_TOOL_BIN_DIR")
+            .or_else(|| var("XDG_BIN_HOME"))
+            .unwrap_or(local_bin);
+        dirs.push(("uv", dir));
+    }
+    dirs
+}
+
+// Scoop puts its shims in <scoop>/shims and Chocolatey in <chocolatey>/bin, both
+// a single directory in PATH standing in for every app installed
+pub fn shim_manager(path: &Path) -> Option<&'static str> {
+    let name = |c: Option<&OsStr>| c.map(|n| n.to_string_lossy().to_lowercase());
+    let parent = name(path.parent().and_then(Path::file_name));
+    match (parent.as_deref(), name(path.file_name()).as_deref()) {
+        (Some("scoop"), Some("shims")) => Some("scoop"),
+        (Some("chocolatey"), Some("bin")) => Some("chocolatey"),
+        _ => None,
+    }
+}
+
+// Scoop writes a <command>.shim next to each shim with the real executable as
+// path = "C:\Users\me\scoop\apps\<app>\current\<command>.exe", Chocolatey's
+// shims don't tell so the commands themselves are what there is
+pub fn shimmed_apps(path: &Path) -> Vec<String> {
+    let mut apps: BTreeSet<String> = BTreeSet::new();
+    if shim_manager(path) == Some("scoop") {
+        for entry in fs::read_dir(path).into_iter().flatten().flatten() {
+            let file = entry.path();
+            if file.extension().is_none_or(|e| e != "shim") {
+                continue;
+            }
+            let contents = fs::read_to_string(&file).unwrap_or_default();
+            let target = contents.lines().find_map(|l| {
+                let (key, value) = l.split_once('=')?;
+                (key.trim() == "path").then(|| value.trim().trim_matches('"').replace('\\', "/"))
+            });
+            if let Some((owner, _)) = target.and_then(|t| owner_from_layout(Path::new(&t))) {
+                apps.extend(owner.packages);
+            }
+        }
+    } else {
+        apps.extend(command_names(path));
+    }
+    apps.into_iter().collect()
+}
+
+pub fn command_names(path: &Path) -> Vec<String> {
+    let mut names: Vec<String> = list_executables(path)
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|exe| command_name(exe))
+        .collect();
+    names.sort();
+    names.dedup();
+    names
+}
+
+// Packages that aren't installed for the machine get their commands linked into
+// %LOCALAPPDATA%\Microsoft\WinGet\Links, which winget adds to the user's PATH
+pub fn winget_links() -> Option<PathBuf> {
+    let local = env::var_os("LOCALAPPDATA").filter(|d| !d.is_empty())?;
+    Some(
+        PathBuf::from(local)
+            .join("Microsoft")
+            .join("WinGet")
+            .join("Links"),
+    )
+}
+
+pub fn broken_links(path: &Path) -> Vec<PathBuf> {
+    let mut broken: Vec<PathBuf> = fs::read_dir(path)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|e| e.path())
+        .filter(|p| p.is_symlink() && !p.exists())
+        .collect();
+    broken.sort();
+    broken
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_owners() {
+        let dpkg = "coreutils, bash: /usr/bin\ndiversion by dash from: /bin/sh\n";
+        assert_eq!(parse_owners("dpkg", dpkg), vec!["bash", "coreutils"]);
+        let rpm = "coreutils\nfile /x is not owned by any package\n";
+        assert_eq!(parse_owners("rpm", rpm), vec!["coreutils"])
+    }
+
+    #[test]
+    fn test_owner_from_layout() {
+        let (brew, dir) =
+            owner_from_layout(Path::new("/usr/local/Cellar/node/21.1.0/bin")).unwrap();
+        assert_eq!(brew.manager, "brew");
+        assert_eq!(brew.packages, vec!["node"]);
+        assert_eq!(dir, Path::new("/usr/local/Cellar/node"));
+        let (scoop, _) =
+            owner_from_layout(Path::new("/c/Users/me/scoop/apps/git/current/bin")).unwrap();
+        assert_eq!(scoop.packages, vec!["git"]);
+        assert!(owner_from_layout(Path::new("/usr/bin")).is_none())
+    }
+
+    #[test]
+    fn test_orphaned_by() {
+        let gone = Path::new("/nonexistent/Cellar/node/21.1.0/bin");
+        assert_eq!(orphaned_by(gone).unwrap().packages, vec!["node"]);
+        assert!(orphaned_by(Path::new("/nonexistent/bin")).is_none())
+    }
+
+    #[test]
+    fn test_brew_opt_path() {
+        let cellar = Path::new("/usr/local/Cellar/node/21.1.0/bin");
+        let opt = brew_opt_path(cellar).unwrap();
+        assert_eq!(opt, Path::new("/usr/local/opt/node/bin"));
+        assert!(brew_opt_path(Path::new("/usr/local/Cellar/node")).is_none());
+        assert!(brew_opt_path(Path::new("/usr/local/bin")).is_none())
+    }
+
+    #[test]
+    fn test_rustup_toolchain() {
+        let bin = Path::new("/home/me/.rustup/toolchains/stable-x86_64-unknown-linux-gnu/bin");
+        let name = rustup_toolchain(bin).unwrap();
+        assert_eq!(name, "stable-x86_64-unknown-linux-gnu");
+        assert!(rustup_toolchain(Path::new("/home/me/.cargo/bin")).is_none());
+        assert!(rustup_toolchain(Path::new("/opt/toolchains/gcc/bin")).is_none())
+    }
+
+    #[test]
+    fn test_shim_manager() {
+        assert_eq!(
+            shim_manager(Path::new("/c/Users/me/scoop/shims")),
+            Some("scoop")
+        );
+        let choco = Path::new("/c/ProgramData/chocolatey/bin");
+        assert_eq!(shim_manager(choco), Some("chocolatey"));
+        assert!(shim_manager(Path::new("/usr/local/bin")).is_none())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_broken_links() {
+        let dir = env::temp_dir().join(format!("pathaid-links-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        std::os::unix::fs::symlink("/nonexistent/tool", dir.join("tool")).unwrap();
+        std::os::unix::fs::symlink("/", dir.join("root")).unwrap();
+        assert_eq!(broken_links(&dir), vec![dir.join("tool")]);
+        fs::remove_dir_all(&dir).unwrap()
+    }
+}
@@ -3,23 +3,192 @@ Joel Gruselius 2024
 
 Summary of pathops functions
 
-# get the PATH environment variable
-get_path() -> Result<String>
+# get the PATH environment variable, as it is even if it isn't valid Unicode
+get_path() -> Result<OsString>
+
+# compose PATH as a fresh login would get it from system files only
+system_path() -> Result<OsString>
+
+# compose PATH as new processes get it from the machine and user registry values (Windows)
+registry_path() -> Result<String>
+
+# get any environment variable holding paths
+get_var(name: str) -> Result<OsString>
 
 # split the string on ':' (or ';' on Windows)
 split(OsStr) -> Vec<PathBuf>
 
 # join the paths with ':' (or ';' on Windows) between
-join(Vec<PathBuf>) -> Vec<PathBuf>
+join(Vec<PathBuf>) -> Result<OsString>
+
+# get a path string as text, for where bytes that aren't valid Unicode can't go
+into_string(OsString) -> Result<String>
+
+# split the string, but treat an empty string as having no entries
+split_entries(OsStr) -> Vec<PathBuf>
+
+# split a Windows PATH string on ';', honoring double-quoted entries
+split_quoted(str) -> Vec<PathBuf>
+
+# join the paths with ';', quoting entries that contain ';'
+join_quoted(Vec<PathBuf>) -> Result<String>
+
+# resolve links, junctions and subst drives to the real directory
+resolve(Path) -> io::Result<PathBuf>
+
+# resolve all entries, dropping or keeping those that can't be resolved
+resolve_all(Vec<PathBuf>, keep_missing: bool) -> Vec<PathBuf>
+
+# find the problems with entries that validate reports without looking at packages
+validate(Vec<PathBuf>, var: str, allow_cwd: bool, show: fn) -> Result<Vec<Issue>>
+
+# describe every entry, with the number of executables if count
+entries(Vec<PathBuf>, count: bool) -> Vec<PathEntry>
+
+# get the owner, permissions, modification time and link status of an entry
+entry_meta(Path, count: bool) -> EntryMeta
+
+# make a relative path absolute from the current directory
+absolute(Path) -> Result<PathBuf>
+
+# check if an entry refers to the current directory ('' or '.')
+is_cwd_entry(Path) -> bool
+
+# refuse an addition that refers to the current directory
+ensure_not_cwd(addition: OsStr) -> Result<()>
 
 # check if path exists and is a directory
 exists(Path) -> bool
 
+# get the drive letter of a path, like Z:
+drive_of(Path) -> Option<String>
+
+# check if a drive is there, not disconnected or without media (Windows)
+drive_available(drive: str) -> bool
+
+# leave entries on network and removable drives alone from now on (Windows), in the binary only
+skip_slow_drives()
+
+# give up on entries that take too long to look at, or once past a deadline, in the binary only
+set_probe_budget(per_entry: Option<Duration>, deadline: Option<Instant>)
+
+# tell why an entry is left alone, if it is, in the binary only
+skipped(Path) -> Option<Skipped>
+is_skipped(Path) -> bool
+
+# get the entries given up on for the probe budget so far, in the binary only
+skipped_entries() -> Vec<(PathBuf, Skipped)>
+
+# look at entries under another root, like a mounted image or container, from now on (once), in the binary only
+set_root(Path) -> Result<()>
+
+# check that a directory can be a root, giving it canonicalized
+root_dir(Path) -> Result<PathBuf>
+
+# get that root, if set, in the binary only
+root() -> Option<Path>
+
+# get where a path is on this system, following links as they would be under the root
+on_host(Path) -> io::Result<PathBuf>
+
+# expand ~ and $NAME (and %NAME% on Windows) in a path, as a shell would in an rc file
+expand(Path) -> PathBuf
+
+# look at entries expanded, while keeping them as written, from now on, in the binary only
+set_expand(bool)
+
+# check if any of the settings above differ from the defaults, in the binary only
+is_tuned() -> bool
+
 # check if path contains no executables (case of below)
 is_empty(Path) -> Result<bool>
 
+# check if path contains nothing at all (for variables other than PATH)
+is_empty_dir(Path) -> Result<bool>
+
+# check if path is on a file system mounted read-only
+is_read_only(Path) -> Result<bool>
+
+# check if path has the immutable attribute set
+is_immutable(Path) -> Result<bool>
+
+# find the mount point and file system type of a path
+mount_of(Path) -> Result<Mount>
+
+# check if a file system type is a network (or otherwise remote) one
+is_network_fs(str) -> bool
+
+# list all executables in a path
+list_executables(Path) -> Result<Vec<PathBuf>>
+
+# find the executable a command name runs, as the shell would look it up
+find_executable(path_var: OsStr, name: str) -> Option<PathBuf>
+
+# find every executable a command name could run, with the position of its entry
+find_executables(path_var: OsStr, name: str) -> Vec<(usize, PathBuf)>
+
+# get the name a command is run by, compared as the platform does
+command_name(Path) -> Option<String>
+
+# map every command name to the executables providing it, in lookup order
+scan_executables(Vec<PathBuf>) -> BTreeMap<String, Vec<(usize, PathBuf)>>
+
+# check if a path is a file that can be run, by permission bits or PATHEXT
+is_executable(Path) -> bool
+
+# get the SHA-256 of a file as hex, with the cli feature
+file_sha256(Path) -> Result<String>
+
 # count all executables in a path
-count_files(Path) -> Result<usize>
+count_executables(Path) -> Result<usize>
+
+# get the executables in a path with a full path too long for MAX_PATH
+exceeds_max_path(Path) -> Vec<PathBuf>
+
+# get the number of characters left for file names in an entry within MAX_PATH
+max_path_room(Path) -> usize
+
+# check if long paths are enabled in the registry, or None if not Windows
+long_paths_enabled() -> Option<bool>
+
+# find executables with names that only differ by case
+find_case_collisions(Path) -> Result<Vec<Vec<String>>>
+
+# show invisible characters, odd spaces and spaces at the ends of a path as escapes like \u{a0}
+escape_invisible(Path) -> String
+
+# remove invisible characters and spaces at the ends, making non-breaking spaces plain
+strip_invisible(Path) -> PathBuf
+
+# tell where in a path strip_invisible would change it
+invisible_places(Path) -> Vec<str>
+
+# write a path without trailing separators, '.' segments and repeated separators
+normalize(Path) -> PathBuf
+
+# compose the characters of a path the same way (NFC), to compare it with others
+normalize_unicode(Path) -> PathBuf
+
+# tell how the characters of a path are composed: NFC, NFD or mixed
+unicode_form(Path) -> &str
+
+# find pairs of entries only differing in how their characters are composed
+find_duplicates_normalized(Vec<PathBuf>) -> Vec<(PathBuf, PathBuf)>
+
+# find entries only in the current PATH and only in the one new processes would get
+find_drift(current: OsStr, composed: OsStr) -> (Vec<PathBuf>, Vec<PathBuf>)
+
+# compare two lists of entries, telling which were added, removed or moved
+diff(old: Vec<PathBuf>, new: Vec<PathBuf>) -> Vec<DiffEntry>
+
+# compare entries regardless of case from now on, the default on Windows and macOS, in the binary only
+set_ignore_case(bool)
+
+# get what an entry is compared by, normalized and folded as set
+case_key(Path) -> PathBuf
+
+# find the 0-based positions of an entry, as written or after resolving
+find_positions(Vec<PathBuf>, target: Path, resolved: bool) -> Vec<usize>
 
 # find any duplicate entries
 find_duplicates(Vec<PathBuf>) -> Vec<PathBuf>
@@ -27,188 +196,2198 @@ find_duplicates(Vec<PathBuf>) -> Vec<PathBuf>
 # find duplicate any entries after "canonicalizing" them
 find_duplicates_resolved(Vec<PathBuf>) -> Vec<PathBuf>
 
+# find entries that are the same directory by device and inode but resolve differently
+find_duplicates_by_id(Vec<PathBuf>) -> Vec<(PathBuf, PathBuf)>
+
 # return all unique entries
 dedup(Vec<PathBuf>) -> Vec<PathBuf>
 
+# get the positions of unique entries, keeping the first or the last of each
+dedup_positions(Vec<PathBuf>, keep: Keep) -> Vec<usize>
+
+# get the positions of the entries any of the filters hold for
+select(Vec<PathBuf>, filters: Vec<Filter>, executables: bool) -> Vec<usize>
+
+# remove all entries that are, or resolve to, any of the targets
+remove_paths(path_var: OsStr, targets: Vec<PathBuf>) -> Result<OsString>
+
+# find the positions of entries that are, or resolve to, the target
+find_matches(Vec<PathBuf>, target: Path) -> Vec<usize>
+
+# subdirectories of an install prefix that belong in a variable
+prefix_paths(prefix: Path, var: str) -> Vec<PathBuf>
+
+# remove all entries that, as written or resolved, match any of the glob patterns, with the cli feature
+remove_matching(path_var: OsStr, patterns: Vec<str>) -> Result<OsString>
+
+# existing subdirectories of an install prefix that belong in a variable
+prefix_dirs(prefix: Path, var: str) -> Vec<PathBuf>
+
 # add addition to end of PATH and print the results
-append_path(path_var: OsStr, addition: OsStr) -> Result<String>
+append_path(path_var: OsStr, addition: OsStr) -> Result<OsString>
 
 # add addition to front of PATH and print the results
-prepend_path(path_var: OsStr, addition: OsStr) -> Result<String>
+prepend_path(path_var: OsStr, addition: OsStr) -> Result<OsString>
+
+# split arguments that may hold several entries each into one list, without repeats
+split_additions(Vec<OsStr>) -> Vec<PathBuf>
+
+# add all additions to front of PATH, in the order given
+prepend_paths(path_var: OsStr, additions: Vec<PathBuf>) -> Result<OsString>
+
+# add all additions to end of PATH, in the order given
+append_paths(path_var: OsStr, additions: Vec<PathBuf>) -> Result<OsString>
+
+# add all additions before the entry at a position, in the order given
+insert_paths(path_var: OsStr, index: usize, additions: Vec<PathBuf>) -> Result<OsString>
+
+# move the entry at one position so it ends up at another
+move_entry(path_var: OsStr, from: usize, to: usize) -> Result<OsString>
+
+# ensure addition is not already present in PATH (when all paths are resolved)
+ensure_unique_addition(path_var: OsStr, addition: OsStr) -> Result<()>
 
 # ensure addition exists and not already present in PATH (when all paths are resolved)
 validate_addition(path_var: OsStr, addition: OsStr) -> Result<()>
 */
 
-use anyhow::{anyhow, ensure, Context, Result};
-use is_executable::IsExecutable;
-use std::collections::HashSet;
+use crate::fixture;
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::env;
-use std::ffi::OsStr;
+use std::ffi::{OsStr, OsString};
 use std::fs;
-use std::path::{Path, PathBuf};
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Mutex, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use unicode_normalization::UnicodeNormalization;
 
-// Get the PATH environment variable
-pub fn get_path() -> Result<String> {
-    let path = env::var_os("PATH").context("unable to fetch PATH environment variable")?;
-    path.into_string()
-        .map_err(|_| anyhow!("OS string contains symbols this program can't deal with"))
+/// Everything that can go wrong in this module
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    /// An I/O error, with what was being done
+    #[error("unable to {what}")]
+    Unable {
+        what: &'static str,
+        #[source]
+        source: io::Error,
+    },
+    /// A program run to find something out didn't give an answer
+    #[error("{0}")]
+    Failed(&'static str),
+    #[error("'{0}' isn't valid Unicode, so it can't be given as text")]
+    NotUnicode(String),
+    #[error("path contains a NUL byte")]
+    Nul(#[from] std::ffi::NulError),
+    /// The environment variable that isn't set
+    #[error("unable to fetch {0} environment variable")]
+    NotSet(String),
+    #[error("unable to join path components")]
+    Join(#[from] env::JoinPathsError),
+    /// An entry that can't be quoted when joining with ';'
+    #[error("'{0}' contains a '\"' and can't be joined")]
+    Quote(String),
+    #[cfg(feature = "cli")]
+    #[error("'{pattern}' is not a valid pattern")]
+    Pattern {
+        pattern: String,
+        #[source]
+        source: glob::PatternError,
+    },
+    #[error("no mount found for '{0}'")]
+    NoMount(PathBuf),
+    /// What isn't available on this platform
+    #[error("{0} can't be looked up on this platform")]
+    Unsupported(&'static str),
+    /// An addition meaning the current directory
+    #[error("refusing to add '{0}', {CWD_RATIONALE}")]
+    Cwd(String),
+    /// An addition that doesn't exist
+    #[error("'{0}' is not an existing directory")]
+    Missing(String),
+    /// An addition that is already in PATH, telling as what
+    #[error("{0}")]
+    Present(String),
+    /// A position to insert at past the end of the entries
+    #[error("position {index} is past the end, positions go up to {len}")]
+    Position { index: usize, len: usize },
+    /// A second root for the process, with the one it already has
+    #[error("the root is already set to '{}'", .0.display())]
+    RootSet(PathBuf),
 }
 
-// Split the string on ':' or ';' (Windows)
+impl Error {
+    /// How to get around a refused addition with another option, if there is one
+    pub fn hint(&self) -> Option<&'static str> {
+        match self {
+            Error::Missing(_) => {
+                Some("use --create to make the directory first or --force to add it anyway")
+            }
+            Error::Present(_) => Some("use --promote to move the existing entry instead"),
+            Error::NotUnicode(_) => {
+                Some("only plain text output can have it as it is, without --format or --shell")
+            }
+            _ => None,
+        }
+    }
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Get the PATH environment variable
+pub fn get_path() -> Result<OsString> {
+    get_var("PATH")
+}
+
+/// Compose the PATH a fresh login gets from system files (/etc/profile, /etc/paths
+/// and so on), by running a login shell with an empty environment and a home
+/// directory without any dotfiles
+#[cfg(unix)]
+pub fn system_path() -> Result<OsString> {
+    use std::os::unix::ffi::OsStringExt;
+    let home = private_dir()?;
+    let output = process::Command::new("/bin/sh")
+        .args(["-l", "-c", "printf '%s' \"$PATH\""])
+        .env_clear()
+        .env("HOME", &home)
+        .output();
+    let _ = fs::remove_dir_all(&home);
+    let output = output.map_err(|source| Error::Unable {
+        what: "run a login shell",
+        source,
+    })?;
+    if !output.status.success() {
+        return Err(Error::Failed("login shell failed to start"));
+    }
+    Ok(OsString::from_vec(output.stdout))
+}
+
+// A new directory only this user can get into, with a name no one else can know
+// in advance, as the login shell runs whatever .profile is in its home
+#[cfg(unix)]
+fn private_dir() -> Result<PathBuf> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::{OsStrExt, OsStringExt};
+
+    let template = env::temp_dir().join("pathaid-XXXXXX");
+    let mut template = CString::new(template.as_os_str().as_bytes())?.into_bytes_with_nul();
+    // SAFETY: template is NUL-terminated and mkdtemp only replaces the X's in it
+    if unsafe { libc::mkdtemp(template.as_mut_ptr().cast()) }.is_null() {
+        return Err(Error::Unable {
+            what: "create a private directory",
+            source: io::Error::last_os_error(),
+        });
+    }
+    template.pop();
+    Ok(PathBuf::from(OsString::from_vec(template)))
+}
+
+/// The machine-wide part of PATH is kept in the registry, with %VARIABLES% unexpanded
+#[cfg(windows)]
+pub fn system_path() -> Result<OsString> {
+    Ok(system_registry_path()?.into())
+}
+
+#[cfg(windows)]
+fn system_registry_path() -> Result<String> {
+    registry_value(r"HKLM\SYSTEM\CurrentControlSet\Control\Session Manager\Environment")
+}
+
+/// New processes get the machine-wide PATH followed by the user's own, which is
+/// what Explorer hands out after a sign-in or an environment change broadcast
+#[cfg(windows)]
+pub fn registry_path() -> Result<String> {
+    let machine = system_registry_path()?;
+    // Users don't need a PATH of their own:
+    let user = registry_value(r"HKCU\Environment").unwrap_or_default();
+    let parts: Vec<String> = [machine, user]
+        .into_iter()
+        .filter(|p| !p.is_empty())
+        .collect();
+    Ok(parts.join(";"))
+}
+
+#[cfg(windows)]
+fn registry_value(key: &str) -> Result<String> {
+    let output = process::Command::new("reg")
+        .args(["query", key, "/v", "Path"])
+        .output()
+        .map_err(|source| Error::Unable {
+            what: "query the registry",
+            source,
+        })?;
+    if !output.status.success() {
+        return Err(Error::Failed("unable to read Path from the registry"));
+    }
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let value = stdout
+        .lines()
+        .find_map(|l| {
+            l.split_once("REG_EXPAND_SZ")
+                .or_else(|| l.split_once("REG_SZ"))
+        })
+        .map(|(_, v)| v.trim())
+        .ok_or(Error::Failed("unable to read Path from the registry"))?;
+    Ok(expand_percent_vars(value))
+}
+
+// Read a number from the registry, as given by reg query like 0x1
+#[cfg(windows)]
+fn registry_dword(key: &str, name: &str) -> Option<u32> {
+    let output = process::Command::new("reg")
+        .args(["query", key, "/v", name])
+        .output()
+        .ok()?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let (_, value) = stdout.lines().find_map(|l| l.split_once("REG_DWORD"))?;
+    u32::from_str_radix(value.trim().trim_start_matches("0x"), 16).ok()
+}
+
+// Replace %NAME% with the value of environment variable NAME where it is set
+#[cfg(windows)]
+fn expand_percent_vars(value: &str) -> String {
+    let mut expanded = String::new();
+    let mut rest = value;
+    while let Some(start) = rest.find('%') {
+        expanded.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let var = after
+            .find('%')
+            .and_then(|end| env::var(&after[..end]).ok().map(|v| (end, v)));
+        match var {
+            Some((end, v)) => {
+                expanded.push_str(&v);
+                rest = &after[end + 1..];
+            }
+            None => {
+                expanded.push('%');
+                rest = after;
+            }
+        }
+    }
+    expanded.push_str(rest);
+    expanded
+}
+
+/// Get an environment variable with paths, like MANPATH or LD_LIBRARY_PATH
+pub fn get_var(name: &str) -> Result<OsString> {
+    fixture::var(name, || env::var_os(name)).ok_or_else(|| Error::NotSet(name.to_string()))
+}
+
+/// Split the string on ':' or ';' (Windows)
 pub fn split(path_var: impl AsRef<OsStr>) -> Vec<PathBuf> {
+    // Split as UTF-16 so entries that aren't valid Unicode come back as they were:
+    #[cfg(windows)]
+    {
+        use std::os::windows::ffi::{OsStrExt, OsStringExt};
+        let units = path_var.as_ref().encode_wide();
+        split_units(units, u16::from(b'"'), u16::from(b';'))
+            .into_iter()
+            .map(|entry| PathBuf::from(OsString::from_wide(&entry)))
+            .collect()
+    }
+    #[cfg(not(windows))]
     env::split_paths(&path_var).collect()
 }
 
-pub fn join(paths: &[PathBuf]) -> Result<String> {
-    let path = env::join_paths(paths).context("unable to join path components")?;
-    path.into_string()
-        .map_err(|_| anyhow!("OS string contains symbols this program can't deal with"))
+pub fn join(paths: &[PathBuf]) -> Result<OsString> {
+    if cfg!(windows) {
+        return Ok(join_quoted(paths)?.into());
+    }
+    Ok(env::join_paths(paths)?)
+}
+
+/// Fails on the first entry that isn't valid Unicode, naming it as well as it can
+pub fn into_string(path_var: OsString) -> Result<String> {
+    path_var.into_string().map_err(|path_var| {
+        let entry = split(&path_var)
+            .into_iter()
+            .find(|p| p.to_str().is_none())
+            .unwrap_or(PathBuf::from(path_var));
+        Error::NotUnicode(entry.to_string_lossy().into_owned())
+    })
+}
+
+/// Split on ';' like Windows does, where a double-quoted section may contain ';'
+/// and the quotes themselves are not part of the entry
+pub fn split_quoted(path_var: &str) -> Vec<PathBuf> {
+    // Split at ASCII characters, so every part is still valid UTF-8:
+    split_units(path_var.bytes(), b'"', b';')
+        .into_iter()
+        .map(|entry| PathBuf::from(String::from_utf8_lossy(&entry).into_owned()))
+        .collect()
+}
+
+// Split code units on the separator outside quotes, leaving out the quotes
+fn split_units<T: Copy + PartialEq>(
+    units: impl Iterator<Item = T>,
+    quote: T,
+    separator: T,
+) -> Vec<Vec<T>> {
+    let mut entries: Vec<Vec<T>> = Vec::new();
+    let mut current: Vec<T> = Vec::new();
+    let mut in_quotes = false;
+
+    for unit in units {
+        if unit == quote {
+            in_quotes = !in_quotes;
+        } else if unit == separator && !in_quotes {
+            entries.push(std::mem::take(&mut current));
+        } else {
+            current.push(unit);
+        }
+    }
+    entries.push(current);
+    entries
+}
+
+/// Join with ';' and quote the entries that need it so split_quoted() gets them back
+pub fn join_quoted(paths: &[PathBuf]) -> Result<String> {
+    let mut entries: Vec<String> = Vec::new();
+    for path in paths {
+        let entry = path
+            .to_str()
+            .ok_or_else(|| Error::NotUnicode(path.to_string_lossy().into_owned()))?;
+        if entry.contains('"') {
+            return Err(Error::Quote(entry.to_string()));
+        }
+        if entry.contains(';') {
+            entries.push(format!("\"{}\"", entry));
+        } else {
+            entries.push(entry.to_string());
+        }
+    }
+    Ok(entries.join(";"))
+}
+
+/// Split, but treat an empty string as no entries at all rather than one empty entry
+pub fn split_entries(path_var: impl AsRef<OsStr>) -> Vec<PathBuf> {
+    if path_var.as_ref().is_empty() {
+        Vec::new()
+    } else {
+        split(path_var)
+    }
 }
 
-// Split and join via HashSet as internal functions for manipulating path:
+// Split via HashSet as internal function for manipulating path:
 fn split_hs(path_var: impl AsRef<OsStr>) -> HashSet<PathBuf> {
-    env::split_paths(&path_var).collect()
+    split(path_var).into_iter().collect()
+}
+
+/// Resolve symlinks, junctions and subst drives to the real directory, without the
+/// \\?\ prefix Windows adds so the result can be compared with entries as written
+pub fn resolve(path: &Path) -> std::io::Result<PathBuf> {
+    if is_skipped(path) {
+        return Err(io::Error::other("the drive is skipped"));
+    }
+    let res = fixture::resolve(path, || match current_root() {
+        Some(root) => resolve_in(&root, &expanded(path)),
+        None => expanded(path).canonicalize(),
+    })?;
+    Ok(if cfg!(windows) {
+        strip_verbatim(res)
+    } else {
+        res
+    })
+}
+
+/// Resolve every entry, the ones that can't be are either dropped or kept as they are
+pub fn resolve_all(paths: &[PathBuf], keep_missing: bool) -> Vec<PathBuf> {
+    paths
+        .iter()
+        .filter_map(|p| match resolve(p) {
+            Ok(res) => Some(res),
+            _ if keep_missing => Some(p.clone()),
+            _ => None,
+        })
+        .collect()
+}
+
+// Turn \\?\C:\dir into C:\dir and \\?\UNC\server\share into \\server\share
+fn strip_verbatim(path: PathBuf) -> PathBuf {
+    match path.to_str() {
+        Some(s) if s.starts_with(r"\\?\UNC\") => PathBuf::from(format!(r"\\{}", &s[8..])),
+        Some(s) if s.starts_with(r"\\?\") && s[4..].chars().nth(1) == Some(':') => {
+            PathBuf::from(&s[4..])
+        }
+        _ => path,
+    }
+}
+
+/// Why entries for the current directory are refused unless explicitly allowed
+pub const CWD_RATIONALE: &str = "commands would be looked up in whatever directory you are in, \
+so anyone who can write to a directory you visit can shadow the commands you run";
+
+/// Check if an entry is empty or '.', which both mean the current directory
+pub fn is_cwd_entry(path: &Path) -> bool {
+    path.components().all(|c| c == Component::CurDir)
+}
+
+pub fn ensure_not_cwd(addition: impl AsRef<OsStr>) -> Result<()> {
+    if is_cwd_entry(Path::new(&addition)) {
+        return Err(Error::Cwd(addition.as_ref().to_string_lossy().into_owned()));
+    }
+    Ok(())
+}
+
+/// An entry as given in JSON output
+#[derive(Serialize, Deserialize, Debug)]
+pub struct PathEntry {
+    pub index: usize,
+    pub path: PathBuf,
+    pub resolved: Option<PathBuf>,
+    pub exists: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub executables: Option<usize>,
+}
+
+/// A problem found with an entry, with a code to tell the kinds apart in JSON output
+#[derive(Serialize, Debug)]
+pub struct Issue {
+    pub code: &'static str,
+    pub path: Option<PathBuf>,
+    pub message: String,
+}
+
+/// Find the problems with entries that pathaid validate reports from the entries
+/// alone, the command adds what it knows about installed packages. Paths in the
+/// messages are given by show, with how much attention they need from 0 to 2.
+pub fn validate(
+    paths: &[PathBuf],
+    var: &str,
+    allow_cwd: bool,
+    show: impl Fn(&Path, usize) -> String,
+) -> Result<Vec<Issue>> {
+    let mut issues: Vec<Issue> = Vec::new();
+    let mut issue = |code, path: &Path, message: String| {
+        issues.push(Issue {
+            code,
+            path: Some(path.to_path_buf()),
+            message,
+        })
+    };
+    // Mounts are this system's, so they say nothing about entries under another root:
+    let on_host = current_root().is_none();
+    for p in paths.iter() {
+        if is_cwd_entry(p) {
+            if !allow_cwd {
+                issue(
+                    "cwd",
+                    p,
+                    format!(
+                        "{} refers to the current directory, {}",
+                        show(Path::new(&format!("'{}'", p.display())), 2),
+                        CWD_RATIONALE
+                    ),
+                );
+            }
+            continue;
+        }
+        if strip_invisible(p) != *p {
+            issue(
+                "invisible",
+                p,
+                format!(
+                    "'{}' has invisible characters or spaces {} (shown escaped), likely pasted from a web page, clean strips them",
+                    escape_invisible(p),
+                    invisible_places(p).join(" and ")
+                ),
+            );
+        }
+        if let Some(skipped) = skipped(p) {
+            issue(
+                "unchecked",
+                p,
+                format!("{} wasn't checked, {}", show(p, 1), skipped.reason()),
+            );
+            continue;
+        }
+        if !exists(p) {
+            // Only a shell reading an rc file expands these, PATH has them as is:
+            let expanded = expand(p);
+            if expanded != *p && exists(&expanded) {
+                issue(
+                    "unexpanded",
+                    p,
+                    format!(
+                        "{} isn't expanded in PATH, so commands are looked for in a directory named like that rather than {}, write it expanded or check it with --expand",
+                        show(Path::new(&format!("'{}'", p.display())), 2),
+                        show(&expanded, 0)
+                    ),
+                );
+            } else if let Some(drive) = drive_of(p).filter(|d| !drive_available(d)) {
+                issue(
+                    "drive-unavailable",
+                    p,
+                    format!(
+                        "{} is on drive {}, which is disconnected, has no media or isn't assigned",
+                        show(p, 2),
+                        drive
+                    ),
+                );
+            } else {
+                issue(
+                    "missing",
+                    p,
+                    format!("{} is not an accessible directory", show(p, 2)),
+                );
+            }
+            continue;
+        }
+        // Only PATH is expected to hold executables:
+        let empty = match var {
+            "PATH" => is_empty(p)?,
+            _ => is_empty_dir(p)?,
+        };
+        if empty {
+            issue("empty", p, format!("{} is empty", show(p, 1)));
+        }
+        for names in find_case_collisions(p).unwrap_or_default() {
+            issue(
+                "case-collision",
+                p,
+                format!(
+                    "{} has executables only differing by case: {}",
+                    show(p, 1),
+                    names.join(", ")
+                ),
+            );
+        }
+        // Not wrong as such, but explains why installing there fails:
+        if on_host && is_read_only(p).unwrap_or(false) {
+            issue(
+                "read-only",
+                p,
+                format!("{} is on a read-only mount", show(p, 0)),
+            );
+        }
+        if is_immutable(p).unwrap_or(false) {
+            issue("immutable", p, format!("{} is immutable", show(p, 0)));
+        }
+    }
+    // Give the position of every copy, so the ones to remove can be picked out:
+    let positions = |p: &Path, resolved: bool| {
+        let positions = find_positions(paths, p, resolved);
+        let list: Vec<String> = positions.iter().map(usize::to_string).collect();
+        (positions.len(), list.join(", "))
+    };
+    let mut reported: HashSet<PathBuf> = HashSet::new();
+    // Spellings that only differ in case are one duplicate when case is ignored:
+    for p in find_duplicates(paths)
+        .iter()
+        .filter(|p| reported.insert(case_key(p)))
+    {
+        let (n, list) = positions(p, false);
+        issue(
+            "duplicate",
+            p,
+            format!(
+                "{} is included {} times, at positions {}",
+                show(&normalize(p), 1),
+                n,
+                list
+            ),
+        );
+    }
+    let mut reported: HashSet<PathBuf> = HashSet::new();
+    for p in find_duplicates_resolved(paths)
+        .iter()
+        .filter(|p| reported.insert(case_key(p)))
+    {
+        let (n, list) = positions(p, true);
+        issue(
+            "duplicate-resolved",
+            p,
+            format!(
+                "{} is included {} times when entries are resolved, at positions {}",
+                show(p, 1),
+                n,
+                list
+            ),
+        );
+    }
+    for (first, other) in find_duplicates_normalized(paths).iter() {
+        issue(
+            "unicode-normalization",
+            other,
+            format!(
+                "{} is written in {} and {} in {}, which look the same and are one directory on macOS but two on most other systems",
+                show(other, 1),
+                unicode_form(other),
+                show(first, 1),
+                unicode_form(first)
+            ),
+        );
+    }
+    for (first, other) in find_duplicates_by_id(paths).iter() {
+        issue(
+            "same-directory",
+            other,
+            format!(
+                "{} is the same directory as {}",
+                show(other, 1),
+                show(first, 1)
+            ),
+        );
+    }
+
+    Ok(issues)
+}
+
+/// Describe each entry, counting executables only if asked as that reads every directory
+pub fn entries(paths: &[PathBuf], count: bool) -> Vec<PathEntry> {
+    paths
+        .iter()
+        .enumerate()
+        .map(|(index, p)| PathEntry {
+            index,
+            path: p.clone(),
+            resolved: resolve(p).ok(),
+            exists: exists(p),
+            executables: count.then(|| count_executables(p).ok()).flatten(),
+        })
+        .collect()
 }
 
-fn join_hs(paths: &HashSet<PathBuf>) -> Result<String> {
-    let path = env::join_paths(paths).context("unable to join path components")?;
-    path.into_string()
-        .map_err(|_| anyhow!("OS string contains symbols this program can't deal with"))
+/// What a long listing shows of an entry, from looking at it once. Entries that
+/// can't be looked at have nothing but symlink set, if they are dangling links.
+#[derive(Serialize, Debug, Default, PartialEq)]
+pub struct EntryMeta {
+    pub symlink: bool,
+    pub dir: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub executables: Option<usize>,
+    /// User name, or the uid if there is none or entries are under another root
+    pub owner: Option<String>,
+    /// Permission bits, only on Unix
+    pub mode: Option<u32>,
+    /// Seconds since the Unix epoch
+    pub modified: Option<u64>,
 }
 
-// Check if path exists and is a directory
+/// Look at an entry once for a long listing, counting executables only if asked as
+/// that reads the directory
+pub fn entry_meta(path: &Path, count: bool) -> EntryMeta {
+    let mut meta = EntryMeta::default();
+    if is_skipped(path) {
+        return meta;
+    }
+    meta.symlink = on_host_unresolved(path).is_some_and(|p| p.is_symlink());
+    let Ok(m) = on_host(path).and_then(fs::metadata) else {
+        return meta;
+    };
+    meta.dir = m.is_dir();
+    meta.executables = count.then(|| count_executables(path).ok()).flatten();
+    meta.modified = m
+        .modified()
+        .ok()
+        .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+        .map(|d| d.as_secs());
+    owner_and_mode(&m, &mut meta);
+    meta
+}
+
+#[cfg(unix)]
+fn owner_and_mode(m: &fs::Metadata, meta: &mut EntryMeta) {
+    use std::os::unix::fs::MetadataExt;
+
+    // Names on this system needn't be the ones under another root:
+    let name = if current_root().is_none() {
+        user_name(m.uid())
+    } else {
+        None
+    };
+    meta.owner = Some(name.unwrap_or_else(|| m.uid().to_string()));
+    meta.mode = Some(m.mode() & 0o7777);
+}
+
+#[cfg(not(unix))]
+fn owner_and_mode(_m: &fs::Metadata, _meta: &mut EntryMeta) {}
+
+#[cfg(unix)]
+fn user_name(uid: u32) -> Option<String> {
+    use std::ffi::CStr;
+
+    let mut buf = vec![0 as libc::c_char; 4096];
+    let mut pwd: libc::passwd = unsafe { std::mem::zeroed() };
+    let mut found: *mut libc::passwd = std::ptr::null_mut();
+    // SAFETY: pwd points into buf, which outlives it, and is only read when found
+    let ret = unsafe { libc::getpwuid_r(uid, &mut pwd, buf.as_mut_ptr(), buf.len(), &mut found) };
+    if ret != 0 || found.is_null() {
+        return None;
+    }
+    Some(
+        unsafe { CStr::from_ptr(pwd.pw_name) }
+            .to_string_lossy()
+            .into_owned(),
+    )
+}
+
+/// Make a relative path absolute by joining it to the current directory, without
+/// resolving any links
+pub fn absolute(path: impl AsRef<Path>) -> Result<PathBuf> {
+    std::path::absolute(path).map_err(|source| Error::Unable {
+        what: "make path absolute",
+        source,
+    })
+}
+
+/// Check if path exists and is a directory, which an entry skipped for the probe
+/// budget isn't known to be, so check is_skipped before taking false as missing
 pub fn exists(path: &Path) -> bool {
-    match path.canonicalize() {
-        // exists() can probably be removed because
-        Ok(p) => p.exists() && p.is_dir(), // I think canonicalize() already does it
-        _ => false,
+    if is_skipped(path) {
+        return false;
+    }
+    fixture::is_dir(path, || match current_root() {
+        Some(_) => on_host(path).is_ok_and(|p| p.is_dir()),
+        None => match expanded(path).canonicalize() {
+            // exists() can probably be removed because
+            Ok(p) => p.exists() && p.is_dir(), // I think canonicalize() already does it
+            _ => false,
+        },
+    })
+}
+
+// Set once, for everything to be looked up as if it was the root directory
+static ROOT: OnceLock<PathBuf> = OnceLock::new();
+
+// How a PathSet looks at its entries, each setting in place of the one for the
+// process where it is given
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct Options {
+    pub(crate) root: Option<PathBuf>,
+    pub(crate) ignore_case: Option<bool>,
+    pub(crate) expand: Option<bool>,
+}
+
+thread_local! {
+    // The options of the with_options() call this thread is in, if any
+    static SCOPED: RefCell<Option<Options>> = const { RefCell::new(None) };
+}
+
+// Put back the options of an outer call, also when the inner one panics
+struct Restore(Option<Options>);
+
+impl Drop for Restore {
+    fn drop(&mut self) {
+        SCOPED.set(self.0.take());
+    }
+}
+
+// Run f with options in place of the settings for the process, on this thread
+pub(crate) fn with_options<T>(options: &Options, f: impl FnOnce() -> T) -> T {
+    let _restore = Restore(SCOPED.replace(Some(options.clone())));
+    f()
+}
+
+fn current_root() -> Option<PathBuf> {
+    SCOPED
+        .with_borrow(|s| s.as_ref().and_then(|o| o.root.clone()))
+        .or_else(|| ROOT.get().cloned())
+}
+
+// How many links are followed before giving up, as Linux does
+const MAX_LINKS: usize = 40;
+
+// Look at entries under root rather than /, for the rest of the process, to check
+// the PATH of a mounted image or container without running it. Links are followed
+// as they would be inside it, so an absolute link stays under root. Setting
+// another root later fails, PathSet::under_root() is for looking at several.
+#[cfg(feature = "cli")]
+pub(crate) fn set_root(root: &Path) -> Result<()> {
+    let root = root_dir(root)?;
+    match ROOT.get() {
+        Some(set) if *set != root => Err(Error::RootSet(set.clone())),
+        Some(_) => Ok(()),
+        None => {
+            let _ = ROOT.set(root);
+            Ok(())
+        }
+    }
+}
+
+/// Check that root is a directory that entries can be looked at under, giving it
+/// canonicalized as links under it are followed from there
+pub fn root_dir(root: &Path) -> Result<PathBuf> {
+    let root = root.canonicalize().map_err(|source| Error::Unable {
+        what: "find the root directory",
+        source,
+    })?;
+    if !root.is_dir() {
+        return Err(io::Error::other(format!("{} is not a directory", root.display())).into());
+    }
+    Ok(root)
+}
+
+// The root given to set_root(), if any
+#[cfg(feature = "cli")]
+pub(crate) fn root() -> Option<&'static Path> {
+    ROOT.get().map(PathBuf::as_path)
+}
+
+// Resolve path like canonicalize() would with root as /, giving the path as seen
+// from inside root. A relative path is taken from root, where a chroot starts.
+fn resolve_in(root: &Path, path: &Path) -> io::Result<PathBuf> {
+    let mut resolved = PathBuf::from("/");
+    let mut pending: Vec<PathBuf> = path
+        .components()
+        .rev()
+        .map(|c| c.as_os_str().into())
+        .collect();
+    let mut links = 0;
+    while let Some(part) = pending.pop() {
+        match part.components().next() {
+            Some(Component::Normal(name)) => {
+                let next = resolved.join(name);
+                let host = root.join(next.strip_prefix("/").unwrap_or(&next));
+                if !fs::symlink_metadata(&host)?.file_type().is_symlink() {
+                    resolved = next;
+                    continue;
+                }
+                links += 1;
+                if links > MAX_LINKS {
+                    return Err(io::Error::other("too many levels of links"));
+                }
+                let target = fs::read_link(&host)?;
+                if target.has_root() {
+                    resolved = PathBuf::from("/");
+                }
+                pending.extend(target.components().rev().map(|c| c.as_os_str().into()));
+            }
+            Some(Component::ParentDir) => {
+                resolved.pop();
+            }
+            _ => {}
+        }
+    }
+    Ok(resolved)
+}
+
+/// Where path is on this system, which is itself unless there is a root
+pub fn on_host(path: &Path) -> io::Result<PathBuf> {
+    let path = expanded(path);
+    match current_root() {
+        Some(root) => {
+            let inside = resolve_in(&root, &path)?;
+            Ok(root.join(inside.strip_prefix("/").unwrap_or(&inside)))
+        }
+        None => Ok(path),
+    }
+}
+
+// Entries copied from rc files, like ~/bin or $HOME/go/bin, are only looked at
+// expanded once asked to
+static EXPAND: AtomicBool = AtomicBool::new(false);
+
+#[cfg(feature = "cli")]
+pub(crate) fn set_expand(expand: bool) {
+    EXPAND.store(expand, Ordering::Relaxed);
+}
+
+fn expanded(path: &Path) -> PathBuf {
+    let scoped = SCOPED.with_borrow(|s| s.as_ref().and_then(|o| o.expand));
+    match scoped.unwrap_or_else(|| EXPAND.load(Ordering::Relaxed)) {
+        true => expand(path),
+        false => path.to_path_buf(),
+    }
+}
+
+/// Expand ~ at the start to the home directory and $NAME and ${NAME} (and %NAME%
+/// on Windows) to the value of the environment variable. Variables that aren't
+/// set are left as written, and so is a path that isn't valid Unicode.
+pub fn expand(path: &Path) -> PathBuf {
+    let Some(s) = path.to_str() else {
+        return path.to_path_buf();
+    };
+    #[cfg(windows)]
+    let s = &expand_percent_vars(s);
+    let mut expanded = String::new();
+    let mut rest = s;
+    let home = env::var(if cfg!(windows) { "USERPROFILE" } else { "HOME" });
+    if let (Some(after), Ok(home)) = (rest.strip_prefix('~'), home) {
+        if after.is_empty() || after.starts_with(['/', '\\']) {
+            expanded.push_str(&home);
+            rest = after;
+        }
+    }
+    while let Some(start) = rest.find('$') {
+        expanded.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let (name, tail) = match after.strip_prefix('{') {
+            Some(braced) => match braced.find('}') {
+                Some(end) => (&braced[..end], &braced[end + 1..]),
+                None => ("", after),
+            },
+            None => {
+                let end = after
+                    .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                    .unwrap_or(after.len());
+                (&after[..end], &after[end..])
+            }
+        };
+        match env::var(name).ok().filter(|_| !name.is_empty()) {
+            Some(value) => {
+                expanded.push_str(&value);
+                rest = tail;
+            }
+            None => {
+                expanded.push('$');
+                rest = after;
+            }
+        }
+    }
+    expanded.push_str(rest);
+    PathBuf::from(expanded)
+}
+
+// Where path is on this system with its last component left as it is, to tell if
+// that is a link
+fn on_host_unresolved(path: &Path) -> Option<PathBuf> {
+    match (path.parent(), path.file_name()) {
+        (Some(parent), Some(name)) if current_root().is_some() => {
+            Some(on_host(parent).ok()?.join(name))
+        }
+        _ => Some(path.to_path_buf()),
+    }
+}
+
+// Executable by is_executable() once found on this system
+fn is_executable_on_host(path: &Path) -> bool {
+    on_host(path).is_ok_and(|p| is_executable(&p) || is_app_exec_alias(&p))
+}
+
+// Set once asked to, a disconnected network drive can take seconds to time out
+static SKIP_SLOW_DRIVES: AtomicBool = AtomicBool::new(false);
+
+/// Get the drive letter a path starts with, upper case and with the colon
+pub fn drive_of(path: &Path) -> Option<String> {
+    let s = path.to_string_lossy();
+    let s = s.strip_prefix(r"\\?\").unwrap_or(&s);
+    let mut chars = s.chars();
+    match (chars.next(), chars.next()) {
+        (Some(letter), Some(':')) if letter.is_ascii_alphabetic() => {
+            Some(format!("{}:", letter.to_ascii_uppercase()))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(windows)]
+fn drive_type(drive: &str) -> u32 {
+    use std::os::windows::ffi::OsStrExt;
+    use windows_sys::Win32::Storage::FileSystem::GetDriveTypeW;
+
+    let root: Vec<u16> = OsStr::new(&format!("{}\\", drive))
+        .encode_wide()
+        .chain(Some(0))
+        .collect();
+    // SAFETY: root is NUL-terminated and only read during the call
+    unsafe { GetDriveTypeW(root.as_ptr()) }
+}
+
+/// A drive letter that isn't assigned, a network drive that is disconnected or
+/// a card reader without a card are all unavailable. Probing the last two is
+/// what takes long, so a skipped drive is taken as available.
+#[cfg(windows)]
+pub fn drive_available(drive: &str) -> bool {
+    use windows_sys::Win32::System::WindowsProgramming::DRIVE_NO_ROOT_DIR;
+
+    if drive_type(drive) == DRIVE_NO_ROOT_DIR {
+        return false;
+    }
+    (SKIP_SLOW_DRIVES.load(Ordering::Relaxed) && is_slow_drive(drive))
+        || fs::metadata(format!("{}\\", drive)).is_ok()
+}
+
+#[cfg(not(windows))]
+pub fn drive_available(_drive: &str) -> bool {
+    true
+}
+
+// Make exists() and resolve() treat entries on network and removable drives as
+// unavailable without looking at them, for the rest of the process
+#[cfg(feature = "cli")]
+pub(crate) fn skip_slow_drives() {
+    SKIP_SLOW_DRIVES.store(true, Ordering::Relaxed);
+}
+
+#[cfg(windows)]
+fn is_slow_drive(drive: &str) -> bool {
+    use windows_sys::Win32::System::WindowsProgramming::{
+        DRIVE_CDROM, DRIVE_REMOTE, DRIVE_REMOVABLE,
+    };
+
+    matches!(
+        drive_type(drive),
+        DRIVE_REMOTE | DRIVE_REMOVABLE | DRIVE_CDROM
+    )
+}
+
+#[cfg(not(windows))]
+fn is_slow_drive(_drive: &str) -> bool {
+    false
+}
+
+/// Why an entry is left alone rather than looked at
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Skipped {
+    /// On a network or removable drive, after skip_slow_drives()
+    SlowDrive,
+    /// Looking at it took longer than the budget for each entry
+    TooSlow,
+    /// Not looked at before the deadline
+    PastDeadline,
+}
+
+impl Skipped {
+    /// Why, by the option of the pathaid binary that led to it
+    pub fn reason(self) -> &'static str {
+        match self {
+            Skipped::SlowDrive => {
+                "it is on a network or removable drive left alone with --skip-slow-drives"
+            }
+            Skipped::TooSlow => "looking at it took longer than --max-probe-ms",
+            Skipped::PastDeadline => "the --deadline had passed",
+        }
     }
 }
 
-// Count all executables in a path
-pub fn count_files(path: &Path) -> Result<usize> {
-    Ok(fs::read_dir(path)?
-        .filter_map(|d| d.ok().and_then(|p| p.path().canonicalize().ok()))
-        .filter(|p| p.is_executable())
-        .count())
+struct Budget {
+    per_entry: Option<Duration>,
+    deadline: Option<Instant>,
 }
 
-// Check if path contains no executables (special case of count_files = 0)
+static BUDGET: OnceLock<Budget> = OnceLock::new();
+// Entries looked at so far, with None for the ones that answered in time
+static PROBED: Mutex<BTreeMap<PathBuf, Option<Skipped>>> = Mutex::new(BTreeMap::new());
+
+// Give up on entries that don't answer within per_entry, and on every entry not
+// looked at before deadline, for the rest of the process. Entries given up on
+// are treated like skip_slow_drives() treats slow drives.
+#[cfg(feature = "cli")]
+pub(crate) fn set_probe_budget(per_entry: Option<Duration>, deadline: Option<Instant>) {
+    if per_entry.is_some() || deadline.is_some() {
+        let _ = BUDGET.set(Budget {
+            per_entry,
+            deadline,
+        });
+    }
+}
+
+// Look at path in a thread of its own and wait at most timeout for it, telling
+// if it answered. A hung network mount can't be interrupted, so the thread is
+// left behind and goes away with the process.
+fn probe(path: &Path, timeout: Duration) -> bool {
+    let (sender, receiver) = mpsc::channel();
+    let owned = path.to_path_buf();
+    // The options of a PathSet are only in place on this thread:
+    let options = SCOPED.with_borrow(Option::clone).unwrap_or_default();
+    thread::spawn(move || {
+        let found = with_options(&options, || on_host(&owned).and_then(fs::metadata).is_ok());
+        let _ = sender.send(found);
+    });
+    receiver.recv_timeout(timeout).is_ok()
+}
+
+// Each entry is only probed once, later lookups go by how that went
+fn over_budget(path: &Path) -> Option<Skipped> {
+    let budget = BUDGET.get()?;
+    let mut probed = PROBED.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(skipped) = probed.get(path) {
+        return *skipped;
+    }
+    let left = budget
+        .deadline
+        .map(|d| d.saturating_duration_since(Instant::now()));
+    let (timeout, reason) = match (budget.per_entry, left) {
+        (Some(per_entry), Some(left)) if left < per_entry => (left, Skipped::PastDeadline),
+        (Some(per_entry), _) => (per_entry, Skipped::TooSlow),
+        (None, left) => (left.unwrap_or_default(), Skipped::PastDeadline),
+    };
+    let skipped = (timeout.is_zero() || !probe(path, timeout)).then_some(reason);
+    probed.insert(path.to_path_buf(), skipped);
+    skipped
+}
+
+pub(crate) fn skipped(path: &Path) -> Option<Skipped> {
+    if SKIP_SLOW_DRIVES.load(Ordering::Relaxed) && drive_of(path).is_some_and(|d| is_slow_drive(&d))
+    {
+        return Some(Skipped::SlowDrive);
+    }
+    over_budget(path)
+}
+
+pub(crate) fn is_skipped(path: &Path) -> bool {
+    skipped(path).is_some()
+}
+
+// The entries given up on so far for taking too long or being past the deadline
+#[cfg(feature = "cli")]
+pub(crate) fn skipped_entries() -> Vec<(PathBuf, Skipped)> {
+    let probed = PROBED.lock().unwrap_or_else(|e| e.into_inner());
+    probed
+        .iter()
+        .filter_map(|(p, skipped)| skipped.map(|s| (p.clone(), s)))
+        .collect()
+}
+
+// App execution aliases (like those in WindowsApps) are zero-byte reparse points
+// that can't be resolved, but still run fine
+fn is_app_exec_alias(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("exe"))
+        && fs::symlink_metadata(path).is_ok_and(|m| is_alias_file(file_attributes(&m), m.len()))
+}
+
+// A reparse point with no data of its own, by its attributes and length
+fn is_alias_file(attributes: u32, len: u64) -> bool {
+    const FILE_ATTRIBUTE_REPARSE_POINT: u32 = 0x400;
+    attributes & FILE_ATTRIBUTE_REPARSE_POINT != 0 && len == 0
+}
+
+#[cfg(windows)]
+fn file_attributes(meta: &fs::Metadata) -> u32 {
+    use std::os::windows::fs::MetadataExt;
+    meta.file_attributes()
+}
+
+// Other systems have no reparse points
+#[cfg(not(windows))]
+fn file_attributes(_meta: &fs::Metadata) -> u32 {
+    0
+}
+
+// The extensions Windows runs a file by, like .EXE, upper case as given
+fn pathext() -> Vec<String> {
+    env::var("PATHEXT")
+        .unwrap_or(String::from(".COM;.EXE;.BAT;.CMD"))
+        .split(';')
+        .filter(|e| !e.is_empty())
+        .map(|e| e.to_uppercase())
+        .collect()
+}
+
+/// Only regular files count, following symlinks, so directories with the search bit
+/// set and data files are left out
+#[cfg(unix)]
+pub fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+
+    fs::metadata(path).is_ok_and(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+}
+
+/// Windows has no execute bit, a file runs if its extension is in PATHEXT
+#[cfg(not(unix))]
+pub fn is_executable(path: &Path) -> bool {
+    let has_ext = path.extension().is_some_and(|e| {
+        let ext = format!(".{}", e.to_string_lossy().to_uppercase());
+        pathext().contains(&ext)
+    });
+    has_ext && fs::metadata(path).is_ok_and(|m| m.is_file())
+}
+
+/// List all executables in a path, as found in the path rather than resolved
+/// Sorted, as the order of a directory listing depends on the file system
+pub fn list_executables(path: &Path) -> Result<Vec<PathBuf>> {
+    if is_skipped(path) {
+        return Err(io::Error::other("the entry is skipped").into());
+    }
+    Ok(fixture::executables(path, || {
+        // Under a root the names are read there, but given as inside it:
+        let mut exes: Vec<PathBuf> = fs::read_dir(on_host(path)?)?
+            .filter_map(|d| d.ok().map(|d| path.join(d.file_name())))
+            .filter(|p| is_executable_on_host(p))
+            .collect();
+        exes.sort();
+        Ok(exes)
+    })?)
+}
+
+/// Find the first executable called name in the entries of path_var
+pub fn find_executable(path_var: impl AsRef<OsStr>, name: &str) -> Option<PathBuf> {
+    find_executables(path_var, name)
+        .into_iter()
+        .next()
+        .map(|(_, p)| p)
+}
+
+/// Find all executables called name in the entries of path_var, in the order they
+/// are looked up and trying the extensions in PATHEXT as well on Windows. A name
+/// with a path separator in it is run as it is, so none are found for it.
+pub fn find_executables(path_var: impl AsRef<OsStr>, name: &str) -> Vec<(usize, PathBuf)> {
+    if name.is_empty() || name.contains(std::path::is_separator) {
+        return Vec::new();
+    }
+    let mut names = vec![name.to_string()];
+    if cfg!(windows) {
+        names.extend(pathext().iter().map(|e| format!("{}{}", name, e)));
+    }
+    split_entries(path_var)
+        .iter()
+        .enumerate()
+        .filter(|(_, dir)| !is_cwd_entry(dir))
+        .flat_map(|(i, dir)| names.iter().map(move |n| (i, dir.join(n))))
+        .filter(|(_, p)| fixture::is_executable(p, || is_executable_on_host(p)))
+        .collect()
+}
+
+/// The SHA-256 of the contents of a file, as lowercase hex
+#[cfg(feature = "cli")]
+pub fn file_sha256(file: &Path) -> Result<String> {
+    use sha2::{Digest, Sha256};
+    let mut hasher = Sha256::new();
+    io::copy(&mut fs::File::open(file)?, &mut hasher)?;
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect())
+}
+
+/// Commands are looked up without their extension and regardless of case on Windows
+pub fn command_name(exe: &Path) -> Option<String> {
+    if cfg!(windows) {
+        Some(exe.file_stem()?.to_string_lossy().to_lowercase())
+    } else {
+        Some(exe.file_name()?.to_string_lossy().into_owned())
+    }
+}
+
+/// List the executables of every entry by command name, with the position of the
+/// entry, so the first of each is the one that runs. An entry resolving to the same
+/// directory as an earlier one is skipped, as it can't provide anything new.
+pub fn scan_executables(paths: &[PathBuf]) -> BTreeMap<String, Vec<(usize, PathBuf)>> {
+    let mut commands: BTreeMap<String, Vec<(usize, PathBuf)>> = BTreeMap::new();
+    let mut scanned: HashSet<PathBuf> = HashSet::new();
+    for (i, p) in paths.iter().enumerate() {
+        if is_cwd_entry(p) || !scanned.insert(resolve(p).unwrap_or_else(|_| p.clone())) {
+            continue;
+        }
+        for exe in list_executables(p).unwrap_or_default() {
+            if let Some(name) = command_name(&exe) {
+                let found = commands.entry(name).or_default();
+                // Several extensions of one command in an entry count once on Windows:
+                if found.last().is_none_or(|(j, _)| *j != i) {
+                    found.push((i, exe));
+                }
+            }
+        }
+    }
+    commands
+}
+
+/// Count all executables in a path
+pub fn count_executables(path: &Path) -> Result<usize> {
+    Ok(list_executables(path)?.len())
+}
+
+/// The longest path Windows programs can open unless they opt in to long paths,
+/// not counting the terminating null
+pub const MAX_PATH: usize = 259;
+
+// Windows counts path lengths in UTF-16 units
+fn path_len(path: &Path) -> usize {
+    path.to_string_lossy().encode_utf16().count()
+}
+
+pub fn exceeds_max_path(path: &Path) -> Vec<PathBuf> {
+    list_executables(path)
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|exe| path_len(exe) > MAX_PATH)
+        .collect()
+}
+
+/// What is left after the entry and a separator, 0 if the entry itself is too long
+pub fn max_path_room(path: &Path) -> usize {
+    MAX_PATH.saturating_sub(path_len(path) + 1)
+}
+
+/// Even with LongPathsEnabled set, only programs with a manifest saying they can
+/// handle long paths get them
+#[cfg(windows)]
+pub fn long_paths_enabled() -> Option<bool> {
+    let key = r"HKLM\SYSTEM\CurrentControlSet\Control\FileSystem";
+    Some(registry_dword(key, "LongPathsEnabled").unwrap_or(0) == 1)
+}
+
+#[cfg(not(windows))]
+pub fn long_paths_enabled() -> Option<bool> {
+    None
+}
+
+/// Get groups of executable names that are the same when case is ignored, which
+/// resolve differently depending on the file system
+pub fn find_case_collisions(path: &Path) -> Result<Vec<Vec<String>>> {
+    let mut groups: HashMap<String, Vec<String>> = HashMap::new();
+    for exe in list_executables(path)? {
+        if let Some(name) = exe.file_name() {
+            let name = name.to_string_lossy().into_owned();
+            groups.entry(name.to_lowercase()).or_default().push(name);
+        }
+    }
+    let mut collisions: Vec<Vec<String>> = groups
+        .into_values()
+        .filter(|g| g.len() > 1)
+        .map(|mut g| {
+            g.sort();
+            g
+        })
+        .collect();
+    collisions.sort();
+    Ok(collisions)
+}
+
+/// Check if path contains no executables (special case of count_executables = 0)
 pub fn is_empty(path: &Path) -> Result<bool> {
-    Ok(count_files(path)? == 0)
+    Ok(count_executables(path)? == 0)
+}
+
+/// Check if path contains no files or directories at all
+pub fn is_empty_dir(path: &Path) -> Result<bool> {
+    if is_skipped(path) {
+        return Err(io::Error::other("the entry is skipped").into());
+    }
+    Ok(fs::read_dir(on_host(path)?)?.next().is_none())
+}
+
+/// Check if path is on a file system that is mounted read-only
+#[cfg(unix)]
+pub fn is_read_only(path: &Path) -> Result<bool> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let c_path = CString::new(on_host(path)?.as_os_str().as_bytes())?;
+    // SAFETY: c_path is NUL-terminated and stat is only read after the call succeeded
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(c_path.as_ptr(), &mut stat) } != 0 {
+        return Err(io::Error::last_os_error().into());
+    }
+    Ok(stat.f_flag & libc::ST_RDONLY != 0)
+}
+
+#[cfg(not(unix))]
+pub fn is_read_only(_path: &Path) -> Result<bool> {
+    Ok(false)
+}
+
+/// Check if path has the immutable attribute (chattr +i), file systems that don't
+/// support attributes at all are treated as not immutable
+#[cfg(target_os = "linux")]
+pub fn is_immutable(path: &Path) -> Result<bool> {
+    use std::os::unix::io::AsRawFd;
+
+    // From linux/fs.h:
+    const FS_IMMUTABLE_FL: libc::c_long = 0x10;
+    let dir = fs::File::open(on_host(path)?)?;
+    let mut flags: libc::c_long = 0;
+    // SAFETY: the descriptor is open for the duration of the call and flags outlives it
+    if unsafe { libc::ioctl(dir.as_raw_fd(), libc::FS_IOC_GETFLAGS, &mut flags) } != 0 {
+        return Ok(false);
+    }
+    Ok(flags & FS_IMMUTABLE_FL != 0)
+}
+
+/// Check if path has the user or system immutable flag (chflags uchg/schg)
+#[cfg(target_os = "macos")]
+pub fn is_immutable(path: &Path) -> Result<bool> {
+    use std::os::macos::fs::MetadataExt;
+
+    let flags = fs::metadata(on_host(path)?)?.st_flags();
+    Ok(flags & (libc::UF_IMMUTABLE | libc::SF_IMMUTABLE) != 0)
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+pub fn is_immutable(_path: &Path) -> Result<bool> {
+    Ok(false)
+}
+
+pub struct Mount {
+    pub point: PathBuf,
+    pub fs_type: String,
+}
+
+// Undo the octal escapes of spaces, tabs and so on in mount points
+#[cfg(target_os = "linux")]
+fn unescape_mount_point(point: &str) -> String {
+    let mut unescaped = String::new();
+    let mut rest = point;
+    while let Some(i) = rest.find('\\') {
+        unescaped.push_str(&rest[..i]);
+        match rest
+            .get(i + 1..i + 4)
+            .and_then(|o| u8::from_str_radix(o, 8).ok())
+        {
+            Some(c) => {
+                unescaped.push(c as char);
+                rest = &rest[i + 4..];
+            }
+            None => {
+                unescaped.push('\\');
+                rest = &rest[i + 1..];
+            }
+        }
+    }
+    unescaped.push_str(rest);
+    unescaped
+}
+
+// Read mount points and types from the contents of /proc/self/mountinfo
+#[cfg(target_os = "linux")]
+fn parse_mountinfo(mountinfo: &str) -> Vec<Mount> {
+    mountinfo
+        .lines()
+        .filter_map(|line| {
+            let (fields, rest) = line.split_once(" - ")?;
+            let point = fields.split(' ').nth(4)?;
+            let fs_type = rest.split(' ').next()?;
+            Some(Mount {
+                point: PathBuf::from(unescape_mount_point(point)),
+                fs_type: fs_type.to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Find the mount a path is on as the longest mount point containing it, the last
+/// one listed wins if something is mounted on top of another mount
+#[cfg(target_os = "linux")]
+pub fn mount_of(path: &Path) -> Result<Mount> {
+    // Under another root, what the entry is on here is what counts:
+    let res = on_host(path)?.canonicalize()?;
+    let mountinfo = fs::read_to_string("/proc/self/mountinfo")?;
+    parse_mountinfo(&mountinfo)
+        .into_iter()
+        .filter(|m| res.starts_with(&m.point))
+        .max_by_key(|m| m.point.as_os_str().len())
+        .ok_or_else(|| Error::NoMount(path.to_path_buf()))
+}
+
+#[cfg(target_os = "macos")]
+pub fn mount_of(path: &Path) -> Result<Mount> {
+    use std::ffi::{CStr, CString};
+    use std::os::unix::ffi::OsStrExt;
+
+    let c_path = CString::new(on_host(path)?.as_os_str().as_bytes())?;
+    // SAFETY: c_path is NUL-terminated and stat is only read after the call succeeded
+    let mut stat: libc::statfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statfs(c_path.as_ptr(), &mut stat) } != 0 {
+        return Err(io::Error::last_os_error().into());
+    }
+    // SAFETY: the kernel fills both names as NUL-terminated strings
+    let (point, fs_type) = unsafe {
+        (
+            CStr::from_ptr(stat.f_mntonname.as_ptr()),
+            CStr::from_ptr(stat.f_fstypename.as_ptr()),
+        )
+    };
+    Ok(Mount {
+        point: PathBuf::from(point.to_string_lossy().into_owned()),
+        fs_type: fs_type.to_string_lossy().into_owned(),
+    })
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+pub fn mount_of(_path: &Path) -> Result<Mount> {
+    Err(Error::Unsupported("mounts"))
 }
 
-// Get elements occurring more than once
+/// File systems where every lookup may go over the network, including the 9p
+/// mounts WSL uses for Windows drives
+pub fn is_network_fs(fs_type: &str) -> bool {
+    matches!(
+        fs_type,
+        "nfs" | "nfs4" | "cifs" | "smb3" | "smbfs" | "afpfs" | "webdav" | "9p" | "afs"
+    ) || fs_type.starts_with("fuse.sshfs")
+        || fs_type.starts_with("fuse.rclone")
+}
+
+/// Compare the PATH of this process with the one new processes would get, to find
+/// entries added along the way (by a terminal or IDE) and those not picked up yet.
+/// Windows paths are the same regardless of case and trailing separators.
+pub fn find_drift(
+    current: impl AsRef<OsStr>,
+    composed: impl AsRef<OsStr>,
+) -> (Vec<PathBuf>, Vec<PathBuf>) {
+    let key = |p: &PathBuf| {
+        let s = p.to_string_lossy();
+        if cfg!(windows) {
+            s.trim_end_matches(['\\', '/']).to_lowercase()
+        } else {
+            s.trim_end_matches('/').to_string()
+        }
+    };
+    let current = dedup_literal(split_entries(current));
+    let composed = dedup_literal(split_entries(composed));
+    let current_keys: HashSet<String> = current.iter().map(key).collect();
+    let composed_keys: HashSet<String> = composed.iter().map(key).collect();
+    let added = current
+        .into_iter()
+        .filter(|p| !composed_keys.contains(&key(p)))
+        .collect();
+    let missing = composed
+        .into_iter()
+        .filter(|p| !current_keys.contains(&key(p)))
+        .collect();
+    (added, missing)
+}
+
+fn dedup_literal(paths: Vec<PathBuf>) -> Vec<PathBuf> {
+    let mut seen: HashSet<PathBuf> = HashSet::new();
+    paths
+        .into_iter()
+        .filter(|p| seen.insert(p.clone()))
+        .collect()
+}
+
+/// How an entry differs between two lists of entries
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum DiffKind {
+    Same,
+    Added,
+    Removed,
+    /// In both, but in another order relative to the other entries
+    Moved,
+}
+
+/// An entry of either list, with its positions in them, counted from 0
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct DiffEntry {
+    pub kind: DiffKind,
+    pub path: PathBuf,
+    pub old_index: Option<usize>,
+    pub new_index: Option<usize>,
+}
+
+/// Compare old and new entries as written, in the order of new with removed entries
+/// where they were. Entries kept in the same order relative to each other are the
+/// longest common subsequence, any other entry in both lists has moved.
+pub fn diff(old: &[PathBuf], new: &[PathBuf]) -> Vec<DiffEntry> {
+    // lcs[i][j] is the length of the longest common subsequence of old[i..] and new[j..]:
+    let mut lcs = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if old[i] == new[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+    let entry = |kind, path: &PathBuf, old_index, new_index| DiffEntry {
+        kind,
+        path: path.clone(),
+        old_index,
+        new_index,
+    };
+    let mut entries: Vec<DiffEntry> = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            entries.push(entry(DiffKind::Same, &old[i], Some(i), Some(j)));
+            i += 1;
+            j += 1;
+        } else if i < old.len() && (j == new.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            entries.push(entry(DiffKind::Removed, &old[i], Some(i), None));
+            i += 1;
+        } else {
+            entries.push(entry(DiffKind::Added, &new[j], None, Some(j)));
+            j += 1;
+        }
+    }
+    // An entry both removed and added has moved, and is shown where it ended up:
+    let mut moved = vec![false; entries.len()];
+    for k in 0..entries.len() {
+        if entries[k].kind != DiffKind::Added {
+            continue;
+        }
+        let removed = (0..entries.len()).find(|&r| {
+            !moved[r] && entries[r].kind == DiffKind::Removed && entries[r].path == entries[k].path
+        });
+        if let Some(r) = removed {
+            moved[r] = true;
+            entries[k].kind = DiffKind::Moved;
+            entries[k].old_index = entries[r].old_index;
+        }
+    }
+    entries
+        .into_iter()
+        .zip(moved)
+        .filter_map(|(e, moved)| (!moved).then_some(e))
+        .collect()
+}
+
+// Windows and macOS file systems are case-insensitive unless set up otherwise, so
+// /Users/me/Bin and /users/me/bin are the same directory there
+static IGNORE_CASE: AtomicBool = AtomicBool::new(cfg!(any(windows, target_os = "macos")));
+
+// Compare entries regardless of case, or not, for the rest of the process. Used
+// for duplicates, dedup and additions, where the default depends on the platform.
+#[cfg(feature = "cli")]
+pub(crate) fn set_ignore_case(ignore: bool) {
+    IGNORE_CASE.store(ignore, Ordering::Relaxed);
+}
+
+// Check if a root, expansion, case or a probe budget was set differently from
+// the defaults, which a daemon scanning with the defaults can't answer for
+#[cfg(feature = "cli")]
+pub(crate) fn is_tuned() -> bool {
+    ROOT.get().is_some()
+        || EXPAND.load(Ordering::Relaxed)
+        || BUDGET.get().is_some()
+        || SKIP_SLOW_DRIVES.load(Ordering::Relaxed)
+        || IGNORE_CASE.load(Ordering::Relaxed) != cfg!(any(windows, target_os = "macos"))
+}
+
+// Entries as compared, lower case when case is ignored. Paths that aren't Unicode
+// are left as they are.
+fn fold(path: &Path, ignore_case: bool) -> PathBuf {
+    match path.to_str() {
+        Some(s) if ignore_case => PathBuf::from(s.to_lowercase()),
+        _ => path.to_path_buf(),
+    }
+}
+
+/// What entries are compared by, so that the same directory written differently
+/// is still the same entry
+pub fn case_key(path: &Path) -> PathBuf {
+    let ignore_case = SCOPED.with_borrow(|s| s.as_ref().and_then(|o| o.ignore_case));
+    fold(
+        &normalize(path),
+        ignore_case.unwrap_or_else(|| IGNORE_CASE.load(Ordering::Relaxed)),
+    )
+}
+
+/// Get the positions of all entries equal to target, comparing them resolved if asked
+pub fn find_positions(paths: &[PathBuf], target: &Path, resolved: bool) -> Vec<usize> {
+    let target = case_key(target);
+    paths
+        .iter()
+        .enumerate()
+        .filter(|(_, p)| {
+            if resolved {
+                case_key(&resolve(p).unwrap_or(p.to_path_buf())) == target
+            } else {
+                case_key(p) == target
+            }
+        })
+        .map(|(i, _)| i)
+        .collect()
+}
+
+/// Get elements occurring more than once
 pub fn find_duplicates(paths: &[PathBuf]) -> Vec<PathBuf> {
     let mut seen: HashSet<PathBuf> = HashSet::new();
     let mut duplicates: Vec<PathBuf> = Vec::new();
 
     for path in paths {
-        if seen.contains(path) {
+        if !seen.insert(case_key(path)) {
             duplicates.push(path.clone());
-        } else {
-            seen.insert(path.clone());
         }
     }
     duplicates
 }
 
-// Get elements occurring more than once when resolved
+/// Get elements occurring more than once when resolved
 pub fn find_duplicates_resolved(paths: &[PathBuf]) -> Vec<PathBuf> {
     let mut seen: HashSet<PathBuf> = HashSet::new();
     let mut duplicates: Vec<PathBuf> = Vec::new();
 
     for path in paths {
-        let res = match path.canonicalize() {
-            Ok(p) => p,
-            _ => path.clone(),
+        let res = match resolve(path) {
+            Ok(p) => p,
+            _ => path.clone(),
+        };
+        if !seen.insert(case_key(&res)) {
+            duplicates.push(res);
+        }
+    }
+    duplicates
+}
+
+// Spaces that look like a plain one but aren't
+fn is_odd_space(c: char) -> bool {
+    matches!(c, '\u{a0}' | '\u{2007}' | '\u{202f}' | '\u{3000}')
+}
+
+// Characters that don't show at all, like zero-width spaces, byte order marks, soft
+// hyphens and direction marks, which come along when copying from web pages
+fn is_invisible(c: char) -> bool {
+    c.is_control()
+        || matches!(
+            c,
+            '\u{ad}' | '\u{200b}'..='\u{200f}' | '\u{202a}'..='\u{202e}' | '\u{2060}'..='\u{2064}'
+                | '\u{2066}'..='\u{2069}' | '\u{feff}'
+        )
+}
+
+pub fn escape_invisible(path: &Path) -> String {
+    let s = path.to_string_lossy();
+    let start = s.len() - s.trim_start().len();
+    let end = s.trim_end().len();
+    s.char_indices()
+        .map(|(i, c)| {
+            if is_invisible(c) || is_odd_space(c) || (c.is_whitespace() && (i < start || i >= end))
+            {
+                format!("\\u{{{:x}}}", c as u32)
+            } else {
+                c.to_string()
+            }
+        })
+        .collect()
+}
+
+/// A path that isn't valid Unicode is left as it is
+pub fn strip_invisible(path: &Path) -> PathBuf {
+    let Some(s) = path.to_str() else {
+        return path.to_path_buf();
+    };
+    let stripped: String = s
+        .chars()
+        .filter(|&c| !is_invisible(c))
+        .map(|c| if is_odd_space(c) { ' ' } else { c })
+        .collect();
+    PathBuf::from(stripped.trim())
+}
+
+/// Where a path has invisible characters or odd spaces, as "at the start", "in the
+/// middle" and "at the end", in that order and only the ones that apply
+pub fn invisible_places(path: &Path) -> Vec<&'static str> {
+    let Some(s) = path.to_str() else {
+        return Vec::new();
+    };
+    let odd = |c: char| is_invisible(c) || is_odd_space(c);
+    let at_end = |c: char| odd(c) || c.is_whitespace();
+    let inner = s.trim_matches(at_end);
+    let mut places = Vec::new();
+    if s.starts_with(at_end) {
+        places.push("at the start");
+    }
+    if inner.contains(odd) {
+        places.push("in the middle");
+    }
+    if s.ends_with(at_end) && !inner.is_empty() {
+        places.push("at the end");
+    }
+    places
+}
+
+/// The same directory as written, without what components leave out: trailing
+/// and repeated separators and '.' segments. '..' is kept, as it isn't the same
+/// once links are followed, and an entry for the current directory stays one
+pub fn normalize(path: &Path) -> PathBuf {
+    let normalized: PathBuf = path
+        .components()
+        .filter(|c| *c != Component::CurDir)
+        .collect();
+    match normalized.as_os_str().is_empty() && !path.as_os_str().is_empty() {
+        true => PathBuf::from("."),
+        false => normalized,
+    }
+}
+
+/// A path that isn't valid Unicode is left as it is
+pub fn normalize_unicode(path: &Path) -> PathBuf {
+    match path.to_str() {
+        Some(s) => PathBuf::from(s.nfc().collect::<String>()),
+        None => path.to_path_buf(),
+    }
+}
+
+/// Paths that look the same are told apart by the form they are written in
+pub fn unicode_form(path: &Path) -> &'static str {
+    let s = path.to_string_lossy();
+    if s.nfc().eq(s.chars()) {
+        "NFC"
+    } else if s.nfd().eq(s.chars()) {
+        "NFD"
+    } else {
+        "mixed"
+    }
+}
+
+/// Get pairs of entries that only differ in how characters like é are written, as
+/// one character (NFC) or a letter followed by a combining accent (NFD) like macOS
+/// tends to hand out. Compared as resolved where possible, the later one second.
+pub fn find_duplicates_normalized(paths: &[PathBuf]) -> Vec<(PathBuf, PathBuf)> {
+    let mut seen: HashMap<PathBuf, (PathBuf, PathBuf)> = HashMap::new();
+    let mut duplicates: Vec<(PathBuf, PathBuf)> = Vec::new();
+
+    for path in paths {
+        let res = resolve(path).unwrap_or_else(|_| path.clone());
+        let key = normalize_unicode(&res);
+        match seen.get(&key) {
+            Some((first, first_res)) if *first_res != res => {
+                duplicates.push((first.clone(), path.clone()))
+            }
+            Some(_) => {}
+            None => {
+                seen.insert(key, (path.clone(), res));
+            }
+        }
+    }
+    duplicates
+}
+
+// Device and inode numbers identifying a directory however it is reached
+#[cfg(unix)]
+fn dir_id(path: &Path) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    fs::metadata(on_host(path).ok()?)
+        .ok()
+        .map(|m| (m.dev(), m.ino()))
+}
+
+#[cfg(not(unix))]
+fn dir_id(_path: &Path) -> Option<(u64, u64)> {
+    None
+}
+
+/// Get pairs of entries that are the same directory but don't resolve to the same
+/// path, like with bind mounts, which canonicalize() can't see through
+pub fn find_duplicates_by_id(paths: &[PathBuf]) -> Vec<(PathBuf, PathBuf)> {
+    duplicates_by_id(paths, dir_id)
+}
+
+// The same, with how to get the id of a directory given, so tests can stand in
+// for a bind mount without being root
+fn duplicates_by_id(
+    paths: &[PathBuf],
+    id: impl Fn(&Path) -> Option<(u64, u64)>,
+) -> Vec<(PathBuf, PathBuf)> {
+    let mut seen: HashMap<(u64, u64), PathBuf> = HashMap::new();
+    let mut duplicates: Vec<(PathBuf, PathBuf)> = Vec::new();
+
+    for path in paths {
+        let Some(id) = id(path) else {
+            continue;
         };
-        if seen.contains(&res) {
-            duplicates.push(res);
-        } else {
-            seen.insert(res);
+        match seen.get(&id) {
+            Some(first) if resolve(first).ok() != resolve(path).ok() => {
+                duplicates.push((first.clone(), path.clone()));
+            }
+            Some(_) => {}
+            None => {
+                seen.insert(id, path.clone());
+            }
         }
     }
     duplicates
 }
 
-// Return unique entries while maintaining order
+/// Return unique entries while maintaining order
 pub fn dedup(paths: &[PathBuf]) -> Vec<PathBuf> {
+    dedup_positions(paths, Keep::First)
+        .into_iter()
+        .map(|i| paths[i].clone())
+        .collect()
+}
+
+/// Which of the entries that are the same dedup keeps
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Keep {
+    /// The one looked at first, so nothing changes what runs
+    First,
+    /// The one added last, when later additions are meant to win
+    Last,
+}
+
+/// The positions of the entries left after removing those that are the same as
+/// written or resolved, in order
+pub fn dedup_positions(paths: &[PathBuf], keep: Keep) -> Vec<usize> {
     let mut seen: HashSet<PathBuf> = HashSet::new();
-    let mut unique: Vec<PathBuf> = Vec::new();
+    let mut unique: Vec<usize> = Vec::new();
     let mut resolved: HashSet<PathBuf> = HashSet::new();
 
-    for path in paths {
-        let res = match path.canonicalize() {
-            Ok(p) => p,
+    let order: Vec<usize> = match keep {
+        Keep::First => (0..paths.len()).collect(),
+        Keep::Last => (0..paths.len()).rev().collect(),
+    };
+    for i in order {
+        let path = case_key(&paths[i]);
+        let res = match resolve(&paths[i]) {
+            Ok(p) => case_key(&p),
             _ => path.clone(),
         };
-        if !seen.contains(path) && !resolved.contains(&res) {
-            seen.insert(path.clone());
+        if !seen.contains(&path) && !resolved.contains(&res) {
+            seen.insert(path);
             resolved.insert(res);
-            unique.push(path.clone());
+            unique.push(i);
         }
     }
+    unique.sort();
     unique
 }
 
-// Verify that addition is not already in path string
-fn ensure_unique_addition(path_var: impl AsRef<OsStr>, addition: impl AsRef<OsStr>) -> Result<()> {
+/// A kind of entry to pick out with select
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Filter {
+    /// Not an accessible directory
+    Missing,
+    /// Without executables, or without anything for variables other than PATH
+    Empty,
+    /// The same as an earlier entry, as written or resolved
+    Duplicate,
+    /// None of the above
+    Ok,
+}
+
+impl Filter {
+    /// Check an entry, given if it repeats an earlier one and if it should hold
+    /// executables rather than anything at all
+    pub fn matches(self, path: &Path, duplicate: bool, executables: bool) -> bool {
+        // Nothing is known about an entry given up on, so it is none of them:
+        if is_skipped(path) {
+            return false;
+        }
+        let missing = !exists(path);
+        let empty = || {
+            !missing
+                && match executables {
+                    true => is_empty(path),
+                    false => is_empty_dir(path),
+                }
+                .unwrap_or(false)
+        };
+        match self {
+            Filter::Missing => missing,
+            Filter::Empty => empty(),
+            Filter::Duplicate => duplicate,
+            Filter::Ok => !missing && !duplicate && !empty(),
+        }
+    }
+}
+
+/// Get the positions of entries any of the filters hold for, in order, where a
+/// duplicate is any entry after the first of the same directory
+pub fn select(paths: &[PathBuf], filters: &[Filter], executables: bool) -> Vec<usize> {
+    let mut seen: HashSet<PathBuf> = HashSet::new();
+    paths
+        .iter()
+        .enumerate()
+        .filter(|(_, p)| {
+            let key = case_key(p);
+            let res = case_key(&resolve(p).unwrap_or_else(|_| p.to_path_buf()));
+            let duplicate = seen.contains(&key) || seen.contains(&res);
+            seen.insert(key);
+            seen.insert(res);
+            filters.iter().any(|f| f.matches(p, duplicate, executables))
+        })
+        .map(|(i, _)| i)
+        .collect()
+}
+
+/// Remove every entry that is, or resolves to the same directory as, one of targets
+pub fn remove_paths(path_var: impl AsRef<OsStr>, targets: &[PathBuf]) -> Result<OsString> {
+    let resolved: HashSet<PathBuf> = targets
+        .iter()
+        .flat_map(|p| resolve(p))
+        .map(|p| case_key(&p))
+        .collect();
+    let targets: HashSet<PathBuf> = targets.iter().map(|p| case_key(p)).collect();
+    let paths: Vec<PathBuf> = split_entries(path_var)
+        .into_iter()
+        .filter(|p| {
+            !targets.contains(&case_key(p))
+                && !resolve(p).is_ok_and(|res| resolved.contains(&case_key(&res)))
+        })
+        .collect();
+    join(&paths)
+}
+
+/// Get the positions of entries equal to target as written or after resolving both
+pub fn find_matches(paths: &[PathBuf], target: &Path) -> Vec<usize> {
+    let res = resolve(target).ok().map(|r| case_key(&r));
+    let target = case_key(target);
+    paths
+        .iter()
+        .enumerate()
+        .filter(|(_, p)| {
+            case_key(p) == target || res.is_some() && resolve(p).ok().map(|r| case_key(&r)) == res
+        })
+        .map(|(i, _)| i)
+        .collect()
+}
+
+/// Remove every entry that matches any of the glob patterns, as written or resolved
+#[cfg(feature = "cli")]
+pub fn remove_matching(path_var: impl AsRef<OsStr>, patterns: &[String]) -> Result<OsString> {
+    let patterns: Vec<glob::Pattern> = patterns
+        .iter()
+        .map(|p| {
+            glob::Pattern::new(p).map_err(|source| Error::Pattern {
+                pattern: p.clone(),
+                source,
+            })
+        })
+        .collect::<Result<_>>()?;
+    let matches = |p: &Path| patterns.iter().any(|pat| pat.matches_path(p));
+    let paths: Vec<PathBuf> = split_entries(path_var)
+        .into_iter()
+        .filter(|p| !matches(p) && !resolve(p).is_ok_and(|res| matches(&res)))
+        .collect();
+    join(&paths)
+}
+
+// Subdirectories of an install prefix that go in each kind of variable
+fn prefix_subdirs(var: &str) -> &'static [&'static str] {
+    match var {
+        "PATH" => &["bin", "sbin"],
+        "MANPATH" => &["share/man", "man"],
+        "PKG_CONFIG_PATH" => &["lib/pkgconfig", "share/pkgconfig"],
+        "LD_LIBRARY_PATH" => &["lib", "lib64"],
+        _ => &[],
+    }
+}
+
+/// All directories a prefix could have contributed to var, existing or not
+pub fn prefix_paths(prefix: &Path, var: &str) -> Vec<PathBuf> {
+    prefix_subdirs(var).iter().map(|d| prefix.join(d)).collect()
+}
+
+/// Directories of a prefix that exist and should be added to var
+pub fn prefix_dirs(prefix: &Path, var: &str) -> Vec<PathBuf> {
+    prefix_paths(prefix, var)
+        .into_iter()
+        .filter(|p| exists(p))
+        .collect()
+}
+
+/// Verify that addition is not already in path string
+pub fn ensure_unique_addition(
+    path_var: impl AsRef<OsStr>,
+    addition: impl AsRef<OsStr>,
+) -> Result<()> {
     let path_to_add = PathBuf::from(&addition);
     let unique_paths = split_hs(path_var);
-    ensure!(
-        !unique_paths.contains(&path_to_add),
-        format!("PATH already contains '{}'", path_to_add.display())
-    );
-    let res = path_to_add.canonicalize().unwrap_or(path_to_add.clone());
-    let unique_resolved: HashSet<PathBuf> =
-        unique_paths.iter().flat_map(|p| p.canonicalize()).collect();
-    ensure!(
-        !unique_resolved.contains(&res),
-        if path_to_add.as_os_str() == res.as_os_str() {
-            format!(
-                "PATH already contains a path that resolves to '{}'",
-                path_to_add.display()
-            )
-        } else {
-            format!(
-                "'{}' -> '{}', PATH already contains a path that resolves to '{}'",
-                path_to_add.display(),
-                res.display(),
-                res.display()
-            )
-        }
-    );
+    if let Some(present) = unique_paths
+        .iter()
+        .find(|p| case_key(p) == case_key(&path_to_add))
+    {
+        return Err(Error::Present(format!(
+            "PATH already contains '{}'",
+            present.display()
+        )));
+    }
+    let res = resolve(&path_to_add).unwrap_or(path_to_add.clone());
+    let unique_resolved: HashSet<PathBuf> = unique_paths
+        .iter()
+        .flat_map(|p| resolve(p))
+        .map(|p| case_key(&p))
+        .collect();
+    if unique_resolved.contains(&case_key(&res)) {
+        return Err(Error::Present(
+            if path_to_add.as_os_str() == res.as_os_str() {
+                format!(
+                    "PATH already contains a path that resolves to '{}'",
+                    path_to_add.display()
+                )
+            } else {
+                format!(
+                    "'{}' -> '{}', PATH already contains a path that resolves to '{}'",
+                    path_to_add.display(),
+                    res.display(),
+                    res.display()
+                )
+            },
+        ));
+    }
     Ok(())
 }
 
-// Add addition to the end of path_var
-pub fn append_path(path_var: impl AsRef<OsStr>, addition: impl AsRef<OsStr>) -> Result<String> {
+/// Add addition to the end of path_var
+pub fn append_path(path_var: impl AsRef<OsStr>, addition: impl AsRef<OsStr>) -> Result<OsString> {
     // Now add while preserving order:
-    let mut paths = split(path_var);
+    let mut paths = split_entries(path_var);
     paths.push(PathBuf::from(&addition));
     join(&paths)
 }
 
-// Add addition to the front of path_var
-pub fn prepend_path(path_var: impl AsRef<OsStr>, addition: impl AsRef<OsStr>) -> Result<String> {
+/// Add addition to the front of path_var
+pub fn prepend_path(path_var: impl AsRef<OsStr>, addition: impl AsRef<OsStr>) -> Result<OsString> {
     // Now add while preserving order:
-    let mut paths = split(path_var);
+    let mut paths = split_entries(path_var);
     paths.insert(0, PathBuf::from(&addition));
     join(&paths)
 }
 
-// Combine some unique-ness and existance check
+/// Split every argument like a PATH string, keeping the first of any entry given
+/// twice. An empty argument is the empty entry, for the current directory.
+pub fn split_additions(args: &[impl AsRef<OsStr>]) -> Vec<PathBuf> {
+    let mut additions: Vec<PathBuf> = Vec::new();
+    for p in args.iter().flat_map(split) {
+        if !additions.contains(&p) {
+            additions.push(p);
+        }
+    }
+    additions
+}
+
+/// Add additions to the front of path_var, all at once so they keep their order
+pub fn prepend_paths(path_var: impl AsRef<OsStr>, additions: &[PathBuf]) -> Result<OsString> {
+    let mut paths = additions.to_vec();
+    paths.extend(split_entries(path_var));
+    join(&paths)
+}
+
+/// Add additions to the end of path_var, in the order given
+pub fn append_paths(path_var: impl AsRef<OsStr>, additions: &[PathBuf]) -> Result<OsString> {
+    let mut paths = split_entries(path_var);
+    paths.extend_from_slice(additions);
+    join(&paths)
+}
+
+/// Add additions so the first ends up at index, which may be the number of entries
+/// to add them at the end
+pub fn insert_paths(
+    path_var: impl AsRef<OsStr>,
+    index: usize,
+    additions: &[PathBuf],
+) -> Result<OsString> {
+    let mut paths = split_entries(path_var);
+    if index > paths.len() {
+        return Err(Error::Position {
+            index,
+            len: paths.len(),
+        });
+    }
+    paths.splice(index..index, additions.iter().cloned());
+    join(&paths)
+}
+
+/// Move the entry at from so it ends up at to, counted with the entry in place
+pub fn move_entry(path_var: impl AsRef<OsStr>, from: usize, to: usize) -> Result<OsString> {
+    let mut paths = split_entries(path_var);
+    for index in [from, to] {
+        if index >= paths.len() {
+            return Err(Error::Position {
+                index,
+                len: paths.len().saturating_sub(1),
+            });
+        }
+    }
+    let entry = paths.remove(from);
+    paths.insert(to, entry);
+    join(&paths)
+}
+
+/// Combine some unique-ness and existance check
 pub fn validate_addition(path_var: impl AsRef<OsStr>, addition: impl AsRef<OsStr>) -> Result<()> {
     let path_to_add = Path::new(&addition);
-    ensure!(
-        exists(path_to_add),
-        format!(
-            "'{}' is not an existing directory",
-            addition.as_ref().to_string_lossy()
-        )
-    );
+    if !exists(path_to_add) {
+        return Err(Error::Missing(
+            addition.as_ref().to_string_lossy().into_owned(),
+        ));
+    }
     ensure_unique_addition(path_var, addition)
 }
 
@@ -251,12 +2430,102 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_insert_paths() {
+        let additions = [PathBuf::from("/x"), PathBuf::from("/y")];
+        let joined = |s: &str| join(&split_entries(s)).unwrap();
+        assert_eq!(
+            insert_paths(joined("/a:/b"), 1, &additions).unwrap(),
+            joined("/a:/x:/y:/b")
+        );
+        assert_eq!(
+            insert_paths(joined("/a:/b"), 2, &additions).unwrap(),
+            joined("/a:/b:/x:/y")
+        );
+        assert!(matches!(
+            insert_paths(joined("/a:/b"), 3, &additions),
+            Err(Error::Position { index: 3, len: 2 })
+        ))
+    }
+
+    #[test]
+    fn test_move_entry() {
+        let joined = |s: &str| join(&split_entries(s)).unwrap();
+        assert_eq!(
+            move_entry(joined("/a:/b:/c"), 2, 0).unwrap(),
+            joined("/c:/a:/b")
+        );
+        assert_eq!(
+            move_entry(joined("/a:/b:/c"), 0, 2).unwrap(),
+            joined("/b:/c:/a")
+        );
+        assert!(matches!(
+            move_entry(joined("/a:/b:/c"), 0, 3),
+            Err(Error::Position { index: 3, len: 2 })
+        ))
+    }
+
+    #[test]
+    fn test_select() {
+        let t = Test::new();
+        let missing = PathBuf::from("/nonexistent/bin");
+        let paths = [
+            t.exe_dir.clone(),
+            missing.clone(),
+            t.exe_dir.clone(),
+            missing,
+        ];
+        assert_eq!(select(&paths, &[Filter::Missing], true), vec![1, 3]);
+        assert_eq!(select(&paths, &[Filter::Duplicate], true), vec![2, 3]);
+        assert_eq!(select(&paths, &[Filter::Ok], true), vec![0]);
+        assert_eq!(
+            select(&paths, &[Filter::Ok, Filter::Duplicate], false),
+            vec![0, 2, 3]
+        )
+    }
+
+    #[test]
+    fn test_diff() {
+        let paths = |s: &str| split_entries(s);
+        let kinds = |old: &str, new: &str| -> Vec<(DiffKind, String)> {
+            diff(&paths(old), &paths(new))
+                .into_iter()
+                .map(|e| (e.kind, e.path.to_string_lossy().into_owned()))
+                .collect()
+        };
+        use DiffKind::*;
+        let expected = vec![
+            (Same, "/a".to_string()),
+            (Removed, "/b".to_string()),
+            (Added, "/x".to_string()),
+            (Same, "/c".to_string()),
+        ];
+        assert_eq!(kinds("/a:/b:/c", "/a:/x:/c"), expected);
+        let expected = vec![
+            (Moved, "/c".to_string()),
+            (Same, "/a".to_string()),
+            (Same, "/b".to_string()),
+        ];
+        assert_eq!(kinds("/a:/b:/c", "/c:/a:/b"), expected);
+        let moved = &diff(&paths("/a:/b:/c"), &paths("/c:/a:/b"))[0];
+        assert_eq!((moved.old_index, moved.new_index), (Some(2), Some(0)));
+        assert!(diff(&paths("/a"), &paths("/a"))
+            .iter()
+            .all(|e| e.kind == Same))
+    }
+
     #[test]
     fn test_get_path() {
         let p = get_path().unwrap();
         assert!(!p.is_empty());
     }
 
+    #[test]
+    fn test_get_var() {
+        assert_eq!(get_var("PATH").unwrap(), get_path().unwrap());
+        assert!(get_var("PATHAID_SURELY_UNSET").is_err())
+    }
+
     #[test]
     fn test_split() {
         let test = Test::new();
@@ -266,10 +2535,102 @@ mod tests {
     #[test]
     fn test_join() {
         let test = Test::new();
-        let joined = OsString::from(join(&test.paths).unwrap());
+        let joined = join(&test.paths).unwrap();
         assert_eq!(joined, test.path)
     }
 
+    #[cfg(unix)]
+    #[test]
+    fn test_not_unicode() {
+        use std::os::unix::ffi::OsStrExt;
+        let path_var = OsStr::from_bytes(b"/usr/bin:/opt/caf\xe9/bin");
+        let paths = split(path_var);
+        assert_eq!(paths[1].as_os_str().as_bytes(), b"/opt/caf\xe9/bin");
+        assert_eq!(join(&paths).unwrap(), path_var);
+        assert_eq!(
+            remove_paths(path_var, &[PathBuf::from("/usr/bin")]).unwrap(),
+            OsStr::from_bytes(b"/opt/caf\xe9/bin")
+        );
+        assert!(matches!(
+            into_string(path_var.to_os_string()),
+            Err(Error::NotUnicode(entry)) if entry == "/opt/caf\u{fffd}/bin"
+        ));
+    }
+
+    #[test]
+    fn test_split_quoted() {
+        let paths = split_quoted(r#"C:\bin;"C:\odd;dir";"C:\Program Files\x""#);
+        let expected: Vec<PathBuf> = [r"C:\bin", r"C:\odd;dir", r"C:\Program Files\x"]
+            .iter()
+            .map(PathBuf::from)
+            .collect();
+        assert_eq!(paths, expected)
+    }
+
+    #[test]
+    fn test_split_units() {
+        // An unpaired surrogate, as Windows allows in names, is kept as it is:
+        let units: Vec<u16> = r#"C:\a;"C:\b;c";x"#.encode_utf16().chain([0xd800]).collect();
+        let entries = split_units(units.into_iter(), u16::from(b'"'), u16::from(b';'));
+        assert_eq!(entries.len(), 3);
+        assert_eq!(String::from_utf16(&entries[1]).unwrap(), r"C:\b;c");
+        assert_eq!(entries[2], [u16::from(b'x'), 0xd800]);
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_split_not_unicode() {
+        use std::os::windows::ffi::{OsStrExt, OsStringExt};
+        let mut units: Vec<u16> = r"C:\bin;C:\caf".encode_utf16().collect();
+        units.push(0xd800);
+        let paths = split(OsString::from_wide(&units));
+        assert_eq!(paths.len(), 2);
+        assert_eq!(paths[1].as_os_str().encode_wide().last(), Some(0xd800));
+    }
+
+    #[test]
+    fn test_join_quoted() {
+        let path = r#"C:\bin;"C:\odd;dir";C:\tools"#;
+        assert_eq!(join_quoted(&split_quoted(path)).unwrap(), path);
+        assert!(join_quoted(&[PathBuf::from(r#"C:\bad"dir"#)]).is_err())
+    }
+
+    #[test]
+    fn test_resolve_all() {
+        let test = Test::new();
+        let paths = vec![test.exe_dir.clone(), PathBuf::from(&test.addition)];
+        assert_eq!(resolve_all(&paths, false).len(), 1);
+        assert_eq!(resolve_all(&paths, true)[1], paths[1])
+    }
+
+    #[test]
+    fn test_is_cwd_entry() {
+        assert!(is_cwd_entry(Path::new("")));
+        assert!(is_cwd_entry(Path::new(".")));
+        assert!(is_cwd_entry(Path::new("./")));
+        assert!(!is_cwd_entry(Path::new("./bin")));
+        assert!(ensure_not_cwd(".").is_err())
+    }
+
+    #[test]
+    fn test_absolute() {
+        let test = Test::new();
+        let cwd = env::current_dir().unwrap();
+        let abs = absolute("./build/bin").unwrap();
+        assert_eq!(abs, cwd.join("build").join("bin"));
+        assert_eq!(absolute(&test.addition).unwrap(), test.addition)
+    }
+
+    #[test]
+    fn test_strip_verbatim() {
+        let stripped = strip_verbatim(PathBuf::from(r"\\?\C:\tools"));
+        assert_eq!(stripped, PathBuf::from(r"C:\tools"));
+        let stripped = strip_verbatim(PathBuf::from(r"\\?\UNC\server\share"));
+        assert_eq!(stripped, PathBuf::from(r"\\server\share"));
+        let stripped = strip_verbatim(PathBuf::from(r"\\?\Volume{1234}\tools"));
+        assert_eq!(stripped, PathBuf::from(r"\\?\Volume{1234}\tools"))
+    }
+
     #[test]
     fn test_exists() {
         let test = Test::new();
@@ -277,12 +2638,173 @@ mod tests {
     }
 
     #[test]
-    fn test_count_files() {
+    fn test_count_executables() {
         let test = Test::new();
-        let count = count_files(&test.exe_dir).unwrap();
+        let count = count_executables(&test.exe_dir).unwrap();
         assert!(count > 0)
     }
 
+    #[cfg(unix)]
+    #[test]
+    fn test_is_executable() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = env::temp_dir().join(format!("pathaid-exec-{}", process::id()));
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        let script = dir.join("script");
+        fs::write(&script, "#!/bin/sh\n").unwrap();
+        fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+        fs::write(dir.join("data.txt"), "").unwrap();
+        assert!(is_executable(&script));
+        assert!(!is_executable(&dir.join("data.txt")));
+        // A directory can be searched but not run:
+        assert!(!is_executable(&dir.join("sub")));
+        assert_eq!(count_executables(&dir).unwrap(), 1);
+        fs::remove_dir_all(&dir).unwrap()
+    }
+
+    #[test]
+    fn test_strip_invisible() {
+        let pasted = PathBuf::from("\u{feff}/opt/my\u{a0}tool/bin\u{200b} ");
+        assert_eq!(
+            escape_invisible(&pasted),
+            "\\u{feff}/opt/my\\u{a0}tool/bin\\u{200b}\\u{20}"
+        );
+        assert_eq!(strip_invisible(&pasted), PathBuf::from("/opt/my tool/bin"));
+        let plain = PathBuf::from("/opt/my tool/bin");
+        assert_eq!(escape_invisible(&plain), "/opt/my tool/bin");
+        assert_eq!(strip_invisible(&plain), plain);
+        assert_eq!(
+            invisible_places(&pasted),
+            ["at the start", "in the middle", "at the end"]
+        );
+        assert_eq!(
+            invisible_places(Path::new("/opt/bin\u{200b}")),
+            ["at the end"]
+        );
+        assert!(invisible_places(&plain).is_empty())
+    }
+
+    #[test]
+    fn test_normalize() {
+        let cases = [
+            ("/usr/bin/", "/usr/bin"),
+            ("/usr//bin", "/usr/bin"),
+            ("/usr/./bin/.", "/usr/bin"),
+            ("./node_modules/.bin", "node_modules/.bin"),
+            ("/opt/tool/../bin", "/opt/tool/../bin"),
+            ("/", "/"),
+            (".", "."),
+            ("./", "."),
+            ("", ""),
+        ];
+        for (path, normalized) in cases {
+            assert_eq!(
+                normalize(Path::new(path)).as_os_str(),
+                normalized,
+                "{}",
+                path
+            );
+        }
+        let paths = ["/usr/bin/", "/usr//bin", "/usr/bin"].map(PathBuf::from);
+        assert_eq!(
+            find_positions(&paths, Path::new("/usr/./bin"), false),
+            [0, 1, 2]
+        );
+        assert_eq!(dedup_positions(&paths, Keep::First), [0])
+    }
+
+    #[test]
+    fn test_find_duplicates_normalized() {
+        let nfc = PathBuf::from("/nonexistent/caf\u{e9}/bin");
+        let nfd = PathBuf::from("/nonexistent/cafe\u{301}/bin");
+        assert_ne!(nfc, nfd);
+        assert_eq!(normalize_unicode(&nfd), nfc);
+        assert_eq!(unicode_form(&nfd), "NFD");
+        let paths = vec![nfc.clone(), nfd.clone(), nfc.clone()];
+        assert_eq!(find_duplicates_normalized(&paths), vec![(nfc, nfd)])
+    }
+
+    #[test]
+    fn test_probe() {
+        let test = Test::new();
+        assert!(probe(&test.exe_dir, Duration::from_secs(5)));
+        // Nothing answers in no time at all:
+        assert!(!probe(&test.exe_dir, Duration::ZERO))
+    }
+
+    #[test]
+    fn test_drive_of() {
+        assert_eq!(drive_of(Path::new(r"z:\tools")), Some(String::from("Z:")));
+        assert_eq!(drive_of(Path::new(r"\\?\C:\bin")), Some(String::from("C:")));
+        assert_eq!(drive_of(Path::new("/usr/bin")), None);
+        assert!(drive_available("Z:") || cfg!(windows))
+    }
+
+    #[test]
+    fn test_max_path_room() {
+        assert_eq!(max_path_room(Path::new(r"C:\bin")), MAX_PATH - 7);
+        let long = PathBuf::from("x".repeat(MAX_PATH));
+        assert_eq!(max_path_room(&long), 0);
+        assert!(exceeds_max_path(&long).is_empty())
+    }
+
+    #[test]
+    fn test_is_app_exec_alias() {
+        assert!(!is_app_exec_alias(&env::current_exe().unwrap()));
+        // FILE_ATTRIBUTE_REPARSE_POINT, with and without FILE_ATTRIBUTE_ARCHIVE:
+        assert!(is_alias_file(0x400, 0) && is_alias_file(0x420, 0));
+        assert!(!is_alias_file(0x400, 4096) && !is_alias_file(0x20, 0))
+    }
+
+    // Every .exe in WindowsApps is an alias, on systems that have any
+    #[cfg(windows)]
+    #[test]
+    fn test_is_app_exec_alias_windows_apps() {
+        let Some(local) = env::var_os("LOCALAPPDATA") else {
+            return;
+        };
+        let apps = PathBuf::from(local).join("Microsoft").join("WindowsApps");
+        let Ok(dir) = fs::read_dir(&apps) else {
+            return;
+        };
+        for exe in dir
+            .flatten()
+            .map(|e| e.path())
+            .filter(|p| p.extension().is_some_and(|e| e.eq_ignore_ascii_case("exe")))
+        {
+            assert!(is_app_exec_alias(&exe), "{} isn't an alias", exe.display())
+        }
+    }
+
+    #[test]
+    fn test_find_case_collisions() {
+        let test = Test::new();
+        assert!(find_case_collisions(&test.exe_dir).unwrap().is_empty());
+        let dir = env::temp_dir().join(format!("pathaid-case-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let names = if cfg!(windows) {
+            ["Foo.exe", "foo.exe", "bar.exe"]
+        } else {
+            ["Foo", "foo", "bar"]
+        };
+        for name in names {
+            fs::write(dir.join(name), "").unwrap();
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::PermissionsExt;
+                fs::set_permissions(dir.join(name), fs::Permissions::from_mode(0o755)).unwrap();
+            }
+        }
+        // Only where the file system keeps Foo and foo apart:
+        let distinct = fs::read_dir(&dir).unwrap().count() == names.len();
+        let collisions = find_case_collisions(&dir);
+        fs::remove_dir_all(&dir).unwrap();
+        if distinct {
+            assert_eq!(collisions.unwrap(), [[names[0], names[1]]])
+        }
+    }
+
     #[test]
     fn test_is_empty() {
         let test = Test::new();
@@ -290,6 +2812,36 @@ mod tests {
         assert!(!res)
     }
 
+    #[test]
+    fn test_is_read_only() {
+        let test = Test::new();
+        assert!(!is_read_only(&test.exe_dir).unwrap());
+        assert!(!is_immutable(&test.exe_dir).unwrap())
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_parse_mountinfo() {
+        let mountinfo = "23 28 0:22 / /proc rw,relatime - proc proc rw\n\
+            36 35 98:0 / /mnt/my\\040disk rw,noatime master:1 - ext4 /dev/sdb1 rw\n";
+        let mounts = parse_mountinfo(mountinfo);
+        assert_eq!(mounts[1].point, PathBuf::from("/mnt/my disk"));
+        assert_eq!(mounts[1].fs_type, "ext4")
+    }
+
+    #[test]
+    fn test_mount_of() {
+        let test = Test::new();
+        if cfg!(any(target_os = "linux", target_os = "macos")) {
+            let mount = mount_of(&test.exe_dir).unwrap();
+            assert!(test
+                .exe_dir
+                .canonicalize()
+                .unwrap()
+                .starts_with(mount.point))
+        }
+    }
+
     #[test]
     fn test_find_duplicates() {
         let test = Test::new();
@@ -297,12 +2849,96 @@ mod tests {
         assert_eq!(find_duplicates(&test.paths), dups)
     }
 
+    #[test]
+    fn test_find_duplicates_by_id() {
+        let test = Test::new();
+        let paths = vec![test.exe_dir.clone(), test.exe_dir.join(".")];
+        assert!(find_duplicates_by_id(&paths).is_empty());
+        if cfg!(unix) {
+            assert!(dir_id(&test.exe_dir).is_some())
+        }
+        let dir = env::temp_dir().join(format!("pathaid-id-{}", process::id()));
+        let (a, b) = (dir.join("a"), dir.join("b"));
+        fs::create_dir_all(&a).unwrap();
+        fs::create_dir_all(&b).unwrap();
+        // As if b were a bind mount of a:
+        let bound = duplicates_by_id(&[a.clone(), b.clone()], |_| Some((1, 1)));
+        #[cfg(unix)]
+        let linked = {
+            std::os::unix::fs::symlink(&a, dir.join("link")).unwrap();
+            find_duplicates_by_id(&[a.clone(), dir.join("link")])
+        };
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(bound, [(a, b)]);
+        // A link resolves to the same path, which find_duplicates_resolved reports:
+        #[cfg(unix)]
+        assert!(linked.is_empty())
+    }
+
     #[test]
     fn test_dedup() {
         let test = Test::new();
         assert_eq!(find_duplicates(&test.paths), test.dups)
     }
 
+    #[test]
+    fn test_fold() {
+        let bin = Path::new("/Users/me/Bin");
+        assert_eq!(fold(bin, true), PathBuf::from("/users/me/bin"));
+        assert_eq!(fold(bin, false), bin);
+        if !IGNORE_CASE.load(Ordering::Relaxed) {
+            let paths = ["/Users/me/Bin", "/users/me/bin"].map(PathBuf::from);
+            assert!(find_duplicates(&paths).is_empty());
+        }
+    }
+
+    #[test]
+    fn test_dedup_positions() {
+        let test = Test::new();
+        let paths = [
+            test.exe_dir.clone(),
+            PathBuf::from("/nonexistent/bin"),
+            test.exe_dir.join("."),
+            PathBuf::from("/nonexistent/bin"),
+        ];
+        assert_eq!(dedup_positions(&paths, Keep::First), vec![0, 1]);
+        assert_eq!(dedup_positions(&paths, Keep::Last), vec![2, 3]);
+        assert_eq!(dedup(&paths), paths[..2].to_vec())
+    }
+
+    #[test]
+    fn test_remove_paths() {
+        let test = Test::new();
+        let res = remove_paths(&test.path, &test.dups).unwrap();
+        assert_eq!(split(res), test.paths[1..4].to_vec())
+    }
+
+    #[cfg(feature = "cli")]
+    #[test]
+    fn test_remove_matching() {
+        let test = Test::new();
+        let res = remove_matching(&test.path, &[String::from("/usr/local/*")]).unwrap();
+        assert_eq!(split(res), test.paths[2..4].to_vec());
+        assert!(remove_matching(&test.path, &[String::from("[")]).is_err())
+    }
+
+    #[test]
+    fn test_prefix_dirs() {
+        let test = Test::new();
+        let prefix = test.exe_dir.parent().unwrap();
+        assert!(prefix_dirs(prefix, "PATH").is_empty());
+        assert_eq!(prefix_paths(prefix, "PATH")[0], prefix.join("bin"));
+        assert!(prefix_paths(prefix, "CDPATH").is_empty())
+    }
+
+    #[test]
+    fn test_addition_error_hint() {
+        let test = Test::new();
+        let err = validate_addition(&test.path, "/not/a/dir").unwrap_err();
+        assert!(matches!(err, Error::Missing(_)));
+        assert!(err.hint().unwrap().contains("--create"))
+    }
+
     #[test]
     fn test_ensure_unique_addition() {
         let test = Test::new();
@@ -319,7 +2955,16 @@ mod tests {
         let delim = if cfg!(windows) { ";" } else { ":" };
         let expected = format!("{}{}{}", test.path.to_str().unwrap(), delim, test.addition);
         let res = append_path(&test.path, &test.addition).unwrap();
-        assert_eq!(res, expected)
+        assert_eq!(res.to_str(), Some(expected.as_str()))
+    }
+
+    #[test]
+    fn test_append_path_empty() {
+        let test = Test::new();
+        assert_eq!(
+            append_path("", &test.addition).unwrap().to_str(),
+            Some(test.addition.as_str())
+        )
     }
 
     #[test]
@@ -328,6 +2973,195 @@ mod tests {
         let delim = if cfg!(windows) { ";" } else { ":" };
         let expected = format!("{}{}{}", test.addition, delim, test.path.to_str().unwrap());
         let res = prepend_path(&test.path, &test.addition).unwrap();
-        assert_eq!(res, expected)
+        assert_eq!(res.to_str(), Some(expected.as_str()))
+    }
+
+    #[test]
+    fn test_split_additions() {
+        let delim = if cfg!(windows) { ";" } else { ":" };
+        let args = [
+            format!("/a{}/b", delim),
+            String::from("/c"),
+            String::from("/a"),
+        ];
+        let expected: Vec<PathBuf> = ["/a", "/b", "/c"].iter().map(PathBuf::from).collect();
+        assert_eq!(split_additions(&args), expected);
+        assert_eq!(split_additions(&[""]), [PathBuf::new()])
+    }
+
+    #[test]
+    fn test_prepend_paths() {
+        let test = Test::new();
+        let delim = if cfg!(windows) { ";" } else { ":" };
+        let args = [format!("/a{}/b", delim), String::from("/c")];
+        let expected = format!("/a{d}/b{d}/c{d}{}", test.path.to_str().unwrap(), d = delim);
+        let res = prepend_paths(&test.path, &split_additions(&args)).unwrap();
+        assert_eq!(res.to_str(), Some(expected.as_str()))
+    }
+
+    #[test]
+    fn test_append_paths() {
+        let test = Test::new();
+        let delim = if cfg!(windows) { ";" } else { ":" };
+        let args = [String::from("/c"), format!("/a{}/b", delim)];
+        let expected = format!("{}{d}/c{d}/a{d}/b", test.path.to_str().unwrap(), d = delim);
+        let res = append_paths(&test.path, &split_additions(&args)).unwrap();
+        assert_eq!(res.to_str(), Some(expected.as_str()))
+    }
+
+    #[test]
+    fn test_find_drift() {
+        let delim = if cfg!(windows) { ";" } else { ":" };
+        let current = ["/session/bin", "/usr/bin/", "/bin"].join(delim);
+        let composed = ["/usr/bin", "/bin", "/new/bin"].join(delim);
+        let (added, missing) = find_drift(&current, &composed);
+        assert_eq!(added, vec![PathBuf::from("/session/bin")]);
+        assert_eq!(missing, vec![PathBuf::from("/new/bin")])
+    }
+
+    #[test]
+    fn test_find_positions() {
+        let test = Test::new();
+        let dup = Path::new("/usr/local/bin");
+        assert_eq!(find_positions(&test.paths, dup, false), vec![0, 4]);
+        assert!(find_positions(&test.paths, Path::new("/nope"), true).is_empty())
+    }
+
+    #[test]
+    fn test_find_executable() {
+        let test = Test::new();
+        let exe = env::current_exe().unwrap();
+        let name = exe.file_stem().unwrap().to_str().unwrap();
+        let found = find_executable(test.exe_dir.as_os_str(), name).unwrap();
+        assert_eq!(found.parent(), Some(test.exe_dir.as_path()));
+        assert!(find_executable(&test.path, "not-a-command-anywhere").is_none())
+    }
+
+    #[test]
+    fn test_entries() {
+        let paths = [env::temp_dir(), PathBuf::from("/nonexistent")];
+        let found = entries(&paths, false);
+        assert!(found[0].exists && found[0].resolved.is_some());
+        assert!(!found[1].exists && found[1].resolved.is_none());
+        assert_eq!(found[1].index, 1);
+        assert!(found.iter().all(|e| e.executables.is_none()))
+    }
+
+    #[test]
+    fn test_scan_executables() {
+        let test = Test::new();
+        let exe = env::current_exe().unwrap();
+        let name = command_name(&exe).unwrap();
+        let paths = [
+            test.exe_dir.clone(),
+            PathBuf::from("/nonexistent"),
+            test.exe_dir.clone(),
+        ];
+        let commands = scan_executables(&paths);
+        // The second copy of the same directory doesn't shadow anything:
+        assert_eq!(commands[&name], vec![(0, exe)])
+    }
+
+    #[test]
+    fn test_find_executables() {
+        let test = Test::new();
+        let exe = env::current_exe().unwrap();
+        let name = exe.file_stem().unwrap().to_str().unwrap();
+        let path_var = join(&[
+            test.exe_dir.clone(),
+            PathBuf::from("/nonexistent"),
+            test.exe_dir.clone(),
+        ])
+        .unwrap();
+        let found: Vec<usize> = find_executables(&path_var, name)
+            .iter()
+            .map(|(i, _)| *i)
+            .collect();
+        assert_eq!(found, vec![0, 2]);
+        let dir = test.exe_dir.file_name().unwrap().to_str().unwrap();
+        let parent = join(&[test.exe_dir.parent().unwrap().to_path_buf()]).unwrap();
+        assert!(find_executables(&parent, &format!("{}/{}", dir, name)).is_empty());
+        assert!(find_executables(&path_var, "").is_empty())
+    }
+
+    #[test]
+    fn test_find_matches() {
+        let test = Test::new();
+        let exe_dir = test.exe_dir.join(".");
+        let paths = vec![
+            test.exe_dir.clone(),
+            PathBuf::from("/nope"),
+            exe_dir.clone(),
+        ];
+        assert_eq!(find_matches(&paths, &exe_dir), vec![0, 2]);
+        assert_eq!(find_matches(&paths, Path::new("/nope")), vec![1]);
+        assert!(find_matches(&paths, Path::new("/other")).is_empty())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_entry_meta() {
+        use std::os::unix::fs::{symlink, PermissionsExt};
+
+        let dir = env::temp_dir().join(format!("pathaid-meta-{}", process::id()));
+        fs::create_dir_all(dir.join("bin")).unwrap();
+        fs::set_permissions(dir.join("bin"), fs::Permissions::from_mode(0o1777)).unwrap();
+        fs::write(dir.join("bin/tool"), "").unwrap();
+        fs::set_permissions(dir.join("bin/tool"), fs::Permissions::from_mode(0o755)).unwrap();
+        symlink(dir.join("bin"), dir.join("link")).unwrap();
+        let bin = entry_meta(&dir.join("bin"), true);
+        let link = entry_meta(&dir.join("link"), false);
+        let missing = entry_meta(&dir.join("missing"), true);
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(bin.dir && !bin.symlink);
+        assert_eq!((bin.mode, bin.executables), (Some(0o1777), Some(1)));
+        assert!(bin.owner.is_some() && bin.modified.is_some());
+        assert!(link.symlink && link.dir && link.executables.is_none());
+        assert_eq!(missing, EntryMeta::default())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_expand() {
+        let home = env::var("HOME").unwrap();
+        let cases = [
+            ("~/bin", format!("{}/bin", home)),
+            ("~", home.clone()),
+            ("$HOME/go/bin", format!("{}/go/bin", home)),
+            ("${HOME}/.local/bin", format!("{}/.local/bin", home)),
+            ("/opt/~other/bin", String::from("/opt/~other/bin")),
+            ("~other/bin", String::from("~other/bin")),
+            (
+                "$PATHAID_UNSET_VARIABLE/bin",
+                String::from("$PATHAID_UNSET_VARIABLE/bin"),
+            ),
+            ("/opt/a$/bin", String::from("/opt/a$/bin")),
+            ("${HOME/bin", String::from("${HOME/bin")),
+        ];
+        for (path, expanded) in cases {
+            assert_eq!(expand(Path::new(path)), PathBuf::from(expanded), "{}", path);
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_resolve_in() {
+        use std::os::unix::fs::symlink;
+
+        let root = env::temp_dir().join(format!("pathaid-root-{}", process::id()));
+        fs::create_dir_all(root.join("usr/bin")).unwrap();
+        // Absolute and relative links, as an image has them:
+        symlink("/usr/bin", root.join("bin")).unwrap();
+        symlink("../usr/bin", root.join("usr/sbin")).unwrap();
+        symlink("/loop", root.join("loop")).unwrap();
+        let bin = resolve_in(&root, Path::new("/bin"));
+        let sbin = resolve_in(&root, Path::new("/usr/sbin/../bin"));
+        let missing = resolve_in(&root, Path::new("/bin/missing"));
+        let looping = resolve_in(&root, Path::new("/loop"));
+        fs::remove_dir_all(&root).unwrap();
+        assert_eq!(bin.unwrap(), PathBuf::from("/usr/bin"));
+        assert_eq!(sbin.unwrap(), PathBuf::from("/usr/bin"));
+        assert!(missing.is_err());
+        assert!(looping.is_err())
     }
 }
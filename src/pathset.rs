@@ -0,0 +1,262 @@
+/*
+Summary of pathset functions
+
+The entries of a path-like variable as one value, for tools that want to check
+or change PATH without running the pathaid binary. Built on pathops, which has
+the same operations for path strings.
+
+# read PATH, or another variable, from the environment
+PathSet::from_env() -> Result<PathSet>
+PathSet::from_var(name: str) -> Result<PathSet>
+
+# split a path string, an empty string has no entries
+PathSet::parse(OsStr) -> PathSet
+
+# look at the entries under another root, without case or expanded, whatever the process does
+PathSet::under_root(Path) -> Result<PathSet>
+PathSet::ignore_case(bool) -> PathSet
+PathSet::expand(bool) -> PathSet
+
+# find the problems validate reports without looking at packages
+PathSet::validate() -> Result<Vec<Issue>>
+
+# keep the first of entries that are the same, as written or resolved
+PathSet::dedup()
+
+# add a directory at the end or front, refusing what pathaid append would refuse
+PathSet::append(Path) -> Result<()>
+PathSet::prepend(Path) -> Result<()>
+
+# remove every entry that is, or resolves to, a directory
+PathSet::remove(Path) -> usize
+
+# join the entries into a path string again
+PathSet::join() -> Result<OsString>
+*/
+
+use crate::pathops::{self, Issue, Result};
+use std::ffi::{OsStr, OsString};
+use std::path::{Path, PathBuf};
+
+/// The entries of PATH, or another path-like variable, in lookup order
+///
+/// ```
+/// use pathaid::PathSet;
+///
+/// let mut path: PathSet = ["/opt/a/bin", "/opt/b/bin", "/opt/a/bin"].into_iter().collect();
+/// path.dedup();
+/// assert_eq!(path.len(), 2);
+/// assert!(path.append("/opt/c/bin").is_err(), "it doesn't exist");
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PathSet {
+    entries: Vec<PathBuf>,
+    // The variable they are from, if not PATH
+    var: Option<String>,
+    // Where they differ from the settings for the process
+    options: pathops::Options,
+}
+
+impl PathSet {
+    /// PATH of this process
+    pub fn from_env() -> Result<Self> {
+        Ok(Self::parse(pathops::get_path()?))
+    }
+
+    /// Any path-like variable of this process, like MANPATH or PYTHONPATH
+    pub fn from_var(name: &str) -> Result<Self> {
+        Ok(Self {
+            var: Some(name.to_string()),
+            ..Self::parse(pathops::get_var(name)?)
+        })
+    }
+
+    /// Split on ':' (';' on Windows), an empty string has no entries rather than
+    /// one meaning the current directory
+    pub fn parse(path_var: impl AsRef<OsStr>) -> Self {
+        Self {
+            entries: pathops::split_entries(path_var),
+            var: None,
+            options: pathops::Options::default(),
+        }
+    }
+
+    /// Look at the entries under root rather than /, like the PATH of a mounted
+    /// image or container, following links as they would be inside it
+    pub fn under_root(mut self, root: impl AsRef<Path>) -> Result<Self> {
+        self.options.root = Some(pathops::root_dir(root.as_ref())?);
+        Ok(self)
+    }
+
+    /// Compare entries regardless of case, or with it, rather than as the platform
+    /// does
+    pub fn ignore_case(mut self, ignore: bool) -> Self {
+        self.options.ignore_case = Some(ignore);
+        self
+    }
+
+    /// Look at entries with ~ and $NAME expanded, as copied from an rc file
+    pub fn expand(mut self, expand: bool) -> Self {
+        self.options.expand = Some(expand);
+        self
+    }
+
+    // Call pathops with the options of this set in place
+    fn scoped<T>(&self, f: impl FnOnce() -> T) -> T {
+        pathops::with_options(&self.options, f)
+    }
+
+    pub fn entries(&self) -> &[PathBuf] {
+        &self.entries
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Check if dir is an entry, as written or resolved
+    pub fn contains(&self, dir: impl AsRef<Path>) -> bool {
+        self.scoped(|| !pathops::find_matches(&self.entries, dir.as_ref()).is_empty())
+    }
+
+    /// The problems pathaid validate reports, except for those about installed
+    /// packages, with plain messages
+    pub fn validate(&self) -> Result<Vec<Issue>> {
+        let var = self.var.as_deref().unwrap_or("PATH");
+        self.scoped(|| pathops::validate(&self.entries, var, false, |p, _| p.display().to_string()))
+    }
+
+    /// Keep the first of entries that are the same as written or when resolved
+    pub fn dedup(&mut self) {
+        self.entries = self.scoped(|| pathops::dedup(&self.entries));
+    }
+
+    // Refused like pathaid append and prepend do without options
+    fn check_addition(&self, dir: &Path) -> Result<()> {
+        pathops::ensure_not_cwd(dir)?;
+        let path_var = self.join()?;
+        self.scoped(|| pathops::validate_addition(path_var, dir))
+    }
+
+    /// Add an existing directory at the end, unless it is there already
+    pub fn append(&mut self, dir: impl AsRef<Path>) -> Result<()> {
+        self.check_addition(dir.as_ref())?;
+        self.entries.push(dir.as_ref().to_path_buf());
+        Ok(())
+    }
+
+    /// Add an existing directory at the front, unless it is there already
+    pub fn prepend(&mut self, dir: impl AsRef<Path>) -> Result<()> {
+        self.check_addition(dir.as_ref())?;
+        self.entries.insert(0, dir.as_ref().to_path_buf());
+        Ok(())
+    }
+
+    /// Remove every entry that is dir or resolves to the same directory, telling
+    /// how many were removed
+    pub fn remove(&mut self, dir: impl AsRef<Path>) -> usize {
+        let matches = self.scoped(|| pathops::find_matches(&self.entries, dir.as_ref()));
+        let mut i = 0;
+        self.entries.retain(|_| {
+            i += 1;
+            !matches.contains(&(i - 1))
+        });
+        matches.len()
+    }
+
+    /// Join with ':' (';' on Windows) as a value for PATH
+    pub fn join(&self) -> Result<OsString> {
+        pathops::join(&self.entries)
+    }
+}
+
+impl<P: Into<PathBuf>> FromIterator<P> for PathSet {
+    fn from_iter<I: IntoIterator<Item = P>>(iter: I) -> Self {
+        Self {
+            entries: iter.into_iter().map(Into::into).collect(),
+            var: None,
+            options: pathops::Options::default(),
+        }
+    }
+}
+
+impl<'a> IntoIterator for &'a PathSet {
+    type Item = &'a PathBuf;
+    type IntoIter = std::slice::Iter<'a, PathBuf>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.entries.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_path_set() {
+        let exe_dir = std::env::current_exe()
+            .unwrap()
+            .parent()
+            .unwrap()
+            .to_path_buf();
+        let missing = PathBuf::from("/nonexistent/bin");
+        let mut path: PathSet = [missing.clone(), exe_dir.clone(), missing.clone()]
+            .into_iter()
+            .collect();
+        let codes: Vec<&str> = path.validate().unwrap().iter().map(|i| i.code).collect();
+        // The missing entries are also the same when resolved, as validate tells:
+        assert_eq!(
+            codes,
+            vec!["missing", "missing", "duplicate", "duplicate-resolved"]
+        );
+        path.dedup();
+        assert_eq!(path.entries(), [missing.clone(), exe_dir.clone()]);
+        assert!(matches!(
+            path.append(&exe_dir),
+            Err(pathops::Error::Present(_))
+        ));
+        assert!(matches!(
+            path.prepend("/nonexistent/other"),
+            Err(pathops::Error::Missing(_))
+        ));
+        assert_eq!(path.remove(&missing), 1);
+        assert_eq!(PathSet::parse(path.join().unwrap()), path);
+        assert!(PathSet::parse("").is_empty())
+    }
+
+    #[test]
+    fn test_path_set_options() {
+        let root = std::env::temp_dir().join(format!("pathaid-set-{}", std::process::id()));
+        std::fs::create_dir_all(root.join("opt/tool/bin")).unwrap();
+        let path = PathSet::parse("/opt/tool/bin");
+        let under_root = path
+            .clone()
+            .under_root(&root)
+            .map(|p| p.validate().unwrap());
+        std::fs::remove_dir_all(&root).unwrap();
+        // Only there, and the process still looks at /:
+        assert!(under_root.unwrap().iter().all(|i| i.code == "empty"));
+        assert_eq!(path.validate().unwrap()[0].code, "missing");
+        let cased: PathSet = ["/opt/Tool/bin", "/opt/tool/bin"].into_iter().collect();
+        let mut ignored = cased.clone().ignore_case(true);
+        ignored.dedup();
+        assert_eq!(ignored.len(), 1);
+        let mut kept = cased.ignore_case(false);
+        kept.dedup();
+        assert_eq!(kept.len(), 2);
+        let home: PathSet = ["~"].into_iter().collect();
+        if std::env::var_os("HOME").is_some_and(|h| Path::new(&h).is_dir()) {
+            let codes = |set: PathSet| -> Vec<&str> {
+                set.validate().unwrap().iter().map(|i| i.code).collect()
+            };
+            assert!(codes(home.clone()).contains(&"unexpanded"));
+            let expanded = codes(home.expand(true));
+            assert!(!expanded.contains(&"unexpanded") && !expanded.contains(&"missing"))
+        }
+    }
+}
@@ -0,0 +1,391 @@
+/*
+Summary of query functions
+
+A small expression language for picking out entries, such as
+`status == missing && origin != system` or `fs == nfs || executables > 100`.
+Comparisons can be combined with && and ||, negated with ! and grouped with
+parentheses. A field on its own is true if it is set, like `duplicate`.
+
+# parse an expression
+parse(str) -> Result<Expr>
+
+# describe every entry of a path string with the fields a query can use
+entries(path_var: OsStr, system: Option<Vec<PathBuf>>) -> Vec<Entry>
+
+# check if an entry matches an expression
+Expr::matches(Entry) -> Result<bool>
+
+# check if an expression uses a field
+Expr::uses(field: str) -> bool
+*/
+
+use crate::envmodules::{self, Manager};
+use crate::pathops;
+use anyhow::{bail, ensure, Context, Result};
+use serde::Serialize;
+use std::ffi::OsStr;
+use std::path::PathBuf;
+
+// The fields of an entry, in the order they are listed in errors
+const FIELDS: [&str; 8] = [
+    "index",
+    "path",
+    "resolved",
+    "status",
+    "origin",
+    "fs",
+    "duplicate",
+    "executables",
+];
+
+// Everything a query can ask about an entry
+#[derive(Serialize, Debug, Default)]
+pub struct Entry {
+    pub index: usize,
+    pub path: PathBuf,
+    pub resolved: Option<PathBuf>,
+    // cwd, missing, empty or ok
+    pub status: String,
+    // module, stow, system or user, as far as it could be told
+    pub origin: String,
+    pub fs: Option<String>,
+    // If the same directory comes earlier, as written or resolved
+    pub duplicate: bool,
+    pub executables: usize,
+}
+
+enum Value {
+    Text(String),
+    Number(f64),
+    Bool(bool),
+}
+
+impl Entry {
+    fn field(&self, name: &str) -> Result<Value> {
+        let text = |s: Option<&str>| Value::Text(s.unwrap_or_default().to_string());
+        Ok(match name {
+            "index" => Value::Number(self.index as f64),
+            "path" => text(Some(&self.path.to_string_lossy())),
+            "resolved" => text(
+                self.resolved
+                    .as_ref()
+                    .map(|r| r.to_string_lossy())
+                    .as_deref(),
+            ),
+            "status" => text(Some(&self.status)),
+            "origin" => text(Some(&self.origin)),
+            "fs" => text(self.fs.as_deref()),
+            "duplicate" => Value::Bool(self.duplicate),
+            "executables" => Value::Number(self.executables as f64),
+            _ => bail!(
+                "there is no field '{}', the fields are {}",
+                name,
+                FIELDS.join(", ")
+            ),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    // Glob match, like the patterns of env --without
+    Like,
+}
+
+#[derive(Debug)]
+pub enum Expr {
+    Compare(String, Op, String),
+    Field(String),
+    Not(Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Word(String),
+    Op(Op),
+    And,
+    Or,
+    Not,
+    Open,
+    Close,
+}
+
+fn tokenize(s: &str) -> Result<Vec<Token>> {
+    let mut tokens: Vec<Token> = Vec::new();
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        let mut next_is = |n: char| chars.next_if_eq(&n).is_some();
+        let token = match c {
+            c if c.is_whitespace() => continue,
+            '(' => Token::Open,
+            ')' => Token::Close,
+            '&' if next_is('&') => Token::And,
+            '|' if next_is('|') => Token::Or,
+            '=' if next_is('=') => Token::Op(Op::Eq),
+            '!' if next_is('=') => Token::Op(Op::Ne),
+            '!' => Token::Not,
+            '<' if next_is('=') => Token::Op(Op::Le),
+            '<' => Token::Op(Op::Lt),
+            '>' if next_is('=') => Token::Op(Op::Ge),
+            '>' => Token::Op(Op::Gt),
+            '~' => Token::Op(Op::Like),
+            '"' | '\'' => {
+                let mut word = String::new();
+                loop {
+                    match chars.next() {
+                        Some(q) if q == c => break,
+                        Some(ch) => word.push(ch),
+                        None => bail!("missing closing {} in '{}'", c, s),
+                    }
+                }
+                Token::Word(word)
+            }
+            _ => {
+                let mut word = String::from(c);
+                while let Some(ch) =
+                    chars.next_if(|ch| !ch.is_whitespace() && !"()&|=!<>~\"'".contains(*ch))
+                {
+                    word.push(ch);
+                }
+                ensure!(
+                    !"&|=".contains(c),
+                    "unexpected '{}' in '{}', use &&, || or ==",
+                    c,
+                    s
+                );
+                Token::Word(word)
+            }
+        };
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn next(&mut self) -> Option<&Token> {
+        self.pos += 1;
+        self.tokens.get(self.pos - 1)
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn or(&mut self) -> Result<Expr> {
+        let mut expr = self.and()?;
+        while self.peek() == Some(&Token::Or) {
+            self.pos += 1;
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr> {
+        let mut expr = self.unary()?;
+        while self.peek() == Some(&Token::And) {
+            self.pos += 1;
+            expr = Expr::And(Box::new(expr), Box::new(self.unary()?));
+        }
+        Ok(expr)
+    }
+
+    fn unary(&mut self) -> Result<Expr> {
+        match self.next() {
+            Some(Token::Not) => Ok(Expr::Not(Box::new(self.unary()?))),
+            Some(Token::Open) => {
+                let expr = self.or()?;
+                ensure!(self.next() == Some(&Token::Close), "missing closing )");
+                Ok(expr)
+            }
+            Some(Token::Word(field)) => {
+                let field = field.clone();
+                ensure!(
+                    FIELDS.contains(&field.as_str()),
+                    "there is no field '{}', the fields are {}",
+                    field,
+                    FIELDS.join(", ")
+                );
+                let Some(&Token::Op(op)) = self.peek() else {
+                    return Ok(Expr::Field(field));
+                };
+                self.pos += 1;
+                match self.next() {
+                    Some(Token::Word(value)) => Ok(Expr::Compare(field, op, value.clone())),
+                    _ => bail!("expected a value to compare {} with", field),
+                }
+            }
+            Some(token) => bail!("unexpected {:?}", token),
+            None => bail!("the expression ends too early"),
+        }
+    }
+}
+
+pub fn parse(s: &str) -> Result<Expr> {
+    let mut parser = Parser {
+        tokens: tokenize(s)?,
+        pos: 0,
+    };
+    let expr = parser
+        .or()
+        .with_context(|| format!("unable to parse '{}'", s))?;
+    if let Some(token) = parser.peek() {
+        bail!("unable to parse '{}': unexpected {:?}", s, token);
+    }
+    Ok(expr)
+}
+
+fn compare(value: &Value, op: Op, other: &str) -> Result<bool> {
+    let ordering = match value {
+        Value::Number(n) => {
+            let other: f64 = other
+                .parse()
+                .with_context(|| format!("'{}' is not a number", other))?;
+            n.partial_cmp(&other)
+        }
+        Value::Bool(b) => {
+            let other: bool = other
+                .parse()
+                .with_context(|| format!("'{}' is neither true nor false", other))?;
+            Some(b.cmp(&other))
+        }
+        Value::Text(t) if op == Op::Like => {
+            let pattern = glob::Pattern::new(other)
+                .with_context(|| format!("'{}' is not a valid pattern", other))?;
+            return Ok(pattern.matches(t));
+        }
+        Value::Text(t) => Some(t.as_str().cmp(other)),
+    };
+    let Some(ordering) = ordering else {
+        return Ok(false);
+    };
+    Ok(match op {
+        Op::Eq => ordering.is_eq(),
+        Op::Ne => ordering.is_ne(),
+        Op::Lt => ordering.is_lt(),
+        Op::Le => ordering.is_le(),
+        Op::Gt => ordering.is_gt(),
+        Op::Ge => ordering.is_ge(),
+        Op::Like => bail!("~ only matches text against a pattern"),
+    })
+}
+
+impl Expr {
+    pub fn matches(&self, entry: &Entry) -> Result<bool> {
+        Ok(match self {
+            Expr::Compare(field, op, value) => compare(&entry.field(field)?, *op, value)?,
+            Expr::Field(field) => match entry.field(field)? {
+                Value::Bool(b) => b,
+                Value::Number(n) => n != 0.0,
+                Value::Text(t) => !t.is_empty(),
+            },
+            Expr::Not(expr) => !expr.matches(entry)?,
+            Expr::And(a, b) => a.matches(entry)? && b.matches(entry)?,
+            Expr::Or(a, b) => a.matches(entry)? || b.matches(entry)?,
+        })
+    }
+
+    pub fn uses(&self, name: &str) -> bool {
+        match self {
+            Expr::Compare(field, _, _) | Expr::Field(field) => field == name,
+            Expr::Not(expr) => expr.uses(name),
+            Expr::And(a, b) | Expr::Or(a, b) => a.uses(name) || b.uses(name),
+        }
+    }
+}
+
+// Describe each entry, telling system entries apart only if those are given as
+// finding them takes a login shell
+pub fn entries(path_var: &OsStr, system: Option<&[PathBuf]>) -> Vec<Entry> {
+    let loaded = envmodules::loaded_modules();
+    let paths = pathops::split(path_var);
+    let mut seen: Vec<PathBuf> = Vec::new();
+    let mut entries: Vec<Entry> = Vec::new();
+    for (index, p) in paths.iter().enumerate() {
+        let resolved = pathops::resolve(p).ok();
+        let status = if pathops::is_cwd_entry(p) {
+            "cwd"
+        } else if !pathops::exists(p) {
+            "missing"
+        } else if pathops::is_empty(p).unwrap_or(false) {
+            "empty"
+        } else {
+            "ok"
+        };
+        let origin = match envmodules::manager(p, &loaded) {
+            Some(Manager::Module(_)) => "module",
+            Some(Manager::Stow(_)) => "stow",
+            None => match system {
+                Some(system) if system.contains(p) => "system",
+                Some(_) => "user",
+                None => "",
+            },
+        };
+        let key = resolved.clone().unwrap_or_else(|| p.clone());
+        let duplicate = seen.contains(p) || seen.contains(&key);
+        seen.push(p.clone());
+        seen.push(key);
+        entries.push(Entry {
+            index,
+            path: p.clone(),
+            resolved,
+            status: status.to_string(),
+            origin: origin.to_string(),
+            fs: pathops::mount_of(p).ok().map(|m| m.fs_type),
+            duplicate,
+            executables: pathops::count_executables(p).unwrap_or(0),
+        });
+    }
+    entries
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry() -> Entry {
+        Entry {
+            index: 3,
+            path: PathBuf::from("/opt/tool/bin"),
+            status: String::from("missing"),
+            origin: String::from("user"),
+            ..Default::default()
+        }
+    }
+
+    fn matches(s: &str) -> bool {
+        parse(s).unwrap().matches(&entry()).unwrap()
+    }
+
+    #[test]
+    fn test_query_compare() {
+        assert!(matches("status == missing && origin != system"));
+        assert!(matches("index >= 3 && index < 4"));
+        assert!(matches("path ~ '/opt/*'"));
+        assert!(!matches("duplicate"));
+        assert!(matches(
+            "!duplicate && (fs == nfs || status == \"missing\")"
+        ))
+    }
+
+    #[test]
+    fn test_query_errors() {
+        assert!(parse("status ==").is_err());
+        assert!(parse("color == red").is_err());
+        assert!(parse("(status == ok").is_err());
+        assert!(parse("status = ok").is_err());
+        assert!(parse("index > many").unwrap().matches(&entry()).is_err())
+    }
+}
@@ -0,0 +1,235 @@
+/*
+Summary of rcfile functions
+
+A changed PATH can be kept in the startup file of the user's shell, in a block
+between marker lines that is replaced on every write and left alone otherwise,
+or printed as a statement setting it to eval in the current shell.
+
+# find the startup file of a shell, ~/.profile for any shell not known
+rc_file(Option<Shell>, home: Path) -> PathBuf
+
+# find the profile of PowerShell
+powershell_profile(home: Path) -> PathBuf
+
+# set a path-like variable as the shell does, with the home directory as $HOME
+path_line(Option<Shell>, var: str, path: str, home: Path) -> String
+
+# replace the managed block in the contents of a file, or add it at the end
+update_block(contents: str, line: str) -> String
+
+# tell how a shell sets variables from its name, like bash or nu
+syntax(name: str) -> Option<Syntax>
+
+# set a variable to a value in the syntax of a shell, quoted to eval as is
+set_line(Syntax, var: str, value: str) -> String
+*/
+
+use crate::pathops;
+use clap_complete::Shell;
+use std::env;
+use std::path::{Path, PathBuf};
+
+const BEGIN: &str = "# >>> pathaid >>>";
+const END: &str = "# <<< pathaid <<<";
+
+// The shell names syntax knows
+pub const SHELL_NAMES: [&str; 9] = [
+    "bash",
+    "zsh",
+    "sh",
+    "fish",
+    "csh",
+    "tcsh",
+    "powershell",
+    "pwsh",
+    "nu",
+];
+
+// Shells grouped by how they set a variable
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Syntax {
+    // bash, zsh and sh
+    Posix,
+    Fish,
+    Csh,
+    PowerShell,
+    Nu,
+}
+
+pub fn rc_file(shell: Option<Shell>, home: &Path) -> PathBuf {
+    let dir = |var, default: &str| match env::var_os(var).filter(|d| !d.is_empty()) {
+        Some(dir) => PathBuf::from(dir),
+        None => home.join(default),
+    };
+    match shell {
+        Some(Shell::Bash) => home.join(".bashrc"),
+        Some(Shell::Zsh) => dir("ZDOTDIR", "").join(".zshrc"),
+        Some(Shell::Fish) => dir("XDG_CONFIG_HOME", ".config").join("fish/config.fish"),
+        Some(Shell::PowerShell) => powershell_profile(home),
+        _ => home.join(".profile"),
+    }
+}
+
+pub fn powershell_profile(home: &Path) -> PathBuf {
+    let dir = if cfg!(windows) {
+        home.join("Documents").join("PowerShell")
+    } else {
+        home.join(".config/powershell")
+    };
+    dir.join("Microsoft.PowerShell_profile.ps1")
+}
+
+// Entries under the home directory are written relative to $HOME, so the block
+// still works if the home directory moves. Everything else is quoted to be taken
+// literally, so an entry can't run anything when the file is read.
+pub fn path_line(shell: Option<Shell>, var: &str, path: &str, home: &Path) -> String {
+    let syntax = match shell {
+        Some(Shell::Fish) => Syntax::Fish,
+        // A profile of its own on each machine, so written as it is
+        Some(Shell::PowerShell) => return set_line(Syntax::PowerShell, var, path),
+        _ => Syntax::Posix,
+    };
+    let words: Vec<String> = pathops::split_entries(path)
+        .iter()
+        .map(|entry| match entry.strip_prefix(home) {
+            Ok(rest) if rest.as_os_str().is_empty() => String::from("$HOME"),
+            Ok(rest) => format!(
+                "$HOME{}",
+                quoted(syntax, &format!("/{}", rest.to_string_lossy()))
+            ),
+            Err(_) => quoted(syntax, &entry.to_string_lossy()),
+        })
+        .collect();
+    match syntax {
+        // PATH is a list in fish, one word per entry
+        Syntax::Fish => format!("set -gx {} {}", var, words.join(" "))
+            .trim_end()
+            .to_string(),
+        _ => format!("export {}={}", var, words.join(":")),
+    }
+}
+
+pub fn update_block(contents: &str, line: &str) -> String {
+    let block = format!("{}\n{}\n{}\n", BEGIN, line, END);
+    if let (Some(start), Some(end)) = (contents.find(BEGIN), contents.find(END)) {
+        if start < end {
+            let after = contents[end + END.len()..]
+                .strip_prefix('\n')
+                .unwrap_or(&contents[end + END.len()..]);
+            return format!("{}{}{}", &contents[..start], block, after);
+        }
+    }
+    let mut updated = contents.to_string();
+    if !updated.is_empty() && !updated.ends_with('\n') {
+        updated.push('\n');
+    }
+    updated.push_str(&block);
+    updated
+}
+
+pub fn syntax(name: &str) -> Option<Syntax> {
+    Some(match name {
+        "bash" | "zsh" | "sh" => Syntax::Posix,
+        "fish" => Syntax::Fish,
+        "csh" | "tcsh" => Syntax::Csh,
+        "powershell" | "pwsh" => Syntax::PowerShell,
+        "nu" => Syntax::Nu,
+        _ => return None,
+    })
+}
+
+// Wrap in single quotes, which only need the quote itself escaped in most shells
+fn single_quoted(s: &str, escaped_quote: &str) -> String {
+    format!("'{}'", s.replace('\'', escaped_quote))
+}
+
+fn double_quoted(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+// Quote a value so the shell takes it literally
+fn quoted(syntax: Syntax, s: &str) -> String {
+    match syntax {
+        Syntax::Posix => single_quoted(s, "'\\''"),
+        Syntax::Fish => single_quoted(&s.replace('\\', "\\\\"), "\\'"),
+        // History expansion happens even inside single quotes in csh
+        Syntax::Csh => single_quoted(&s.replace('!', "\\!"), "'\\''"),
+        Syntax::PowerShell => single_quoted(s, "''"),
+        Syntax::Nu => double_quoted(s),
+    }
+}
+
+pub fn set_line(syntax: Syntax, var: &str, value: &str) -> String {
+    let words = || -> Vec<String> {
+        pathops::split_entries(value)
+            .iter()
+            .map(|e| quoted(syntax, &e.to_string_lossy()))
+            .collect()
+    };
+    match syntax {
+        Syntax::Posix => format!("export {}={}", var, quoted(syntax, value)),
+        // Variables ending in PATH are lists in fish, split on ':' when exported
+        Syntax::Fish if var.ends_with("PATH") => format!("set -gx {} {}", var, words().join(" "))
+            .trim_end()
+            .to_string(),
+        Syntax::Fish => format!("set -gx {} {}", var, quoted(syntax, value)),
+        Syntax::Csh => format!("setenv {} {}", var, quoted(syntax, value)),
+        Syntax::PowerShell => format!("$env:{} = {}", var, quoted(syntax, value)),
+        // PATH is a list in nu, converted back to a string for other processes
+        Syntax::Nu if var == "PATH" || var == "Path" => {
+            format!("$env.{} = [{}]", var, words().join(", "))
+        }
+        Syntax::Nu => format!("$env.{} = {}", var, quoted(syntax, value)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_path_line() {
+        let home = Path::new("/home/u");
+        let path = "/home/u/bin:/usr/bin:/home/user/bin";
+        assert_eq!(
+            path_line(Some(Shell::Bash), "PATH", path, home),
+            "export PATH=$HOME'/bin':'/usr/bin':'/home/user/bin'"
+        );
+        assert_eq!(
+            path_line(Some(Shell::Fish), "MANPATH", "/home/u/man:/usr/man", home),
+            "set -gx MANPATH $HOME'/man' '/usr/man'"
+        );
+        // Nothing in an entry is expanded or run when the file is read
+        assert_eq!(
+            path_line(Some(Shell::Zsh), "PATH", "/opt/x$(cmd):/home/u/it's", home),
+            "export PATH='/opt/x$(cmd)':$HOME'/it'\\''s'"
+        )
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_set_line() {
+        let path = "/usr/bin:/opt/it's/bin";
+        let line = |name| set_line(syntax(name).unwrap(), "PATH", path);
+        assert_eq!(line("bash"), r"export PATH='/usr/bin:/opt/it'\''s/bin'");
+        assert_eq!(line("fish"), r"set -gx PATH '/usr/bin' '/opt/it\'s/bin'");
+        assert_eq!(line("csh"), r"setenv PATH '/usr/bin:/opt/it'\''s/bin'");
+        assert_eq!(line("pwsh"), "$env:PATH = '/usr/bin:/opt/it''s/bin'");
+        assert_eq!(line("nu"), r#"$env.PATH = ["/usr/bin", "/opt/it's/bin"]"#);
+        assert_eq!(set_line(Syntax::Fish, "PATH", ""), "set -gx PATH")
+    }
+
+    #[test]
+    fn test_update_block() {
+        let added = update_block("alias ll='ls -l'", "export PATH=\"/a\"");
+        assert_eq!(
+            added,
+            "alias ll='ls -l'\n# >>> pathaid >>>\nexport PATH=\"/a\"\n# <<< pathaid <<<\n"
+        );
+        let replaced = update_block(&format!("{}umask 022\n", added), "export PATH=\"/b\"");
+        assert_eq!(
+            replaced,
+            "alias ll='ls -l'\n# >>> pathaid >>>\nexport PATH=\"/b\"\n# <<< pathaid <<<\numask 022\n"
+        )
+    }
+}
@@ -0,0 +1,438 @@
+/*
+Summary of rootfs functions
+
+Compose the PATH a login on a system image would get, without booting it or
+running anything in it, by reading the files login, PAM and the login shell read
+there. Only assignments with a value known from the files themselves are applied,
+in this order:
+
+  /etc/login.defs                  ENV_PATH, what login starts from
+  /usr/lib/environment.d/X.conf    in name order, with a file of the same name in
+  /etc/environment.d/X.conf        /etc replacing the one in /usr/lib
+  /etc/environment                 as pam_env reads it
+  /etc/zsh/zshenv, /etc/zshenv     only when the default shell is zsh
+  /etc/zsh/zprofile, /etc/zprofile
+  /etc/profile                     for sh-like shells, or when a zprofile sources it
+  /etc/profile.d/X.sh              in name order, when /etc/profile reads them
+
+Assignments in every branch of an if apply one after the other, so where a file
+sets one PATH for root and another for everyone else the last one wins, which is
+the one users get on most systems.
+
+# compose the PATH of a login on the system under root, with the entries each file added
+compose_path(root: Path) -> Result<Composed>
+
+# apply the assignments to PATH in a shell or environment.d file to path
+apply_assignments(contents: str, path: String) -> String
+
+# get the entries of one path string that aren't in another
+added_entries(before: str, after: str) -> Vec<PathBuf>
+
+# get the files in a home directory a login shell reads, in the order it reads them
+user_files(shell: str, home: Path) -> Vec<PathBuf>
+
+# get the shell new users get on the system under root
+default_shell(root: Path) -> String
+*/
+
+use crate::pathops::{self, Result};
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// What login sets when login.defs has no ENV_PATH, as in shadow's login
+const DEFAULT_PATH: &str = "/bin:/usr/bin";
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct Composed {
+    pub path: String,
+    pub shell: String,
+    // The files that changed PATH, in the order they were read
+    pub sources: Vec<Source>,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct Source {
+    // As inside the image
+    pub file: PathBuf,
+    // What reads it: login, pam_env, systemd or the path of the shell
+    pub reader: String,
+    // Entries that weren't there before, none if it only moved or removed some
+    pub added: Vec<PathBuf>,
+}
+
+// A file under root, read as it would be from inside it
+fn read(root: &Path, path: &str) -> Option<String> {
+    let inside = Path::new(path);
+    let host = root.join(inside.strip_prefix("/").unwrap_or(inside));
+    fs::read_to_string(host).ok()
+}
+
+// The files in a directory under root with an extension, sorted by name
+fn files_in(root: &Path, dir: &str, ext: &str) -> Vec<String> {
+    let Some(host) = read_dir(root, dir) else {
+        return Vec::new();
+    };
+    let mut names: Vec<String> = host
+        .filter_map(|d| d.ok())
+        .map(|d| d.file_name().to_string_lossy().into_owned())
+        .filter(|n| n.ends_with(ext) && !n.starts_with('.'))
+        .collect();
+    names.sort();
+    names
+}
+
+fn read_dir(root: &Path, dir: &str) -> Option<fs::ReadDir> {
+    let inside = Path::new(dir);
+    fs::read_dir(root.join(inside.strip_prefix("/").unwrap_or(inside))).ok()
+}
+
+// The value of a word, with its quotes removed and $PATH expanded outside single
+// quotes, or None if it refers to anything else that isn't known
+fn expand(word: &str, path: &str) -> Option<String> {
+    let mut value = String::new();
+    let mut rest = word;
+    while let Some(c) = rest.chars().next() {
+        match c {
+            '\'' => {
+                let end = rest[1..].find('\'')? + 1;
+                value.push_str(&rest[1..end]);
+                rest = &rest[end + 1..];
+            }
+            '"' => {
+                let end = rest[1..].find('"')? + 1;
+                value.push_str(&expand_vars(&rest[1..end], path)?);
+                rest = &rest[end + 1..];
+            }
+            _ => {
+                let end = rest.find(['\'', '"']).unwrap_or(rest.len());
+                value.push_str(&expand_vars(&rest[..end], path)?);
+                rest = &rest[end..];
+            }
+        }
+    }
+    Some(value)
+}
+
+fn expand_vars(s: &str, path: &str) -> Option<String> {
+    // Other variables, commands and escapes can't be known without running them:
+    let others = s.replace("${PATH}", "").replace("$PATH", "");
+    if others.contains(['$', '`', '\\']) {
+        return None;
+    }
+    Some(s.replace("${PATH}", path).replace("$PATH", path))
+}
+
+// The first word of a line, up to whitespace or ; outside quotes
+fn first_word(line: &str) -> (&str, &str) {
+    let mut quote = None;
+    for (i, c) in line.char_indices() {
+        match (quote, c) {
+            (None, '\'' | '"') => quote = Some(c),
+            (Some(q), _) if c == q => quote = None,
+            (None, ' ' | '\t' | ';') => return (&line[..i], &line[i..]),
+            _ => {}
+        }
+    }
+    (line, "")
+}
+
+fn has_entry(path: &str, dir: &str) -> bool {
+    pathops::split_entries(path)
+        .iter()
+        .any(|p| p == Path::new(dir))
+}
+
+fn join(first: &str, second: &str) -> String {
+    match (first.is_empty(), second.is_empty()) {
+        (true, _) => second.to_string(),
+        (_, true) => first.to_string(),
+        _ => format!("{}:{}", first, second),
+    }
+}
+
+// Apply one line to path, telling if it changed anything
+fn apply_line(line: &str, path: &mut String) -> bool {
+    let line = line.trim();
+    let line = line.strip_prefix("export ").unwrap_or(line).trim_start();
+    let (word, rest) = first_word(line);
+    let before = path.clone();
+    if let Some(value) = word.strip_prefix("PATH=") {
+        if let Some(value) = expand(value, path) {
+            *path = value;
+        }
+        return *path != before;
+    }
+    // The helpers Arch's /etc/profile and Red Hat's have for adding a directory
+    // unless it is there already:
+    let args: Vec<String> = rest
+        .split(';')
+        .next()
+        .unwrap_or("")
+        .split_whitespace()
+        .filter_map(|a| expand(a, path))
+        .collect();
+    match (word, args.as_slice()) {
+        ("append_path", [dir]) if !has_entry(path, dir) => *path = join(path, dir),
+        ("pathmunge", [dir]) if !has_entry(path, dir) => *path = join(dir, path),
+        ("pathmunge", [dir, after]) if after == "after" && !has_entry(path, dir) => {
+            *path = join(path, dir)
+        }
+        _ => {}
+    }
+    *path != before
+}
+
+// Apply every line of a file to path, telling if any changed it
+fn apply_lines(contents: &str, path: &mut String) -> bool {
+    let mut changed = false;
+    for line in contents
+        .lines()
+        .filter(|l| !l.trim_start().starts_with('#'))
+    {
+        changed |= apply_line(line, path);
+    }
+    changed
+}
+
+/// Apply the assignments to PATH, and the append_path and pathmunge helpers, in
+/// the order they come. Lines setting PATH to anything that can't be known without
+/// running them are left out.
+pub fn apply_assignments(contents: &str, path: String) -> String {
+    let mut path = path;
+    apply_lines(contents, &mut path);
+    path
+}
+
+/// The entries of after that aren't in before, in the order they are in after
+pub fn added_entries(before: &str, after: &str) -> Vec<PathBuf> {
+    let before = pathops::split_entries(before);
+    let mut added: Vec<PathBuf> = Vec::new();
+    for p in pathops::split_entries(after) {
+        if !before.contains(&p) && !added.contains(&p) {
+            added.push(p);
+        }
+    }
+    added
+}
+
+/// The files of a user a login shell reads that are there, in order. bash only
+/// reads the first of its profiles, while .bashrc is left in as most profiles
+/// source it. fish has a syntax of its own, so none are given for it.
+pub fn user_files(shell: &str, home: &Path) -> Vec<PathBuf> {
+    let existing = |names: &[&str]| -> Vec<PathBuf> {
+        names
+            .iter()
+            .map(|n| home.join(n))
+            .filter(|f| f.is_file())
+            .collect()
+    };
+    let name = shell.rsplit('/').next().unwrap_or(shell);
+    match name {
+        "bash" => {
+            let mut files = existing(&[".bash_profile", ".bash_login", ".profile"]);
+            files.truncate(1);
+            files.extend(existing(&[".bashrc"]));
+            files
+        }
+        "zsh" => existing(&[".zshenv", ".zprofile", ".zshrc", ".zlogin"]),
+        "fish" => Vec::new(),
+        _ => existing(&[".profile"]),
+    }
+}
+
+/// The shell useradd gives new users, else the one of the first regular user,
+/// else /bin/sh
+pub fn default_shell(root: &Path) -> String {
+    let useradd = read(root, "/etc/default/useradd").and_then(|c| {
+        c.lines().find_map(|l| {
+            l.trim()
+                .strip_prefix("SHELL=")
+                .map(|s| s.trim_matches('"').to_string())
+        })
+    });
+    let passwd = || {
+        read(root, "/etc/passwd")?.lines().find_map(|l| {
+            let fields: Vec<&str> = l.split(':').collect();
+            let uid: u32 = fields.get(2)?.parse().ok()?;
+            // Regular users start at 1000, 65534 is nobody:
+            if !(1000..65534).contains(&uid) {
+                return None;
+            }
+            fields.get(6).map(|s| s.to_string())
+        })
+    };
+    useradd
+        .filter(|s| !s.is_empty())
+        .or_else(passwd)
+        .unwrap_or(String::from("/bin/sh"))
+}
+
+// login.defs has ENV_PATH as PATH=dirs or just dirs
+fn login_defs_path(contents: &str) -> Option<String> {
+    contents.lines().find_map(|l| {
+        let mut words = l.split_whitespace();
+        (words.next() == Some("ENV_PATH")).then(|| {
+            let value = words.next().unwrap_or("");
+            value.strip_prefix("PATH=").unwrap_or(value).to_string()
+        })
+    })
+}
+
+/// Compose the PATH a login on the system under root gets, reading only its files
+pub fn compose_path(root: &Path) -> Result<Composed> {
+    fs::metadata(root)?;
+    let mut sources: Vec<Source> = Vec::new();
+    let mut path = match read(root, "/etc/login.defs").and_then(|c| login_defs_path(&c)) {
+        Some(path) => {
+            sources.push(Source {
+                file: PathBuf::from("/etc/login.defs"),
+                reader: String::from("login"),
+                added: added_entries("", &path),
+            });
+            path
+        }
+        None => String::from(DEFAULT_PATH),
+    };
+    let mut apply = |file: &str, reader: &str, contents: &str, path: &mut String| {
+        let before = path.clone();
+        if apply_lines(contents, path) {
+            sources.push(Source {
+                file: PathBuf::from(file),
+                reader: reader.to_string(),
+                added: added_entries(&before, path),
+            });
+        }
+    };
+    let mut conf: Vec<(String, String)> = Vec::new();
+    for dir in ["/usr/lib/environment.d", "/etc/environment.d"] {
+        for name in files_in(root, dir, ".conf") {
+            conf.retain(|(n, _)| *n != name);
+            conf.push((name.clone(), format!("{}/{}", dir, name)));
+        }
+    }
+    conf.sort();
+    for (_, file) in conf.iter() {
+        if let Some(contents) = read(root, file) {
+            apply(file, "systemd", &contents, &mut path);
+        }
+    }
+    if let Some(contents) = read(root, "/etc/environment") {
+        apply("/etc/environment", "pam_env", &contents, &mut path);
+    }
+    let shell = default_shell(root);
+    let mut profile = !shell.ends_with("/zsh");
+    if !profile {
+        for file in [
+            "/etc/zsh/zshenv",
+            "/etc/zshenv",
+            "/etc/zsh/zprofile",
+            "/etc/zprofile",
+        ] {
+            if let Some(contents) = read(root, file) {
+                apply(file, &shell, &contents, &mut path);
+                // Like Arch's, which runs /etc/profile in sh emulation:
+                profile |= contents.contains("/etc/profile");
+            }
+        }
+    }
+    if let Some(contents) = read(root, "/etc/profile").filter(|_| profile) {
+        apply("/etc/profile", &shell, &contents, &mut path);
+        if contents.contains("/etc/profile.d") {
+            for name in files_in(root, "/etc/profile.d", ".sh") {
+                let file = format!("/etc/profile.d/{}", name);
+                if let Some(contents) = read(root, &file) {
+                    apply(&file, &shell, &contents, &mut path);
+                }
+            }
+        }
+    }
+    Ok(Composed {
+        path,
+        shell,
+        sources,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_apply_assignments() {
+        let profile = r#"
+if [ "$(id -u)" -eq 0 ]; then
+  PATH="/usr/local/sbin:/usr/sbin:/usr/bin"
+else
+  PATH="/usr/local/bin:/usr/bin"
+fi
+export PATH
+export PATH=$PATH:'/opt/tool/bin'; unset x
+PATH="$(getconf PATH)"
+append_path '/usr/bin'
+append_path "/snap/bin"
+pathmunge /usr/sbin after
+"#;
+        assert_eq!(
+            apply_assignments(profile, String::new()),
+            "/usr/local/bin:/usr/bin:/opt/tool/bin:/snap/bin:/usr/sbin"
+        );
+        assert_eq!(
+            apply_assignments("PATH=${PATH}:/x # added\npathmunge /y", String::from("/a")),
+            "/y:/a:/x"
+        )
+    }
+
+    #[test]
+    fn test_compose_path() {
+        let root = std::env::temp_dir().join(format!("pathaid-rootfs-{}", std::process::id()));
+        for dir in [
+            "etc/profile.d",
+            "etc/environment.d",
+            "usr/lib/environment.d",
+        ] {
+            fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        let files = [
+            ("etc/login.defs", "ENV_PATH\tPATH=/usr/bin:/bin\n"),
+            ("usr/lib/environment.d/10-a.conf", "PATH=/lib-a:$PATH\n"),
+            ("usr/lib/environment.d/20-b.conf", "PATH=/lib-b:$PATH\n"),
+            ("etc/environment.d/20-b.conf", "PATH=/etc-b:${PATH}\n"),
+            (
+                "etc/profile",
+                "for i in /etc/profile.d/*.sh; do . $i; done\n",
+            ),
+            (
+                "etc/profile.d/rust.sh",
+                "export PATH=\"$PATH:/opt/rust/bin\"\n",
+            ),
+            ("etc/profile.d/other.txt", "PATH=/nope\n"),
+        ];
+        for (file, contents) in files {
+            fs::write(root.join(file), contents).unwrap();
+        }
+        let composed = compose_path(&root);
+        fs::remove_dir_all(&root).unwrap();
+        let composed = composed.unwrap();
+        assert_eq!(composed.path, "/etc-b:/lib-a:/usr/bin:/bin:/opt/rust/bin");
+        assert_eq!(composed.shell, "/bin/sh");
+        let sources: Vec<(&str, &str, usize)> = composed
+            .sources
+            .iter()
+            .map(|s| (s.file.to_str().unwrap(), s.reader.as_str(), s.added.len()))
+            .collect();
+        assert_eq!(
+            sources,
+            [
+                ("/etc/login.defs", "login", 2),
+                ("/usr/lib/environment.d/10-a.conf", "systemd", 1),
+                ("/etc/environment.d/20-b.conf", "systemd", 1),
+                ("/etc/profile.d/rust.sh", "/bin/sh", 1)
+            ]
+        );
+        assert_eq!(
+            added_entries("/a:/b", "/c:/a:/b:/c:/d"),
+            ["/c", "/d"].map(PathBuf::from)
+        )
+    }
+}
@@ -0,0 +1,209 @@
+/*
+Summary of rpc functions
+
+JSON-RPC 2.0 over standard input and output, one message per line, so an editor
+can keep one process around instead of starting a new one for every question:
+
+  {"jsonrpc": "2.0", "id": 1, "method": "which", "params": {"name": "ls"}}
+  {"jsonrpc": "2.0", "id": 1, "result": [[3, "/usr/bin/ls"]]}
+
+Requests without an id are notifications and get no response. What the methods
+are is up to the caller, this only handles the protocol.
+
+# answer requests read from input until it ends
+serve(input: BufRead, output: Write, handle: fn(method, params)) -> Result<()>
+
+# answer a single request, or None for a notification
+respond(line: str, handle: fn(method, params)) -> Option<String>
+
+# read the parameters of a method, failing as invalid parameters
+params(serde_json::Value) -> Result<T>
+
+# fail a method for parameters that can't be used
+invalid_params(message: str) -> anyhow::Error
+*/
+
+use anyhow::Result;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fmt;
+use std::io::{BufRead, Write};
+
+// Error codes defined by JSON-RPC 2.0
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+// Anything else going wrong while answering, in the range left to servers
+const SERVER_ERROR: i64 = -32000;
+
+#[derive(Deserialize)]
+struct Request {
+    jsonrpc: String,
+    #[serde(default)]
+    id: Option<Value>,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+#[derive(Serialize)]
+struct Error {
+    code: i64,
+    message: String,
+}
+
+#[derive(Serialize)]
+struct Response {
+    jsonrpc: &'static str,
+    id: Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    result: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<Error>,
+}
+
+// Marks an error as being about the parameters given rather than answering
+#[derive(Debug)]
+struct InvalidParams(String);
+
+impl fmt::Display for InvalidParams {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid parameters: {}", self.0)
+    }
+}
+
+impl std::error::Error for InvalidParams {}
+
+pub fn invalid_params(message: &str) -> anyhow::Error {
+    InvalidParams(message.to_string()).into()
+}
+
+pub fn params<T: DeserializeOwned>(params: Value) -> Result<T> {
+    // Leaving out the parameters is the same as giving none:
+    let params = if params.is_null() {
+        Value::Object(Default::default())
+    } else {
+        params
+    };
+    serde_json::from_value(params).map_err(|e| InvalidParams(e.to_string()).into())
+}
+
+fn error(id: Value, code: i64, message: String) -> String {
+    let response = Response {
+        jsonrpc: "2.0",
+        id,
+        result: None,
+        error: Some(Error { code, message }),
+    };
+    serde_json::to_string(&response).unwrap_or_default()
+}
+
+// The handler gives None for a method it doesn't have
+pub fn respond(
+    line: &str,
+    handle: &mut impl FnMut(&str, Value) -> Option<Result<Value>>,
+) -> Option<String> {
+    let value: Value = match serde_json::from_str(line) {
+        Ok(value) => value,
+        Err(e) => return Some(error(Value::Null, PARSE_ERROR, e.to_string())),
+    };
+    // Keep the id of a request that is otherwise wrong, to answer it:
+    let id = value.get("id").cloned().unwrap_or(Value::Null);
+    let request = match serde_json::from_value::<Request>(value) {
+        Ok(request) if request.jsonrpc == "2.0" => request,
+        Ok(_) => return Some(error(id, INVALID_REQUEST, "jsonrpc must be \"2.0\"".into())),
+        Err(e) => return Some(error(id, INVALID_REQUEST, e.to_string())),
+    };
+    let answer = handle(&request.method, request.params);
+    let id = request.id?;
+    Some(match answer {
+        None => error(
+            id,
+            METHOD_NOT_FOUND,
+            format!("there is no method '{}'", request.method),
+        ),
+        Some(Ok(result)) => serde_json::to_string(&Response {
+            jsonrpc: "2.0",
+            id,
+            result: Some(result),
+            error: None,
+        })
+        .unwrap_or_default(),
+        Some(Err(e)) => {
+            let code = match e.downcast_ref::<InvalidParams>() {
+                Some(_) => INVALID_PARAMS,
+                None => SERVER_ERROR,
+            };
+            error(id, code, format!("{:#}", e))
+        }
+    })
+}
+
+pub fn serve(
+    input: impl BufRead,
+    mut output: impl Write,
+    mut handle: impl FnMut(&str, Value) -> Option<Result<Value>>,
+) -> Result<()> {
+    for line in input.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        if let Some(response) = respond(&line, &mut handle) {
+            writeln!(output, "{}", response)?;
+            // The other end waits for each answer before asking the next:
+            output.flush()?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[derive(Deserialize)]
+    struct Echo {
+        text: String,
+    }
+
+    fn handle(method: &str, value: Value) -> Option<Result<Value>> {
+        match method {
+            "echo" => Some(params::<Echo>(value).map(|p| json!(p.text))),
+            "fail" => Some(Err(anyhow::anyhow!("failed"))),
+            _ => None,
+        }
+    }
+
+    fn respond_to(line: &str) -> Value {
+        serde_json::from_str(&respond(line, &mut handle).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_respond() {
+        let response = respond_to(
+            r#"{"jsonrpc": "2.0", "id": 7, "method": "echo", "params": {"text": "hi"}}"#,
+        );
+        assert_eq!(response, json!({"jsonrpc": "2.0", "id": 7, "result": "hi"}));
+        let code = |line| respond_to(line)["error"]["code"].as_i64().unwrap();
+        assert_eq!(code("{"), PARSE_ERROR);
+        assert_eq!(code(r#"{"id": 1, "method": "echo"}"#), INVALID_REQUEST);
+        assert_eq!(
+            code(r#"{"jsonrpc": "2.0", "id": 1, "method": "nope"}"#),
+            METHOD_NOT_FOUND
+        );
+        assert_eq!(
+            code(r#"{"jsonrpc": "2.0", "id": 1, "method": "echo"}"#),
+            INVALID_PARAMS
+        );
+        assert_eq!(
+            code(r#"{"jsonrpc": "2.0", "id": 1, "method": "fail"}"#),
+            SERVER_ERROR
+        );
+        // Notifications get no answer, even when failing:
+        assert!(respond(r#"{"jsonrpc": "2.0", "method": "fail"}"#, &mut handle).is_none())
+    }
+}
@@ -0,0 +1,904 @@
+/*
+Summary of state functions
+
+Anything kept between runs lives in one directory per user, and only local
+files are ever written, nothing is sent anywhere.
+
+# get the home directory of the user
+home_dir() -> Result<PathBuf>
+
+# get the directory state is kept in
+state_dir() -> Result<PathBuf>
+
+# get the directory data the user asked to keep is kept in
+data_dir() -> Result<PathBuf>
+
+# take the lock serializing changes to state and shell files, waiting a moment for it
+lock() -> Result<fs::File>
+
+# replace the contents of a file so it is either all old or all new, even on a crash
+write_atomic(Path, contents: [u8]) -> Result<()>
+
+# record that a command ran and how long it took, if stats are turned on
+record_run(command: str, elapsed: Duration)
+
+# read the recorded stats, or None if they are turned off
+read_stats() -> Result<Option<Stats>>
+
+# turn the recording of stats on or off
+set_stats_enabled(enabled: bool) -> Result<()>
+
+# remove what is left over in the state directory, returning each file and its size
+gc(dry_run: bool) -> Result<Vec<(PathBuf, u64)>>
+
+# add what is seen of the entries now to their history, if history is turned on
+record_history(entries: Vec<PathEntry>)
+
+# read the history of all entries, or None if it is turned off
+read_history() -> Result<Option<History>>
+
+# turn the keeping of history on or off
+set_history_enabled(enabled: bool) -> Result<()>
+
+# save a path string under a name, replacing any snapshot with the same name
+save_snapshot(name: str, var: str, path: str) -> Result<Snapshot>
+
+# read the snapshot saved under a name
+read_snapshot(name: str) -> Result<Snapshot>
+
+# read all snapshots, ordered by name
+list_snapshots() -> Result<Vec<Snapshot>>
+
+# read the problems doctor found the last time for a variable, if it kept them
+last_doctor_run(var: str) -> Result<Option<DoctorRun>>
+
+# keep the problems doctor found for a variable, replacing those of the last run
+save_doctor_run(var: str, problems: Vec<Problem>) -> Result<()>
+
+# compare the problems of two runs, giving the positions of the new and resolved ones
+compare_problems(before: Vec<Problem>, now: Vec<Problem>) -> (Vec<usize>, Vec<usize>)
+
+# get the short id a problem doctor finds is acknowledged by
+finding_id(check: str, code: str, path: Option<Path>) -> String
+
+# read the acknowledged problems
+read_acks() -> Result<Vec<Ack>>
+
+# acknowledge a problem, replacing an earlier acknowledgment of it
+save_ack(ack: Ack) -> Result<()>
+
+# take back the acknowledgment of a problem, telling if there was one
+remove_ack(id: str) -> Result<bool>
+
+# format seconds since the epoch as a UTC date and time
+format_time(secs: u64) -> String
+
+# get the seconds since the epoch at the start of a date like 2025-12-31, in UTC
+parse_date(date: str) -> Result<u64>
+*/
+
+use crate::pathops::{self, PathEntry};
+use anyhow::{bail, ensure, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+const STATS_FILE: &str = "stats.json";
+const HISTORY_FILE: &str = "history.json";
+const LOCK_FILE: &str = "lock";
+const SNAPSHOT_DIR: &str = "snapshots";
+const DOCTOR_FILE: &str = "doctor.json";
+const ACK_FILE: &str = "acks.json";
+// How long to wait for another pathaid to finish before giving up
+const LOCK_WAIT: Duration = Duration::from_secs(2);
+
+#[derive(Serialize, Deserialize, Default, Debug, PartialEq)]
+pub struct CommandStats {
+    pub runs: u64,
+    pub total_ms: u64,
+    pub max_ms: u64,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct Stats {
+    // Seconds since the epoch when recording was turned on
+    pub since: u64,
+    pub commands: BTreeMap<String, CommandStats>,
+}
+
+impl Stats {
+    pub fn record(&mut self, command: &str, elapsed: Duration) {
+        let ms = elapsed.as_millis() as u64;
+        let entry = self.commands.entry(command.to_string()).or_default();
+        entry.runs += 1;
+        entry.total_ms += ms;
+        entry.max_ms = entry.max_ms.max(ms);
+    }
+}
+
+// What changed about an entry, only changes are kept so the history stays small
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Change {
+    // Seen in PATH for the first time, or again after being removed
+    Added,
+    Missing,
+    // There again after going missing
+    Returned,
+    Executables,
+    Removed,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct Event {
+    // Seconds since the epoch
+    pub time: u64,
+    pub change: Change,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub executables: Option<usize>,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct History {
+    pub entries: BTreeMap<PathBuf, Vec<Event>>,
+}
+
+impl History {
+    // Compare what is seen now with the last known state of each entry, and add
+    // an event for anything that changed, including entries no longer in PATH
+    pub fn observe(&mut self, time: u64, entries: &[PathEntry]) {
+        for entry in entries.iter() {
+            // Nothing is known about an entry given up on, it stays as it was:
+            if pathops::is_skipped(&entry.path) {
+                continue;
+            }
+            let events = self.entries.entry(entry.path.clone()).or_default();
+            // The state after the last events, as (in PATH, exists, executables):
+            let mut last: Option<(bool, bool, Option<usize>)> = None;
+            for e in events.iter() {
+                let (_, exists, count) = last.unwrap_or((true, true, None));
+                last = Some(match e.change {
+                    Change::Added => (true, e.executables.is_some(), e.executables),
+                    Change::Missing => (true, false, None),
+                    Change::Returned | Change::Executables => (true, true, e.executables),
+                    Change::Removed => (false, exists, count),
+                });
+            }
+            // An unreadable directory counts as having nothing, not as missing:
+            let count = entry.exists.then(|| entry.executables.unwrap_or(0));
+            let change = match last {
+                None | Some((false, _, _)) => Some(Change::Added),
+                Some((true, true, _)) if !entry.exists => Some(Change::Missing),
+                Some((true, false, _)) if entry.exists => Some(Change::Returned),
+                Some((true, true, before)) if before != count => Some(Change::Executables),
+                _ => None,
+            };
+            if let Some(change) = change {
+                events.push(Event {
+                    time,
+                    change,
+                    executables: count,
+                });
+            }
+        }
+        for (path, events) in self.entries.iter_mut() {
+            let gone = !entries.iter().any(|e| &e.path == path);
+            if gone && events.last().is_some_and(|e| e.change != Change::Removed) {
+                events.push(Event {
+                    time,
+                    change: Change::Removed,
+                    executables: None,
+                });
+            }
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct Snapshot {
+    pub name: String,
+    // Seconds since the epoch
+    pub time: u64,
+    pub var: String,
+    pub path: String,
+}
+
+pub fn home_dir() -> Result<PathBuf> {
+    ["HOME", "USERPROFILE"]
+        .iter()
+        .filter_map(env::var_os)
+        .find(|h| !h.is_empty())
+        .map(PathBuf::from)
+        .context("unable to find the home directory")
+}
+
+// Follow the XDG base directories, with the usual local app data on Windows
+pub fn state_dir() -> Result<PathBuf> {
+    let var = |name| {
+        env::var_os(name)
+            .filter(|d| !d.is_empty())
+            .map(PathBuf::from)
+    };
+    let base = match var("XDG_STATE_HOME") {
+        Some(dir) => dir,
+        None if cfg!(windows) => var("LOCALAPPDATA").context("LOCALAPPDATA is not set")?,
+        None => home_dir()?.join(".local").join("state"),
+    };
+    Ok(base.join(env!("CARGO_PKG_NAME")))
+}
+
+// Snapshots are kept until removed by the user, so they go with data rather than
+// state, which gc may clean up
+pub fn data_dir() -> Result<PathBuf> {
+    let var = |name| {
+        env::var_os(name)
+            .filter(|d| !d.is_empty())
+            .map(PathBuf::from)
+    };
+    let base = match var("XDG_DATA_HOME") {
+        Some(dir) => dir,
+        None if cfg!(windows) => var("APPDATA").context("APPDATA is not set")?,
+        None => home_dir()?.join(".local").join("share"),
+    };
+    Ok(base.join(env!("CARGO_PKG_NAME")))
+}
+
+// Hold an advisory lock on a file in the state directory for as long as the
+// returned file is kept, so a shell hook and a command run by hand (or several
+// terminals) can't interleave their changes
+pub fn lock() -> Result<fs::File> {
+    let dir = state_dir()?;
+    fs::create_dir_all(&dir).with_context(|| format!("unable to create '{}'", dir.display()))?;
+    let path = dir.join(LOCK_FILE);
+    let file = fs::OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(&path)
+        .with_context(|| format!("unable to open '{}'", path.display()))?;
+    let start = Instant::now();
+    loop {
+        match file.try_lock() {
+            Ok(()) => return Ok(file),
+            Err(fs::TryLockError::WouldBlock) if start.elapsed() < LOCK_WAIT => {
+                thread::sleep(Duration::from_millis(50))
+            }
+            Err(fs::TryLockError::WouldBlock) => bail!(
+                "another pathaid is changing '{}', try again when it is done",
+                dir.display()
+            ),
+            Err(fs::TryLockError::Error(e)) => {
+                return Err(e).with_context(|| format!("unable to lock '{}'", path.display()))
+            }
+        }
+    }
+}
+
+// Write to a temporary file next to the target and rename it over the target, so
+// a crash or a full disk halfway through can't leave a truncated file behind.
+// An existing file keeps its permissions, and its owner as far as allowed.
+pub fn write_atomic(path: &Path, contents: impl AsRef<[u8]>) -> Result<()> {
+    // A link is kept, and the file it points to replaced instead:
+    let target;
+    let path = match fs::symlink_metadata(path) {
+        Ok(meta) if meta.file_type().is_symlink() => {
+            target = fs::canonicalize(path)
+                .with_context(|| format!("unable to follow '{}'", path.display()))?;
+            target.as_path()
+        }
+        _ => path,
+    };
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let name = path
+        .file_name()
+        .with_context(|| format!("'{}' is not a file", path.display()))?;
+    let tmp = dir.join(format!(
+        ".{}.{}.tmp",
+        name.to_string_lossy(),
+        std::process::id()
+    ));
+    // Never write into a file someone else put there under the same name:
+    let mut file = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&tmp)
+        .with_context(|| format!("unable to write '{}'", path.display()))?;
+    let mut write = || -> Result<()> {
+        file.write_all(contents.as_ref())?;
+        if let Ok(meta) = fs::metadata(path) {
+            file.set_permissions(meta.permissions())?;
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::MetadataExt;
+                // Only root can give a file away, anyone else keeps it as is:
+                let _ = std::os::unix::fs::fchown(&file, Some(meta.uid()), Some(meta.gid()));
+            }
+        }
+        file.sync_all()?;
+        fs::rename(&tmp, path)?;
+        // Make the rename itself stick:
+        #[cfg(unix)]
+        fs::File::open(dir)?.sync_all()?;
+        Ok(())
+    };
+    write().map_err(|e| {
+        let _ = fs::remove_file(&tmp);
+        e.context(format!("unable to write '{}'", path.display()))
+    })
+}
+
+fn stats_file() -> Result<PathBuf> {
+    Ok(state_dir()?.join(STATS_FILE))
+}
+
+fn write_stats(stats: &Stats) -> Result<()> {
+    let file = stats_file()?;
+    if let Some(dir) = file.parent() {
+        fs::create_dir_all(dir).with_context(|| format!("unable to create '{}'", dir.display()))?;
+    }
+    write_atomic(&file, serde_json::to_string_pretty(stats)?)
+}
+
+pub fn read_stats() -> Result<Option<Stats>> {
+    let file = stats_file()?;
+    if !file.exists() {
+        return Ok(None);
+    }
+    let contents = fs::read_to_string(&file)
+        .with_context(|| format!("unable to read '{}'", file.display()))?;
+    let stats = serde_json::from_str(&contents)
+        .with_context(|| format!("'{}' is not a valid stats file", file.display()))?;
+    Ok(Some(stats))
+}
+
+// Stats are only kept once turned on, and failing to keep them must never make
+// the command itself fail, so any error is ignored
+pub fn record_run(command: &str, elapsed: Duration) {
+    let Ok(_lock) = lock() else {
+        return;
+    };
+    if let Ok(Some(mut stats)) = read_stats() {
+        stats.record(command, elapsed);
+        let _ = write_stats(&stats);
+    }
+}
+
+pub fn set_stats_enabled(enabled: bool) -> Result<()> {
+    let _lock = lock()?;
+    let file = stats_file()?;
+    if !enabled {
+        if file.exists() {
+            fs::remove_file(&file)
+                .with_context(|| format!("unable to remove '{}'", file.display()))?;
+        }
+        return Ok(());
+    }
+    if !file.exists() {
+        let since = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        write_stats(&Stats {
+            since,
+            ..Default::default()
+        })?;
+    }
+    Ok(())
+}
+
+// Temporary files of writes that never got to the rename, as all writes hold the
+// lock any found while holding it are left over from a crash
+fn gc_dir(dir: &Path, dry_run: bool) -> Result<Vec<(PathBuf, u64)>> {
+    let mut removed: Vec<(PathBuf, u64)> = Vec::new();
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(removed),
+        Err(e) => return Err(e).with_context(|| format!("unable to read '{}'", dir.display())),
+    };
+    for entry in entries.flatten() {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if !(name.starts_with('.') && name.ends_with(".tmp")) {
+            continue;
+        }
+        let size = entry.metadata().map(|m| m.len()).unwrap_or(0);
+        if !dry_run {
+            fs::remove_file(entry.path())
+                .with_context(|| format!("unable to remove '{}'", entry.path().display()))?;
+        }
+        removed.push((entry.path(), size));
+    }
+    removed.sort();
+    Ok(removed)
+}
+
+pub fn gc(dry_run: bool) -> Result<Vec<(PathBuf, u64)>> {
+    let _lock = lock()?;
+    gc_dir(&state_dir()?, dry_run)
+}
+
+fn history_file() -> Result<PathBuf> {
+    Ok(state_dir()?.join(HISTORY_FILE))
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+pub fn read_history() -> Result<Option<History>> {
+    let file = history_file()?;
+    if !file.exists() {
+        return Ok(None);
+    }
+    let contents = fs::read_to_string(&file)
+        .with_context(|| format!("unable to read '{}'", file.display()))?;
+    let history = serde_json::from_str(&contents)
+        .with_context(|| format!("'{}' is not a valid history file", file.display()))?;
+    Ok(Some(history))
+}
+
+// Like stats, history is only kept once turned on and never makes a command fail
+pub fn record_history(entries: &[PathEntry]) {
+    let Ok(_lock) = lock() else {
+        return;
+    };
+    if let (Ok(Some(mut history)), Ok(file)) = (read_history(), history_file()) {
+        history.observe(now(), entries);
+        if let Ok(contents) = serde_json::to_string(&history) {
+            let _ = write_atomic(&file, contents);
+        }
+    }
+}
+
+pub fn set_history_enabled(enabled: bool) -> Result<()> {
+    let _lock = lock()?;
+    let file = history_file()?;
+    if !enabled {
+        if file.exists() {
+            fs::remove_file(&file)
+                .with_context(|| format!("unable to remove '{}'", file.display()))?;
+        }
+        return Ok(());
+    }
+    if !file.exists() {
+        fs::create_dir_all(state_dir()?)?;
+        write_atomic(&file, serde_json::to_string(&History::default())?)?;
+    }
+    Ok(())
+}
+
+// The name becomes a file name, so it can't leave the snapshot directory or
+// be hidden
+fn snapshot_file(name: &str) -> Result<PathBuf> {
+    ensure!(
+        !name.is_empty() && !name.starts_with('.') && !name.contains(['/', '\\']),
+        "'{}' can't be used as a snapshot name",
+        name
+    );
+    Ok(data_dir()?
+        .join(SNAPSHOT_DIR)
+        .join(format!("{}.json", name)))
+}
+
+pub fn save_snapshot(name: &str, var: &str, path: &str) -> Result<Snapshot> {
+    let file = snapshot_file(name)?;
+    let _lock = lock()?;
+    if let Some(dir) = file.parent() {
+        fs::create_dir_all(dir).with_context(|| format!("unable to create '{}'", dir.display()))?;
+    }
+    let snapshot = Snapshot {
+        name: name.to_string(),
+        time: now(),
+        var: var.to_string(),
+        path: path.to_string(),
+    };
+    write_atomic(&file, serde_json::to_string_pretty(&snapshot)?)?;
+    Ok(snapshot)
+}
+
+fn read_snapshot_file(file: &Path) -> Result<Snapshot> {
+    let contents =
+        fs::read_to_string(file).with_context(|| format!("unable to read '{}'", file.display()))?;
+    serde_json::from_str(&contents)
+        .with_context(|| format!("'{}' is not a valid snapshot", file.display()))
+}
+
+pub fn read_snapshot(name: &str) -> Result<Snapshot> {
+    let file = snapshot_file(name)?;
+    if !file.exists() {
+        bail!("there is no snapshot named '{}'", name);
+    }
+    read_snapshot_file(&file)
+}
+
+pub fn list_snapshots() -> Result<Vec<Snapshot>> {
+    let dir = data_dir()?.join(SNAPSHOT_DIR);
+    let entries = match fs::read_dir(&dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e).with_context(|| format!("unable to read '{}'", dir.display())),
+    };
+    let mut snapshots = Vec::new();
+    for entry in entries.flatten() {
+        let file = entry.path();
+        // Skips the temporary files of unfinished writes too:
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if name.starts_with('.') || file.extension().is_none_or(|e| e != "json") {
+            continue;
+        }
+        snapshots.push(read_snapshot_file(&file)?);
+    }
+    snapshots.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(snapshots)
+}
+
+// A problem doctor found, as kept to compare with the next run
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Problem {
+    pub check: String,
+    pub severity: String,
+    pub code: String,
+    pub path: Option<PathBuf>,
+    pub message: String,
+}
+
+impl Problem {
+    // The message can change with the position of an entry, so a problem is the
+    // same one if it is of the same kind about the same entry
+    fn same(&self, other: &Problem) -> bool {
+        (&self.check, &self.code, &self.path) == (&other.check, &other.code, &other.path)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct DoctorRun {
+    // Seconds since the epoch
+    pub time: u64,
+    pub problems: Vec<Problem>,
+}
+
+fn doctor_file() -> Result<PathBuf> {
+    Ok(state_dir()?.join(DOCTOR_FILE))
+}
+
+// The last run for each variable
+fn read_doctor_runs() -> Result<BTreeMap<String, DoctorRun>> {
+    let file = doctor_file()?;
+    if !file.exists() {
+        return Ok(BTreeMap::new());
+    }
+    let contents = fs::read_to_string(&file)
+        .with_context(|| format!("unable to read '{}'", file.display()))?;
+    serde_json::from_str(&contents)
+        .with_context(|| format!("'{}' is not a valid doctor file", file.display()))
+}
+
+pub fn last_doctor_run(var: &str) -> Result<Option<DoctorRun>> {
+    Ok(read_doctor_runs()?.remove(var))
+}
+
+pub fn save_doctor_run(var: &str, problems: Vec<Problem>) -> Result<()> {
+    let _lock = lock()?;
+    let mut runs = read_doctor_runs()?;
+    runs.insert(
+        var.to_string(),
+        DoctorRun {
+            time: now(),
+            problems,
+        },
+    );
+    fs::create_dir_all(state_dir()?)?;
+    write_atomic(&doctor_file()?, serde_json::to_string_pretty(&runs)?)
+}
+
+// Problems found now but not before are new, and those found before but not now
+// are resolved
+pub fn compare_problems(before: &[Problem], now: &[Problem]) -> (Vec<usize>, Vec<usize>) {
+    let missing_from = |problems: &[Problem], other: &[Problem]| -> Vec<usize> {
+        (0..problems.len())
+            .filter(|&i| !other.iter().any(|o| o.same(&problems[i])))
+            .collect()
+    };
+    (missing_from(now, before), missing_from(before, now))
+}
+
+// The same problem found again gets the same id, as long as it is of the same
+// kind and about the same entry, however that is written
+pub fn finding_id(check: &str, code: &str, path: Option<&Path>) -> String {
+    use sha2::{Digest, Sha256};
+    let mut hasher = Sha256::new();
+    hasher.update(format!("{}\0{}\0", check, code));
+    if let Some(path) = path {
+        hasher.update(pathops::normalize(path).as_os_str().as_encoded_bytes());
+    }
+    hasher.finalize()[..4]
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+// A problem the user accepted, which doctor still lists but doesn't fail on
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Ack {
+    pub id: String,
+    pub reason: String,
+    // Seconds since the epoch
+    pub time: u64,
+    // Seconds since the epoch from when the problem counts again
+    pub expires: Option<u64>,
+    // What the problem was about when acknowledged, to tell what the id is for
+    pub code: String,
+    pub path: Option<PathBuf>,
+}
+
+impl Ack {
+    pub fn is_expired(&self) -> bool {
+        self.expires.is_some_and(|e| e <= now())
+    }
+}
+
+// Acknowledgments are kept until taken back, so they go with data like snapshots
+fn ack_file() -> Result<PathBuf> {
+    Ok(data_dir()?.join(ACK_FILE))
+}
+
+pub fn read_acks() -> Result<Vec<Ack>> {
+    let file = ack_file()?;
+    if !file.exists() {
+        return Ok(Vec::new());
+    }
+    let contents = fs::read_to_string(&file)
+        .with_context(|| format!("unable to read '{}'", file.display()))?;
+    serde_json::from_str(&contents)
+        .with_context(|| format!("'{}' is not a valid acknowledgment file", file.display()))
+}
+
+fn write_acks(acks: &[Ack]) -> Result<()> {
+    let file = ack_file()?;
+    if let Some(dir) = file.parent() {
+        fs::create_dir_all(dir).with_context(|| format!("unable to create '{}'", dir.display()))?;
+    }
+    write_atomic(&file, serde_json::to_string_pretty(acks)?)
+}
+
+pub fn save_ack(ack: Ack) -> Result<()> {
+    let _lock = lock()?;
+    let mut acks = read_acks()?;
+    acks.retain(|a| a.id != ack.id);
+    acks.push(ack);
+    write_acks(&acks)
+}
+
+pub fn remove_ack(id: &str) -> Result<bool> {
+    let _lock = lock()?;
+    let mut acks = read_acks()?;
+    let before = acks.len();
+    acks.retain(|a| a.id != id);
+    if acks.len() == before {
+        return Ok(false);
+    }
+    write_acks(&acks)?;
+    Ok(true)
+}
+
+// Turn days since the epoch into a date, from Howard Hinnant's civil_from_days
+pub fn format_time(secs: u64) -> String {
+    let days = (secs / 86400) as i64 + 719468;
+    let era = days.div_euclid(146097);
+    let doe = days.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    let time = secs % 86400;
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02} UTC",
+        year,
+        month,
+        day,
+        time / 3600,
+        time % 3600 / 60
+    )
+}
+
+// The other way around, from days_from_civil, checked by formatting the result
+pub fn parse_date(date: &str) -> Result<u64> {
+    let invalid = || anyhow::anyhow!("'{}' is not a date like 2025-12-31", date);
+    let parts: Vec<i64> = date
+        .split('-')
+        .map(|p| p.parse().map_err(|_| invalid()))
+        .collect::<Result<_>>()?;
+    let [year, month, day] = parts[..] else {
+        return Err(invalid());
+    };
+    ensure!(
+        year >= 1970 && (1..=12).contains(&month) && (1..=31).contains(&day),
+        invalid()
+    );
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let secs = ((era * 146097 + doe - 719468) * 86400) as u64;
+    // Like 2025-02-30, which would be in March:
+    ensure!(
+        format_time(secs).starts_with(&format!("{:04}-{:02}-{:02}", year, month, day)),
+        invalid()
+    );
+    Ok(secs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stats_record() {
+        let mut stats = Stats::default();
+        stats.record("list", Duration::from_millis(30));
+        stats.record("list", Duration::from_millis(10));
+        let expected = CommandStats {
+            runs: 2,
+            total_ms: 40,
+            max_ms: 30,
+        };
+        assert_eq!(stats.commands["list"], expected)
+    }
+
+    #[test]
+    fn test_compare_problems() {
+        let problem = |code: &str, path: &str, message: &str| Problem {
+            check: String::from("validate"),
+            severity: String::from("warn"),
+            code: code.to_string(),
+            path: Some(PathBuf::from(path)),
+            message: message.to_string(),
+        };
+        let before = [
+            problem("missing", "/opt/a/bin", "/opt/a/bin is missing"),
+            problem("duplicate", "/usr/bin", "at positions 1, 4"),
+        ];
+        let now = [
+            problem("duplicate", "/usr/bin", "at positions 0, 3"),
+            problem("empty", "/opt/b/bin", "/opt/b/bin is empty"),
+        ];
+        assert_eq!(compare_problems(&before, &now), (vec![1], vec![0]));
+        assert_eq!(compare_problems(&now, &now), (vec![], vec![]))
+    }
+
+    #[test]
+    fn test_parse_date() {
+        assert_eq!(parse_date("1970-01-01").unwrap(), 0);
+        let leap = parse_date("2024-02-29").unwrap();
+        assert_eq!(format_time(leap), "2024-02-29 00:00 UTC");
+        assert_eq!(
+            format_time(parse_date("2025-12-31").unwrap()),
+            "2025-12-31 00:00 UTC"
+        );
+        for invalid in [
+            "2023-02-29",
+            "2025-13-01",
+            "2025-1",
+            "tomorrow",
+            "1969-12-31",
+        ] {
+            assert!(parse_date(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_finding_id() {
+        let id = finding_id("validate", "missing", Some(Path::new("/opt/a/bin")));
+        assert_eq!(id.len(), 8);
+        assert_eq!(
+            id,
+            finding_id("validate", "missing", Some(Path::new("/opt/a/bin")))
+        );
+        assert_ne!(
+            id,
+            finding_id("audit", "missing", Some(Path::new("/opt/a/bin")))
+        );
+        assert_eq!(
+            id,
+            finding_id("validate", "missing", Some(Path::new("/opt//a/bin/")))
+        );
+        assert_ne!(id, finding_id("validate", "missing", None))
+    }
+
+    #[test]
+    fn test_history_observe() {
+        let entry = |exists, executables| PathEntry {
+            index: 0,
+            path: PathBuf::from("/opt/tool/bin"),
+            resolved: None,
+            exists,
+            executables: Some(executables),
+        };
+        let mut history = History::default();
+        history.observe(1, &[entry(true, 3)]);
+        history.observe(2, &[entry(true, 3)]);
+        history.observe(3, &[entry(true, 4)]);
+        history.observe(4, &[entry(false, 0)]);
+        history.observe(5, &[entry(true, 4)]);
+        history.observe(6, &[]);
+        history.observe(7, &[]);
+        let changes: Vec<(u64, Change)> = history.entries[Path::new("/opt/tool/bin")]
+            .iter()
+            .map(|e| (e.time, e.change))
+            .collect();
+        let expected = [
+            (1, Change::Added),
+            (3, Change::Executables),
+            (4, Change::Missing),
+            (5, Change::Returned),
+            (6, Change::Removed),
+        ];
+        assert_eq!(changes, expected)
+    }
+
+    #[test]
+    fn test_format_time() {
+        assert_eq!(format_time(0), "1970-01-01 00:00 UTC");
+        assert_eq!(format_time(1_709_210_096), "2024-02-29 12:34 UTC")
+    }
+
+    #[test]
+    fn test_write_atomic() {
+        let dir = env::temp_dir().join(format!("pathaid-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("rc");
+        fs::write(&file, "old").unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&file, fs::Permissions::from_mode(0o640)).unwrap();
+        }
+        write_atomic(&file, "new").unwrap();
+        assert_eq!(fs::read_to_string(&file).unwrap(), "new");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&file).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o640);
+        }
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+        fs::remove_dir_all(&dir).unwrap()
+    }
+
+    #[test]
+    fn test_snapshot_name() {
+        for name in ["", ".work", "../work", "a/b", "a\\b"] {
+            assert!(snapshot_file(name).is_err(), "{}", name);
+        }
+        let file = snapshot_file("before-conda").unwrap();
+        assert!(file.ends_with("snapshots/before-conda.json"))
+    }
+
+    #[test]
+    fn test_gc_dir() {
+        let dir = env::temp_dir().join(format!("pathaid-gc-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join(STATS_FILE), "{}").unwrap();
+        fs::write(dir.join(".stats.json.123.tmp"), "{\"since\"").unwrap();
+        let removed = gc_dir(&dir, true).unwrap();
+        assert_eq!(removed, vec![(dir.join(".stats.json.123.tmp"), 8)]);
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 2);
+        gc_dir(&dir, false).unwrap();
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+        fs::remove_dir_all(&dir).unwrap()
+    }
+}
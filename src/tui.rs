@@ -0,0 +1,634 @@
+/*
+Summary of tui functions
+
+Edit PATH in the terminal: entries are listed with their status and can be moved,
+removed or disabled before the result is taken. The editor draws on stderr so the
+result can be printed to stdout and captured, as in export PATH=$(pathaid edit --tui).
+
+# edit the entries of a path-like variable, giving the result or None if cancelled
+edit(path_var: OsStr, var: str) -> Result<Option<OsString>>
+
+# show the entries with their status, refreshed until quit, and what changed meanwhile
+top(path_var: OsStr, var: str) -> Result<()>
+*/
+
+use crate::pathops;
+use anyhow::{ensure, Result};
+use ratatui::backend::CrosstermBackend;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use ratatui::crossterm::execute;
+use ratatui::crossterm::terminal::{self, EnterAlternateScreen, LeaveAlternateScreen};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, List, ListItem, ListState, Paragraph};
+use ratatui::Terminal;
+use std::ffi::{OsStr, OsString};
+use std::io::{self, IsTerminal, Stderr};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+const KEYS: &str =
+    "↑/↓ select  shift+↑/↓ or K/J move  d delete  space disable  enter done  esc cancel";
+const TOP_KEYS: &str = "↑/↓ select  r refresh now  q quit";
+// How often top looks at the entries again
+const REFRESH: Duration = Duration::from_secs(2);
+// How many changes top keeps to show
+const MAX_CHANGES: usize = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Status {
+    Ok,
+    Missing,
+    Empty,
+    Cwd,
+    // The same directory comes earlier, as written or resolved
+    Duplicate,
+    Disabled,
+}
+
+impl Status {
+    fn label(self) -> &'static str {
+        match self {
+            Status::Ok => "ok",
+            Status::Missing => "missing",
+            Status::Empty => "empty",
+            Status::Cwd => "cwd",
+            Status::Duplicate => "duplicate",
+            Status::Disabled => "disabled",
+        }
+    }
+
+    // How much of an entry's share of the health score a problem takes, in
+    // percent: what breaks lookups, or lets another user's files run, takes all
+    fn penalty(self) -> usize {
+        match self {
+            Status::Ok | Status::Disabled => 0,
+            Status::Empty => 25,
+            Status::Duplicate => 50,
+            Status::Missing | Status::Cwd => 100,
+        }
+    }
+
+    fn color(self) -> Color {
+        match self {
+            Status::Ok => Color::Green,
+            Status::Missing | Status::Cwd => Color::Red,
+            Status::Empty | Status::Duplicate => Color::Yellow,
+            Status::Disabled => Color::DarkGray,
+        }
+    }
+}
+
+struct Row {
+    path: PathBuf,
+    enabled: bool,
+    // What the entry is on its own, duplicates depend on the order
+    status: Status,
+}
+
+struct Editor {
+    // The name of the variable, for the title
+    var: String,
+    rows: Vec<Row>,
+    selected: usize,
+}
+
+enum Action {
+    Continue,
+    Done,
+    Cancel,
+}
+
+// Only PATH is expected to hold executables, other variables only anything at all
+fn status(path: &Path, var: &str) -> Status {
+    let is_empty = match var {
+        "PATH" => pathops::is_empty(path),
+        _ => pathops::is_empty_dir(path),
+    };
+    if pathops::is_cwd_entry(path) {
+        Status::Cwd
+    } else if !pathops::exists(path) {
+        Status::Missing
+    } else if is_empty.unwrap_or(false) {
+        Status::Empty
+    } else {
+        Status::Ok
+    }
+}
+
+// Remember an entry, telling if it, or the directory it resolves to, was seen before
+fn seen_before(seen: &mut Vec<PathBuf>, path: &Path) -> bool {
+    let resolved = pathops::resolve(path).unwrap_or_else(|_| path.to_path_buf());
+    let duplicate = seen.iter().any(|p| p == path || *p == resolved);
+    seen.push(path.to_path_buf());
+    seen.push(resolved);
+    duplicate
+}
+
+impl Editor {
+    fn new(path_var: &OsStr, var: &str, status: impl Fn(&Path) -> Status) -> Self {
+        let rows = pathops::split_entries(path_var)
+            .into_iter()
+            .map(|path| Row {
+                status: status(&path),
+                path,
+                enabled: true,
+            })
+            .collect();
+        Self {
+            var: var.to_string(),
+            rows,
+            selected: 0,
+        }
+    }
+
+    // The status to show for each row, with enabled entries seen earlier as duplicates
+    fn statuses(&self) -> Vec<Status> {
+        let mut seen: Vec<PathBuf> = Vec::new();
+        self.rows
+            .iter()
+            .map(|row| {
+                if !row.enabled {
+                    return Status::Disabled;
+                }
+                if seen_before(&mut seen, &row.path) {
+                    Status::Duplicate
+                } else {
+                    row.status
+                }
+            })
+            .collect()
+    }
+
+    fn select(&mut self, up: bool) {
+        if up {
+            self.selected = self.selected.saturating_sub(1);
+        } else if self.selected + 1 < self.rows.len() {
+            self.selected += 1;
+        }
+    }
+
+    // Swap the selected entry with the one above or below, keeping it selected
+    fn shift(&mut self, up: bool) {
+        let other = if up {
+            self.selected.checked_sub(1)
+        } else {
+            Some(self.selected + 1).filter(|&i| i < self.rows.len())
+        };
+        if let Some(other) = other {
+            self.rows.swap(self.selected, other);
+            self.selected = other;
+        }
+    }
+
+    fn delete(&mut self) {
+        if self.selected < self.rows.len() {
+            self.rows.remove(self.selected);
+            self.selected = self.selected.min(self.rows.len().saturating_sub(1));
+        }
+    }
+
+    fn toggle(&mut self) {
+        if let Some(row) = self.rows.get_mut(self.selected) {
+            row.enabled = !row.enabled;
+        }
+    }
+
+    fn key(&mut self, code: KeyCode, modifiers: KeyModifiers) -> Action {
+        let shift = modifiers.contains(KeyModifiers::SHIFT);
+        match code {
+            KeyCode::Up if shift => self.shift(true),
+            KeyCode::Down if shift => self.shift(false),
+            KeyCode::Char('K') => self.shift(true),
+            KeyCode::Char('J') => self.shift(false),
+            KeyCode::Up | KeyCode::Char('k') => self.select(true),
+            KeyCode::Down | KeyCode::Char('j') => self.select(false),
+            KeyCode::Char('d') | KeyCode::Delete => self.delete(),
+            KeyCode::Char(' ') => self.toggle(),
+            KeyCode::Enter => return Action::Done,
+            KeyCode::Char('c') if modifiers.contains(KeyModifiers::CONTROL) => {
+                return Action::Cancel
+            }
+            KeyCode::Esc | KeyCode::Char('q') => return Action::Cancel,
+            _ => {}
+        }
+        Action::Continue
+    }
+
+    fn path(&self) -> Result<OsString> {
+        let enabled: Vec<PathBuf> = self
+            .rows
+            .iter()
+            .filter(|r| r.enabled)
+            .map(|r| r.path.clone())
+            .collect();
+        Ok(pathops::join(&enabled)?)
+    }
+
+    fn draw(&self, frame: &mut ratatui::Frame) {
+        let [list_area, keys_area] =
+            Layout::vertical([Constraint::Min(1), Constraint::Length(1)]).areas(frame.area());
+        // Positions count from 0, as everywhere else:
+        let width = self.rows.len().saturating_sub(1).to_string().len();
+        let items: Vec<ListItem> = self
+            .rows
+            .iter()
+            .zip(self.statuses())
+            .enumerate()
+            .map(|(i, (row, status))| {
+                let path = row.path.to_string_lossy().into_owned();
+                let path = if row.enabled {
+                    Span::raw(path)
+                } else {
+                    Span::styled(path, Style::default().fg(Color::DarkGray))
+                };
+                ListItem::new(Line::from(vec![
+                    Span::raw(format!("{:>width$} ", i)),
+                    Span::styled(
+                        format!("{:<9} ", status.label()),
+                        Style::default().fg(status.color()),
+                    ),
+                    path,
+                ]))
+            })
+            .collect();
+        let list = List::new(items)
+            .block(Block::bordered().title(format!(" {} ", self.var)))
+            .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+        let mut state = ListState::default().with_selected(Some(self.selected));
+        frame.render_stateful_widget(list, list_area, &mut state);
+        frame.render_widget(
+            Paragraph::new(KEYS).style(Style::default().fg(Color::DarkGray)),
+            keys_area,
+        );
+    }
+}
+
+// What top sees of an entry at one refresh
+#[derive(Debug, Clone, PartialEq)]
+struct Seen {
+    path: PathBuf,
+    status: Status,
+    executables: Option<usize>,
+}
+
+struct Top {
+    var: String,
+    entries: Vec<Seen>,
+    // Newest last, as (seconds since the epoch, what happened)
+    changes: Vec<(u64, String)>,
+    selected: usize,
+}
+
+// Only PATH is expected to hold executables, so only there are they counted
+fn look(paths: &[PathBuf], var: &str, status: impl Fn(&Path) -> Status) -> Vec<Seen> {
+    let mut seen: Vec<PathBuf> = Vec::new();
+    paths
+        .iter()
+        .map(|p| Seen {
+            path: p.clone(),
+            status: if seen_before(&mut seen, p) {
+                Status::Duplicate
+            } else {
+                status(p)
+            },
+            executables: if var == "PATH" {
+                pathops::count_executables(p).ok()
+            } else {
+                None
+            },
+        })
+        .collect()
+}
+
+// Each entry starts with an equal share of 100 and loses the penalty of its
+// status from it, while an entry referring to the current directory caps the
+// whole score at 50 as it can run anything placed in whatever directory a
+// command is run from
+fn health_score(statuses: impl Iterator<Item = Status>) -> usize {
+    let statuses: Vec<Status> = statuses.collect();
+    let lost: usize = statuses.iter().map(|s| s.penalty()).sum();
+    let score = 100 - lost.checked_div(statuses.len()).unwrap_or(0);
+    match statuses.contains(&Status::Cwd) {
+        true => score.min(50),
+        false => score,
+    }
+}
+
+// Describe what changed about each entry between two looks at the same entries
+fn changes(before: &[Seen], after: &[Seen]) -> Vec<String> {
+    before
+        .iter()
+        .zip(after)
+        .filter_map(|(b, a)| {
+            let p = a.path.display();
+            if b.status != a.status {
+                Some(format!("{} {} → {}", p, b.status.label(), a.status.label()))
+            } else if b.executables != a.executables {
+                let n = |e: Option<usize>| e.map_or(String::from("?"), |n| n.to_string());
+                Some(format!(
+                    "{} {} → {} executables",
+                    p,
+                    n(b.executables),
+                    n(a.executables)
+                ))
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+impl Top {
+    fn new(path_var: &OsStr, var: &str) -> Self {
+        let paths = pathops::split_entries(path_var);
+        Self {
+            var: var.to_string(),
+            entries: look(&paths, var, |p| status(p, var)),
+            changes: Vec::new(),
+            selected: 0,
+        }
+    }
+
+    fn refresh(&mut self) {
+        let paths: Vec<PathBuf> = self.entries.iter().map(|e| e.path.clone()).collect();
+        let entries = look(&paths, &self.var, |p| status(p, &self.var));
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        for change in changes(&self.entries, &entries) {
+            self.changes.push((time, change));
+        }
+        let excess = self.changes.len().saturating_sub(MAX_CHANGES);
+        self.changes.drain(..excess);
+        self.entries = entries;
+    }
+
+    fn health(&self) -> usize {
+        health_score(self.entries.iter().map(|e| e.status))
+    }
+
+    // How many entries have each problem, as "2 missing, 1 empty"
+    fn problems(&self) -> String {
+        let mut counts: Vec<(Status, usize)> = Vec::new();
+        for e in self.entries.iter().filter(|e| e.status.penalty() > 0) {
+            match counts.iter_mut().find(|(s, _)| *s == e.status) {
+                Some((_, n)) => *n += 1,
+                None => counts.push((e.status, 1)),
+            }
+        }
+        let counts: Vec<String> = counts
+            .iter()
+            .map(|(s, n)| format!("{} {}", n, s.label()))
+            .collect();
+        counts.join(", ")
+    }
+
+    fn draw(&self, frame: &mut ratatui::Frame) {
+        let [header_area, list_area, changes_area, keys_area] = Layout::vertical([
+            Constraint::Length(1),
+            Constraint::Min(3),
+            Constraint::Length(8),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+        let health = self.health();
+        let color = match health {
+            90.. => Color::Green,
+            70.. => Color::Yellow,
+            _ => Color::Red,
+        };
+        frame.render_widget(
+            Paragraph::new(Line::from(vec![
+                Span::raw(format!("{} entries  health ", self.entries.len())),
+                Span::styled(format!("{}%", health), Style::default().fg(color)),
+                Span::raw(match self.problems() {
+                    problems if problems.is_empty() => String::new(),
+                    problems => format!(" ({})", problems),
+                }),
+                Span::styled(
+                    format!("  refreshed every {}s", REFRESH.as_secs()),
+                    Style::default().fg(Color::DarkGray),
+                ),
+            ])),
+            header_area,
+        );
+        let width = self.entries.len().saturating_sub(1).to_string().len();
+        let items: Vec<ListItem> = self
+            .entries
+            .iter()
+            .enumerate()
+            .map(|(i, e)| {
+                let executables = e.executables.map(|n| n.to_string()).unwrap_or_default();
+                ListItem::new(Line::from(vec![
+                    Span::raw(format!("{:>width$} ", i)),
+                    Span::styled(
+                        format!("{:<9} ", e.status.label()),
+                        Style::default().fg(e.status.color()),
+                    ),
+                    Span::styled(
+                        format!("{:>5} ", executables),
+                        Style::default().fg(Color::Magenta),
+                    ),
+                    Span::raw(e.path.to_string_lossy().into_owned()),
+                ]))
+            })
+            .collect();
+        let list = List::new(items)
+            .block(Block::bordered().title(format!(" {} ", self.var)))
+            .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+        let mut state = ListState::default().with_selected(Some(self.selected));
+        frame.render_stateful_widget(list, list_area, &mut state);
+        // As many of the latest changes as fit, the newest at the bottom:
+        let fit = changes_area.height.saturating_sub(2) as usize;
+        let lines: Vec<Line> = self.changes[self.changes.len().saturating_sub(fit)..]
+            .iter()
+            .map(|(time, change)| {
+                Line::from(vec![
+                    Span::styled(
+                        format!("{} ", time_of_day(*time)),
+                        Style::default().fg(Color::DarkGray),
+                    ),
+                    Span::raw(change.clone()),
+                ])
+            })
+            .collect();
+        frame.render_widget(
+            Paragraph::new(lines).block(Block::bordered().title(" changes ")),
+            changes_area,
+        );
+        frame.render_widget(
+            Paragraph::new(TOP_KEYS).style(Style::default().fg(Color::DarkGray)),
+            keys_area,
+        );
+    }
+}
+
+// The time of day of a change, as the date is the same for most
+fn time_of_day(secs: u64) -> String {
+    let time = secs % 86400;
+    format!(
+        "{:02}:{:02}:{:02}",
+        time / 3600,
+        time % 3600 / 60,
+        time % 60
+    )
+}
+
+// Leaves the alternate screen and raw mode however editing ends
+struct Restore;
+
+impl Drop for Restore {
+    fn drop(&mut self) {
+        let _ = terminal::disable_raw_mode();
+        let _ = execute!(io::stderr(), LeaveAlternateScreen);
+    }
+}
+
+// Draw on stderr in the alternate screen, until the returned Restore is dropped
+fn open_terminal(what: &str) -> Result<(Terminal<CrosstermBackend<Stderr>>, Restore)> {
+    ensure!(
+        io::stdin().is_terminal() && io::stderr().is_terminal(),
+        "{} needs a terminal",
+        what
+    );
+    terminal::enable_raw_mode()?;
+    let restore = Restore;
+    execute!(io::stderr(), EnterAlternateScreen)?;
+    let terminal = Terminal::new(CrosstermBackend::new(io::stderr()))?;
+    Ok((terminal, restore))
+}
+
+pub fn edit(path_var: &OsStr, var: &str) -> Result<Option<OsString>> {
+    let mut editor = Editor::new(path_var, var, |p| status(p, var));
+    let (mut terminal, _restore) = open_terminal("the editor")?;
+    loop {
+        terminal.draw(|frame| editor.draw(frame))?;
+        let Event::Key(key) = event::read()? else {
+            continue;
+        };
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+        match editor.key(key.code, key.modifiers) {
+            Action::Continue => {}
+            Action::Done => return Ok(Some(editor.path()?)),
+            Action::Cancel => return Ok(None),
+        }
+    }
+}
+
+pub fn top(path_var: &OsStr, var: &str) -> Result<()> {
+    let (mut terminal, _restore) = open_terminal("top")?;
+    let mut top = Top::new(path_var, var);
+    let mut refreshed = Instant::now();
+    loop {
+        terminal.draw(|frame| top.draw(frame))?;
+        if !event::poll(REFRESH.saturating_sub(refreshed.elapsed()))? {
+            top.refresh();
+            refreshed = Instant::now();
+            continue;
+        }
+        let Event::Key(key) = event::read()? else {
+            continue;
+        };
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+        match key.code {
+            KeyCode::Up | KeyCode::Char('k') => top.selected = top.selected.saturating_sub(1),
+            KeyCode::Down | KeyCode::Char('j') if top.selected + 1 < top.entries.len() => {
+                top.selected += 1
+            }
+            KeyCode::Char('r') => {
+                top.refresh();
+                refreshed = Instant::now();
+            }
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => return Ok(()),
+            KeyCode::Esc | KeyCode::Char('q') => return Ok(()),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn press(editor: &mut Editor, keys: &str) {
+        for c in keys.chars() {
+            editor.key(KeyCode::Char(c), KeyModifiers::NONE);
+        }
+    }
+
+    #[test]
+    fn test_health_score() {
+        use Status::*;
+        assert_eq!(health_score([].into_iter()), 100);
+        assert_eq!(health_score([Ok, Ok, Disabled].into_iter()), 100);
+        assert_eq!(
+            health_score([Ok, Empty, Duplicate, Missing].into_iter()),
+            57
+        );
+        assert_eq!(health_score([Ok, Ok, Ok, Cwd].into_iter()), 50)
+    }
+
+    #[test]
+    fn test_editor_keys() {
+        let path = pathops::join(&[
+            PathBuf::from("/nonexistent/a"),
+            PathBuf::from("/nonexistent/b"),
+            PathBuf::from("/nonexistent/c"),
+            PathBuf::from("/nonexistent/a"),
+        ])
+        .unwrap();
+        let mut editor = Editor::new(&path, "PATH", |_| Status::Ok);
+        assert_eq!(editor.statuses()[3], Status::Duplicate);
+        // Move a down below b, then delete the duplicate at the end:
+        press(&mut editor, "Jjjd");
+        assert_eq!(editor.selected, 2);
+        // Disable c:
+        press(&mut editor, " ");
+        assert_eq!(
+            editor.statuses(),
+            vec![Status::Ok, Status::Ok, Status::Disabled]
+        );
+        let expected = pathops::join(&[
+            PathBuf::from("/nonexistent/b"),
+            PathBuf::from("/nonexistent/a"),
+        ])
+        .unwrap();
+        assert_eq!(editor.path().unwrap(), expected)
+    }
+
+    #[test]
+    fn test_top_changes() {
+        let seen = |path: &str, status, executables| Seen {
+            path: PathBuf::from(path),
+            status,
+            executables,
+        };
+        let before = [
+            seen("/opt/a/bin", Status::Ok, Some(3)),
+            seen("/opt/b/bin", Status::Missing, None),
+            seen("/opt/c/bin", Status::Ok, Some(1)),
+        ];
+        let after = [
+            seen("/opt/a/bin", Status::Ok, Some(5)),
+            seen("/opt/b/bin", Status::Empty, Some(0)),
+            seen("/opt/c/bin", Status::Ok, Some(1)),
+        ];
+        let expected = vec!["/opt/a/bin 3 → 5 executables", "/opt/b/bin missing → empty"];
+        assert_eq!(changes(&before, &after), expected);
+        let top = Top {
+            var: String::from("PATH"),
+            entries: after.to_vec(),
+            changes: Vec::new(),
+            selected: 0,
+        };
+        assert_eq!(top.health(), 92);
+        assert_eq!(top.problems(), "1 empty")
+    }
+}